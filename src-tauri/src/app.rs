@@ -0,0 +1,627 @@
+mod debug;
+mod display;
+mod printer;
+mod rumble;
+mod serial;
+
+use std::{fs, sync::Mutex, time::Duration};
+
+use debug::DebugRequests;
+use display::WebviewDisplay;
+use log::{info, warn};
+use printer::WebviewPrinter;
+use rumble::WebviewRumble;
+use serde::{Deserialize, Serialize};
+use serial::WebviewSerial;
+use tauri::{ipc::Response, AppHandle, Manager, State};
+
+pub use display::PresentedFrame;
+
+use crate::{
+    emulator::{EmulationSpeed, EmulatorHandle, EmulatorInput},
+    gameboy::{
+        Boot, BootRom, Breakpoint, Cheat, CheatCode, Condition, DebugCommand, DebugEvent,
+        DebuggerState, Gameboy, GameboyConfig, Link, LinkSerialEndpoint, MemoryRegion, Model,
+        Printer, SearchComparison, SearchResults, SearchWidth, SpeakerAudioSink, ViewerImage,
+        ViewerPalette, Watchpoint,
+    },
+};
+
+/// How long debugger commands wait for the emulator to report back. Stepping commands that take
+/// longer, such as running many frames, return nothing.
+const DEBUG_RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A device plugged into the serial port.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Peripheral {
+    /// A Game Boy Printer, whose prints are saved to the prints directory.
+    Printer,
+    /// A link cable to another emulator.
+    Link(Link),
+}
+
+/// A Peripheral once any socket it needs has been opened.
+enum SerialPeripheral {
+    Printer,
+    Link(LinkSerialEndpoint),
+}
+
+pub struct AppState {
+    emulator_handle: Option<EmulatorHandle>,
+    debug_requests: Option<DebugRequests>,
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        AppState {
+            emulator_handle: None,
+            debug_requests: None,
+        }
+    }
+}
+
+#[tauri::command]
+pub fn unload_emulator(state: State<Mutex<AppState>>) {
+    info!("Request to unload emulator received.");
+    let mut state = state.lock().unwrap();
+
+    if let Some(ref mut emulator_handle) = state.emulator_handle {
+        info!("Stopping...");
+        emulator_handle.stop();
+    } else {
+        warn!("No emulator loaded!")
+    }
+
+    info!("Unsetting emulator reference.");
+    state.emulator_handle = None;
+    state.debug_requests = None;
+
+    info!("Emulator unloaded.");
+}
+
+/// Loads the ROM with the given name from the app data directory. `boot_rom` names a boot ROM
+/// dump in the same directory, which is run as the given model (DMG unless specified). Without
+/// one, `skip_boot` starts straight at the cartridge entry point, and `model` overrides the model
+/// detected from the cartridge header. Without a `peripheral` on the serial port, serial output is
+/// forwarded to the frontend.
+#[tauri::command]
+pub fn setup_gameboy(
+    state: State<Mutex<AppState>>,
+    app_handle: AppHandle,
+    name: String,
+    boot_rom: Option<String>,
+    model: Option<Model>,
+    skip_boot: Option<bool>,
+    peripheral: Option<Peripheral>,
+) {
+    info!(
+        "Request to load Gameboy emulator with ROM {} received.",
+        name
+    );
+    if let Ok(app_dir) = app_handle.path().app_data_dir() {
+        let rom_path = app_dir.join(format!("{}.gb", name));
+
+        if rom_path.exists() {
+            info!("Identified ROM file at {:?}", rom_path);
+
+            let rom = fs::read(&rom_path).unwrap();
+            let save_data_path = app_handle.path().local_data_dir().ok();
+
+            let boot = match (boot_rom, skip_boot.unwrap_or(false)) {
+                (Some(boot_rom), _) => {
+                    match BootRom::from_file(model.unwrap_or(Model::DMG), &app_dir.join(boot_rom)) {
+                        Ok(boot_rom) => Boot::Rom(boot_rom),
+                        Err(err) => {
+                            warn!("Using the embedded boot ROM instead. {:#}", err);
+                            Boot::Embedded
+                        }
+                    }
+                }
+                (None, true) => Boot::Skip,
+                (None, false) => Boot::Embedded,
+            };
+
+            // Sockets are opened up front, so that failures fall back to a disconnected cable
+            let peripheral = match peripheral {
+                Some(Peripheral::Link(link)) => match link.open() {
+                    Ok(endpoint) => Some(SerialPeripheral::Link(endpoint)),
+                    Err(err) => {
+                        warn!("Leaving the link cable disconnected. {:#}", err);
+                        None
+                    }
+                },
+                Some(Peripheral::Printer) => Some(SerialPeripheral::Printer),
+                None => None,
+            };
+
+            let print_directory = app_dir.join("prints");
+            let print_name = name.clone();
+            let (debug_sender, debug_receiver) = crossbeam::channel::unbounded();
+            let debug_requests = DebugRequests::new(debug_receiver, app_handle.clone());
+
+            let emulator_handle = EmulatorHandle::new(move |receiver| {
+                let mut config = GameboyConfig::builder()
+                    .save_directory(save_data_path)
+                    .display(WebviewDisplay::new(app_handle.clone()))
+                    .rumble(WebviewRumble::new(app_handle.clone()))
+                    .audio(SpeakerAudioSink::new())
+                    .input(receiver)
+                    .debug_events(debug_sender)
+                    .boot(boot);
+                config = match peripheral {
+                    Some(SerialPeripheral::Printer) => config.serial(Printer::new(Box::new(
+                        WebviewPrinter::new(app_handle, print_directory, print_name),
+                    ))),
+                    Some(SerialPeripheral::Link(endpoint)) => config.serial(endpoint),
+                    None => config.serial(WebviewSerial::new(app_handle)),
+                };
+                if let Some(model) = model {
+                    config = config.model(model);
+                }
+
+                Gameboy::new(rom, config.build())
+            });
+
+            let mut state = state.lock().unwrap();
+            state.emulator_handle = Some(emulator_handle);
+            state.debug_requests = Some(debug_requests);
+            info!("Initialized emulator with ROM {}", name);
+        } else {
+            warn!("No ROM file found at expected location {:?}", rom_path);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn start_emulator(state: State<Mutex<AppState>>) {
+    info!("Starting emulator...");
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.start();
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn pause_emulator(state: State<Mutex<AppState>>) {
+    info!("Stopping emulator");
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.pause();
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn frame_advance(state: State<Mutex<AppState>>) {
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.frame_advance();
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn stop_emulator(state: State<Mutex<AppState>>) {
+    info!("Stopping emulator");
+    let mut state = state.lock().unwrap();
+    if let Some(ref mut emulator_handle) = state.emulator_handle {
+        emulator_handle.stop();
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn register_input(state: State<Mutex<AppState>>, key: String, down: bool) {
+    let input = match key.as_str() {
+        "start" => EmulatorInput::Start,
+        "select" => EmulatorInput::Select,
+        "a" => EmulatorInput::A,
+        "b" => EmulatorInput::B,
+        "up" => EmulatorInput::Up,
+        "down" => EmulatorInput::Down,
+        "left" => EmulatorInput::Left,
+        "right" => EmulatorInput::Right,
+        _ => panic!("Invalid input key string {}", key),
+    };
+
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.send_input(input, down);
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn save_state(state: State<Mutex<AppState>>, slot: u8) {
+    info!("Saving state to slot {}", slot);
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.save_state(slot);
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn load_state(state: State<Mutex<AppState>>, slot: u8) {
+    info!("Loading state from slot {}", slot);
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.load_state(slot);
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn rewind(state: State<Mutex<AppState>>, active: bool) {
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.rewind(active);
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+/// Sets the emulation speed multiplier. A multiplier of None runs unthrottled.
+#[tauri::command]
+pub fn set_speed(state: State<Mutex<AppState>>, multiplier: Option<f32>) {
+    let speed = match multiplier {
+        Some(multiplier) => EmulationSpeed::multiplier(multiplier),
+        None => EmulationSpeed::Unthrottled,
+    };
+
+    info!("Setting emulation speed to {:?}", speed);
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.set_speed(speed);
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn record_movie(state: State<Mutex<AppState>>, slot: u8) {
+    info!("Recording movie to slot {}", slot);
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.record_movie(slot);
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn play_movie(state: State<Mutex<AppState>>, slot: u8) {
+    info!("Playing movie from slot {}", slot);
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.play_movie(slot);
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+#[tauri::command]
+pub fn stop_movie(state: State<Mutex<AppState>>) {
+    info!("Stopping movie");
+    let state = state.lock().unwrap();
+    if let Some(ref emulator_handle) = state.emulator_handle {
+        emulator_handle.stop_movie();
+    } else {
+        warn!("No emulator loaded!")
+    }
+}
+
+/// Sends a command to the debugger and waits for the emulator's reply once it has been handled,
+/// which for stepping commands is once they complete. Returns None if no emulator is loaded or it
+/// doesn't reply in time.
+async fn debug_event(app_handle: AppHandle, command: DebugCommand) -> Option<DebugEvent> {
+    let (debug_requests, request, reply) = {
+        let state = app_handle.state::<Mutex<AppState>>();
+        let state = state.lock().unwrap();
+        let (Some(emulator_handle), Some(debug_requests)) =
+            (&state.emulator_handle, &state.debug_requests)
+        else {
+            warn!("No emulator loaded!");
+            return None;
+        };
+
+        let (request, reply) = debug_requests.register();
+        emulator_handle.debug(request, command);
+        (debug_requests.clone(), request, reply)
+    };
+
+    // The state is unlocked while waiting, so that other commands aren't held up
+    tauri::async_runtime::spawn_blocking(move || {
+        debug_requests.wait(request, reply, DEBUG_RESPONSE_TIMEOUT)
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Sends a command to the debugger, returning the debugger state reported once it is handled.
+async fn debug(app_handle: AppHandle, command: DebugCommand) -> Option<DebuggerState> {
+    match debug_event(app_handle, command).await? {
+        DebugEvent::State(debugger_state) => Some(debugger_state),
+        _ => None,
+    }
+}
+
+/// Returns the CPU registers and flags, along with why execution is stopped, if it is.
+#[tauri::command]
+pub async fn debugger_state(app_handle: AppHandle) -> Option<DebuggerState> {
+    debug(app_handle, DebugCommand::Inspect).await
+}
+
+/// Adds a breakpoint at the address, which only stops execution if the `condition` on a register
+/// holds, when given.
+#[tauri::command]
+pub async fn add_breakpoint(
+    app_handle: AppHandle,
+    address: u16,
+    condition: Option<Condition>,
+) -> Option<DebuggerState> {
+    info!("Adding breakpoint at {:#06x}", address);
+    debug(
+        app_handle,
+        DebugCommand::AddBreakpoint(Breakpoint { address, condition }),
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn remove_breakpoint(app_handle: AppHandle, address: u16) -> Option<DebuggerState> {
+    info!("Removing breakpoint at {:#06x}", address);
+    debug(app_handle, DebugCommand::RemoveBreakpoint(address)).await
+}
+
+#[tauri::command]
+pub async fn add_watchpoint(
+    app_handle: AppHandle,
+    watchpoint: Watchpoint,
+) -> Option<DebuggerState> {
+    info!("Adding watchpoint {:?}", watchpoint);
+    debug(app_handle, DebugCommand::AddWatchpoint(watchpoint)).await
+}
+
+#[tauri::command]
+pub async fn remove_watchpoint(
+    app_handle: AppHandle,
+    watchpoint: Watchpoint,
+) -> Option<DebuggerState> {
+    info!("Removing watchpoint {:?}", watchpoint);
+    debug(app_handle, DebugCommand::RemoveWatchpoint(watchpoint)).await
+}
+
+#[tauri::command]
+pub async fn step_instruction(app_handle: AppHandle) -> Option<DebuggerState> {
+    debug(app_handle, DebugCommand::StepInstruction).await
+}
+
+#[tauri::command]
+pub async fn step_over(app_handle: AppHandle) -> Option<DebuggerState> {
+    debug(app_handle, DebugCommand::StepOver).await
+}
+
+#[tauri::command]
+pub async fn step_out(app_handle: AppHandle) -> Option<DebuggerState> {
+    debug(app_handle, DebugCommand::StepOut).await
+}
+
+/// Runs the given number of frames, unless a breakpoint stops execution first.
+#[tauri::command]
+pub async fn run_to_frame(app_handle: AppHandle, frames: u32) -> Option<DebuggerState> {
+    debug(app_handle, DebugCommand::RunToFrame(frames)).await
+}
+
+/// Encodes what a viewer rendered as raw bytes, which reach the webview as an ArrayBuffer rather
+/// than serializing every pixel as a JSON number. The bytes are the length of the JSON that
+/// follows as a little-endian u32, then the JSON, which leaves out the pixels of its images, then
+/// the RGBA8888 pixels of each image in the order they appear in the JSON. Empty if nothing was
+/// rendered.
+fn viewer_response<T: Serialize>(rendered: Option<(T, Vec<&ViewerImage>)>) -> Response {
+    let Some((metadata, images)) = rendered else {
+        return Response::new(Vec::new());
+    };
+
+    let json = serde_json::to_vec(&metadata).unwrap();
+    let mut bytes = Vec::from((json.len() as u32).to_le_bytes());
+    bytes.extend(json);
+    for image in images {
+        bytes.extend(&image.pixels);
+    }
+
+    Response::new(bytes)
+}
+
+/// Renders the 384 tiles in a VRAM bank with a palette, 16 tiles across, as a ViewerImage encoded
+/// by viewer_response. Like the other viewers, this can be polled while the emulator is running as
+/// well as while it is paused.
+#[tauri::command]
+pub async fn view_tiles(app_handle: AppHandle, bank: u8, palette: ViewerPalette) -> Response {
+    let tiles = match debug_event(app_handle, DebugCommand::ViewTiles { bank, palette }).await {
+        Some(DebugEvent::Tiles(image)) => Some(image),
+        _ => None,
+    };
+
+    viewer_response(tiles.as_ref().map(|image| (image, vec![image])))
+}
+
+/// Renders the tile map at 0x9800 (0) or 0x9C00 (1), outlining the area on screen, as a
+/// TilemapImage encoded by viewer_response.
+#[tauri::command]
+pub async fn view_tilemap(app_handle: AppHandle, tilemap: u8) -> Response {
+    let tilemap = match debug_event(app_handle, DebugCommand::ViewTilemap(tilemap)).await {
+        Some(DebugEvent::Tilemap(tilemap)) => Some(tilemap),
+        _ => None,
+    };
+
+    viewer_response(
+        tilemap
+            .as_ref()
+            .map(|tilemap| (tilemap, vec![&tilemap.image])),
+    )
+}
+
+/// Decodes the 40 OAM entries, each with an image of the object it draws, as a list of OamEntry
+/// encoded by viewer_response.
+#[tauri::command]
+pub async fn view_oam(app_handle: AppHandle) -> Response {
+    let entries = match debug_event(app_handle, DebugCommand::ViewOam).await {
+        Some(DebugEvent::Oam(entries)) => Some(entries),
+        _ => None,
+    };
+
+    viewer_response(
+        entries
+            .as_ref()
+            .map(|entries| (entries, entries.iter().map(|entry| &entry.image).collect())),
+    )
+}
+
+/// Reads `length` bytes of a memory region from `start`, either as the CPU sees it or from a bank,
+/// without side effects. Returns None if the range doesn't exist.
+#[tauri::command]
+pub async fn read_memory(
+    app_handle: AppHandle,
+    region: MemoryRegion,
+    start: usize,
+    length: usize,
+) -> Option<Vec<u8>> {
+    let command = DebugCommand::ReadMemory {
+        region,
+        start,
+        length,
+    };
+    match debug_event(app_handle, command).await? {
+        DebugEvent::Memory(bytes) => bytes,
+        _ => None,
+    }
+}
+
+/// Pokes a byte into a memory region, returning the value read back from it afterwards.
+#[tauri::command]
+pub async fn write_memory(
+    app_handle: AppHandle,
+    region: MemoryRegion,
+    offset: usize,
+    value: u8,
+) -> Option<u8> {
+    let command = DebugCommand::WriteMemory {
+        region,
+        offset,
+        value,
+    };
+    match debug_event(app_handle, command).await? {
+        DebugEvent::Memory(bytes) => bytes?.first().copied(),
+        _ => None,
+    }
+}
+
+/// Takes the most recently presented frame as RGBA8888 bytes, which reach the webview as an
+/// ArrayBuffer. Empty if the frame was already taken.
+#[tauri::command]
+pub fn take_frame(presented_frame: State<PresentedFrame>) -> Response {
+    Response::new(presented_frame.take().unwrap_or_default())
+}
+
+/// Lists the cheats for the loaded ROM.
+#[tauri::command]
+pub async fn list_cheats(app_handle: AppHandle) -> Option<Vec<Cheat>> {
+    cheats_event(app_handle, DebugCommand::ListCheats).await
+}
+
+/// Adds a Game Genie or GameShark code, returning the cheats afterwards. Returns None if the
+/// code is invalid.
+#[tauri::command]
+pub async fn add_cheat(app_handle: AppHandle, code: String) -> Option<Vec<Cheat>> {
+    let code = parse_cheat(&code)?;
+    cheats_event(app_handle, DebugCommand::AddCheat(code)).await
+}
+
+#[tauri::command]
+pub async fn remove_cheat(app_handle: AppHandle, code: String) -> Option<Vec<Cheat>> {
+    let code = parse_cheat(&code)?;
+    cheats_event(app_handle, DebugCommand::RemoveCheat(code)).await
+}
+
+#[tauri::command]
+pub async fn set_cheat_enabled(
+    app_handle: AppHandle,
+    code: String,
+    enabled: bool,
+) -> Option<Vec<Cheat>> {
+    let code = parse_cheat(&code)?;
+    cheats_event(app_handle, DebugCommand::SetCheatEnabled(code, enabled)).await
+}
+
+/// Starts a RAM search over WRAM, HRAM and cartridge RAM, with every value as a candidate.
+#[tauri::command]
+pub async fn start_search(
+    app_handle: AppHandle,
+    width: SearchWidth,
+    signed: bool,
+) -> Option<SearchResults> {
+    search_event(app_handle, DebugCommand::StartSearch { width, signed }).await
+}
+
+/// Narrows the RAM search down to the candidates that pass a comparison against their value at
+/// the previous pass.
+#[tauri::command]
+pub async fn narrow_search(
+    app_handle: AppHandle,
+    comparison: SearchComparison,
+) -> Option<SearchResults> {
+    search_event(app_handle, DebugCommand::NarrowSearch(comparison)).await
+}
+
+#[tauri::command]
+pub async fn search_results(app_handle: AppHandle) -> Option<SearchResults> {
+    search_event(app_handle, DebugCommand::ListSearchResults).await
+}
+
+/// Adds GameShark cheats that hold a RAM search result at a value, returning the cheats
+/// afterwards.
+#[tauri::command]
+pub async fn add_search_cheat(
+    app_handle: AppHandle,
+    region: MemoryRegion,
+    offset: usize,
+    value: i32,
+) -> Option<Vec<Cheat>> {
+    let command = DebugCommand::AddSearchCheat {
+        region,
+        offset,
+        value,
+    };
+    cheats_event(app_handle, command).await
+}
+
+fn parse_cheat(code: &str) -> Option<CheatCode> {
+    code.parse()
+        .map_err(|e| warn!("Unable to parse cheat. {:?}", e))
+        .ok()
+}
+
+async fn cheats_event(app_handle: AppHandle, command: DebugCommand) -> Option<Vec<Cheat>> {
+    match debug_event(app_handle, command).await? {
+        DebugEvent::Cheats(cheats) => Some(cheats),
+        _ => None,
+    }
+}
+
+async fn search_event(app_handle: AppHandle, command: DebugCommand) -> Option<SearchResults> {
+    match debug_event(app_handle, command).await? {
+        DebugEvent::Search(results) => Some(results),
+        _ => None,
+    }
+}
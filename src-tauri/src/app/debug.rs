@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crossbeam::channel::{Receiver, Sender};
+use tauri::{AppHandle, Emitter};
+
+use crate::gameboy::{DebugEvent, DebugReply};
+
+/// Hands the emulator's replies to debugger requests back to the requests they answer. Each
+/// request waits on its own reply, so requests in flight at the same time never see each other's
+/// replies. Anything the emulator reports unprompted, such as when a breakpoint stops a running
+/// game, along with replies that arrive after their request gave up waiting, is emitted to the
+/// frontend as a `gb-debugger-stopped` event instead.
+#[derive(Debug, Clone)]
+pub struct DebugRequests {
+    next_request: Arc<AtomicU32>,
+    pending: Arc<Mutex<HashMap<u32, Sender<DebugEvent>>>>,
+}
+
+impl DebugRequests {
+    /// Routes the replies from the emulator on a background thread, until it stops sending them.
+    pub fn new(replies: Receiver<DebugReply>, app_handle: AppHandle) -> Self {
+        let pending = Arc::new(Mutex::new(HashMap::<u32, Sender<DebugEvent>>::new()));
+
+        thread::spawn({
+            let pending = pending.clone();
+            move || {
+                for reply in replies {
+                    let request = reply
+                        .request
+                        .and_then(|request| pending.lock().unwrap().remove(&request));
+                    // The request may have been abandoned without waiting for its reply
+                    let unanswered = match request {
+                        Some(request) => request.send(reply.event).err().map(|e| e.into_inner()),
+                        None => Some(reply.event),
+                    };
+                    if let Some(event) = unanswered {
+                        app_handle.emit("gb-debugger-stopped", event).unwrap();
+                    }
+                }
+            }
+        });
+
+        DebugRequests {
+            next_request: Arc::new(AtomicU32::new(0)),
+            pending,
+        }
+    }
+
+    /// Allocates the id for a new request, returning it along with where the reply will arrive.
+    pub fn register(&self) -> (u32, Receiver<DebugEvent>) {
+        let request = self.next_request.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = crossbeam::channel::bounded(1);
+        self.pending.lock().unwrap().insert(request, sender);
+
+        (request, receiver)
+    }
+
+    /// Blocks until the reply to a request arrives, or the timeout passes.
+    pub fn wait(
+        &self,
+        request: u32,
+        reply: Receiver<DebugEvent>,
+        timeout: Duration,
+    ) -> Option<DebugEvent> {
+        let event = reply.recv_timeout(timeout).ok();
+        let claimed = self.pending.lock().unwrap().remove(&request).is_none();
+
+        match event {
+            // The reply arrived just as the timeout passed, and is about to be sent
+            None if claimed => reply.recv().ok(),
+            event => event,
+        }
+    }
+}
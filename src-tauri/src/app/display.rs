@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::gameboy::Display;
+
+/// The most recently presented frame as RGBA8888 bytes, waiting for the webview to take it.
+#[derive(Debug, Default, Clone)]
+pub struct PresentedFrame(Arc<Mutex<Option<Vec<u8>>>>);
+
+impl PresentedFrame {
+    pub fn take(&self) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().take()
+    }
+
+    fn set(&self, frame: Vec<u8>) {
+        *self.0.lock().unwrap() = Some(frame);
+    }
+}
+
+/// Presents frames by leaving them in the PresentedFrame state and telling the webview their
+/// size, so it can take the pixels as raw bytes rather than through a JSON event payload.
+pub struct WebviewDisplay {
+    app_handle: AppHandle,
+    presented_frame: PresentedFrame,
+    width: u16,
+    height: u16,
+    buffer: Vec<u16>,
+}
+
+impl WebviewDisplay {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let presented_frame = app_handle.state::<PresentedFrame>().inner().clone();
+
+        WebviewDisplay {
+            app_handle,
+            presented_frame,
+            width: 160,
+            height: 144,
+            buffer: vec![0; 160 * 144],
+        }
+    }
+}
+
+impl Display for WebviewDisplay {
+    fn push_pixel(&mut self, x: u8, y: u8, color: u16) {
+        debug_assert!(
+            color <= 0x7FFF,
+            "Invalid RGB555 color {:#06x} provided to Display.",
+            color
+        );
+        debug_assert!(
+            (x as u16) < self.width,
+            "Invalid x-coordinate {} provided to Display.",
+            x
+        );
+        debug_assert!(
+            (y as u16) < self.height,
+            "Invalid y-coordinate {} provided to Display.",
+            y
+        );
+
+        self.buffer[y as usize * self.width as usize + x as usize] = color;
+    }
+
+    fn present(&mut self) {
+        // Expands each RGB555 channel to 8 bits, with red in the lowest five bits
+        let expand = |channel: u16| ((channel << 3) | (channel >> 2)) as u8;
+        let frame = self
+            .buffer
+            .iter()
+            .flat_map(|color| {
+                [
+                    expand(color & 0x1F),
+                    expand((color >> 5) & 0x1F),
+                    expand((color >> 10) & 0x1F),
+                    0xFF,
+                ]
+            })
+            .collect();
+
+        self.presented_frame.set(frame);
+        self.app_handle
+            .emit("gb-present-frame", (self.width, self.height))
+            .unwrap();
+    }
+
+    fn resize(&mut self, width: u16, height: u16) {
+        self.width = width;
+        self.height = height;
+        self.buffer = vec![0; width as usize * height as usize];
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fs, path::PathBuf};
+
+use chrono::Local;
+use log::{error, info};
+use tauri::{AppHandle, Emitter};
+
+use crate::gameboy::{PrintSink, PrintedImage};
+
+/// Writes each print job to a PNG in the prints directory, then tells the frontend where to find
+/// it.
+#[derive(Debug)]
+pub struct WebviewPrinter {
+    app_handle: AppHandle,
+    directory: PathBuf,
+    name: String,
+}
+
+impl WebviewPrinter {
+    /// Prints are named after the ROM they came from, followed by the time they were printed.
+    pub fn new(app_handle: AppHandle, directory: PathBuf, name: String) -> Self {
+        WebviewPrinter {
+            app_handle,
+            directory,
+            name,
+        }
+    }
+}
+
+impl PrintSink for WebviewPrinter {
+    fn print(&mut self, image: PrintedImage) {
+        let path = self.directory.join(format!(
+            "{}-{}.png",
+            self.name,
+            Local::now().format("%Y%m%d-%H%M%S%.3f")
+        ));
+
+        let result = fs::create_dir_all(&self.directory)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| image.write_png(&path));
+        match result {
+            Ok(()) => {
+                info!("Saved print to {:?}", path);
+                self.app_handle.emit("gb-print", path).unwrap();
+            }
+            Err(e) => error!("Unable to save print to {:?}. {:?}", path, e),
+        }
+    }
+}
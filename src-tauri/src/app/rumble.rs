@@ -0,0 +1,21 @@
+use tauri::{AppHandle, Emitter};
+
+use crate::gameboy::RumbleSink;
+
+/// Forwards the rumble motor state to the frontend, which can vibrate a gamepad in response.
+#[derive(Debug)]
+pub struct WebviewRumble {
+    app_handle: AppHandle,
+}
+
+impl WebviewRumble {
+    pub fn new(app_handle: AppHandle) -> Self {
+        WebviewRumble { app_handle }
+    }
+}
+
+impl RumbleSink for WebviewRumble {
+    fn set_rumble(&mut self, active: bool) {
+        self.app_handle.emit("gb-rumble", active).unwrap();
+    }
+}
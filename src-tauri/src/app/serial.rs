@@ -0,0 +1,23 @@
+use tauri::{AppHandle, Emitter};
+
+use crate::gameboy::SerialEndpoint;
+
+/// Forwards each byte sent over the serial port to the frontend, which can show it in a console.
+/// Nothing is connected on the other side, so every transfer receives 0xFF.
+#[derive(Debug)]
+pub struct WebviewSerial {
+    app_handle: AppHandle,
+}
+
+impl WebviewSerial {
+    pub fn new(app_handle: AppHandle) -> Self {
+        WebviewSerial { app_handle }
+    }
+}
+
+impl SerialEndpoint for WebviewSerial {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.app_handle.emit("gb-serial", sent).unwrap();
+        0xFF
+    }
+}
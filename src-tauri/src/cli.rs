@@ -0,0 +1,117 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    emulator::EmulatorCommand,
+    gameboy::{
+        compare_traces, disassemble_rom, Boot, FileTraceSink, Gameboy, GameboyConfig, Location,
+        Model, NullDisplay,
+    },
+};
+
+const DISASM_USAGE: &str = "Usage: emyco disasm <rom> [bank:address] [count]";
+const TRACE_USAGE: &str = "Usage: emyco trace <rom> <log> [count] [reference]";
+
+/// The number of instructions disassembled unless a count is given.
+const DEFAULT_DISASM_COUNT: usize = 32;
+
+/// The number of instructions traced unless a count is given.
+const DEFAULT_TRACE_COUNT: u64 = 1_000_000;
+
+/// Runs the subcommand named by the first argument. Returns None if there isn't one, in which case
+/// the app starts as usual.
+pub fn run(args: &[String]) -> Option<Result<(), anyhow::Error>> {
+    match args.split_first() {
+        Some((command, args)) if command == "disasm" => Some(disasm(args)),
+        Some((command, args)) if command == "trace" => Some(trace(args)),
+        _ => None,
+    }
+}
+
+/// Prints the instructions in a ROM file from a location, which defaults to the entry point.
+/// Locations are given in hex as bank:address, such as 01:4000.
+fn disasm(args: &[String]) -> Result<(), anyhow::Error> {
+    let [path, rest @ ..] = args else {
+        bail!(DISASM_USAGE);
+    };
+    let rom = fs::read(path).with_context(|| format!("Unable to read ROM {:?}.", path))?;
+
+    let location = match rest.first() {
+        Some(location) => location
+            .parse::<Location>()
+            .with_context(|| format!("Invalid location {:?}. {}", location, DISASM_USAGE))?,
+        None => Location::new(Some(0), 0x0100),
+    };
+    let count = match rest.get(1) {
+        Some(count) => count
+            .parse()
+            .with_context(|| format!("Invalid count {:?}. {}", count, DISASM_USAGE))?,
+        None => DEFAULT_DISASM_COUNT,
+    };
+
+    if location
+        .rom_offset()
+        .is_none_or(|offset| offset >= rom.len())
+    {
+        bail!("{} is outside the ROM.", location);
+    }
+
+    for instruction in disassemble_rom(&rom, location, count) {
+        println!("{}", instruction);
+    }
+
+    Ok(())
+}
+
+/// Runs a ROM headless from the cartridge entry point, logging the CPU state in the gameboy-doctor
+/// format for a number of instructions. If a reference log is given, the trace is compared against
+/// it and the first line that differs is reported.
+fn trace(args: &[String]) -> Result<(), anyhow::Error> {
+    let [rom_path, log_path, rest @ ..] = args else {
+        bail!(TRACE_USAGE);
+    };
+    let rom = fs::read(rom_path).with_context(|| format!("Unable to read ROM {:?}.", rom_path))?;
+    let count = match rest.first() {
+        Some(count) => count
+            .parse()
+            .with_context(|| format!("Invalid count {:?}. {}", count, TRACE_USAGE))?,
+        None => DEFAULT_TRACE_COUNT,
+    };
+
+    // gameboy-doctor logs start from the state the DMG boot ROM leaves behind
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .rewind(0, 0)
+        .boot(Boot::Skip)
+        .model(Model::DMG)
+        .trace(FileTraceSink::create(Path::new(log_path), Some(count))?)
+        .stub_ly(true)
+        .build();
+
+    let mut gameboy = Gameboy::new(rom, config)?;
+    while gameboy.tracing() {
+        gameboy.tick();
+    }
+
+    let Some(reference_path) = rest.get(1) else {
+        return Ok(());
+    };
+    let open = |path: &String| {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("Unable to read log {:?}.", path))
+    };
+    match compare_traces(open(log_path)?, open(reference_path)?)? {
+        Some(divergence) => bail!("{}", divergence),
+        None => println!("The trace matches {}.", reference_path),
+    }
+
+    Ok(())
+}
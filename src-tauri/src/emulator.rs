@@ -1,129 +1,95 @@
-use std::{fs, path::PathBuf, sync::Mutex, thread::JoinHandle};
+use std::thread::JoinHandle;
 
 use crossbeam::channel::{Receiver, Sender};
-use log::{info, warn};
-use tauri::{AppHandle, Manager, State};
+use log::{error, info};
 
-use crate::gameboy::Gameboy;
+use crate::gameboy::DebugCommand;
 
 pub trait Emulator {
-    fn new(rom: Vec<u8>, app_handle: AppHandle) -> Self;
-    fn start(&mut self, receiver: &Receiver<EmulatorCommand>) -> Result<(), anyhow::Error>;
+    fn start(&mut self) -> Result<(), anyhow::Error>;
 }
 
-pub struct AppState {
-    emulator_handle: Option<EmulatorHandle>,
-}
+/// An InputSource provides the commands that drive a running emulator, such as key presses and
+/// pause/stop requests.
+pub trait InputSource {
+    /// Returns the next pending command, if any, without blocking.
+    fn poll(&mut self) -> Option<EmulatorCommand>;
 
-impl AppState {
-    pub fn new() -> Self {
-        AppState {
-            emulator_handle: None,
-        }
-    }
+    /// Blocks until the next command is available. Returns None if no more commands will ever be
+    /// produced.
+    fn wait(&mut self) -> Option<EmulatorCommand>;
 }
 
-#[tauri::command]
-pub fn unload_emulator(state: State<Mutex<AppState>>) {
-    info!("Request to unload emulator received.");
-    let mut state = state.lock().unwrap();
-
-    if let Some(ref mut emulator_handle) = state.emulator_handle {
-        info!("Stopping...");
-        emulator_handle.stop();
-    } else {
-        warn!("No emulator loaded!")
+impl InputSource for Receiver<EmulatorCommand> {
+    fn poll(&mut self) -> Option<EmulatorCommand> {
+        self.try_recv().ok()
     }
 
-    info!("Unsetting emulator reference.");
-    state.emulator_handle = None;
-
-    info!("Emulator unloaded.");
+    fn wait(&mut self) -> Option<EmulatorCommand> {
+        self.recv().ok()
+    }
 }
 
-#[tauri::command]
-pub fn setup_gameboy(state: State<Mutex<AppState>>, app_handle: AppHandle, name: String) {
-    info!(
-        "Request to load Gameboy emulator with ROM {} received.",
-        name
-    );
-    if let Ok(app_dir) = app_handle.path().app_data_dir() {
-        let rom_path = app_dir.join(format!("{}.gb", name));
-
-        if rom_path.exists() {
-            info!("Identified ROM file at {:?}", rom_path);
-
-            let mut state = state.lock().unwrap();
-            state.emulator_handle = Some(EmulatorHandle::new::<Gameboy>(app_handle, rom_path));
-            info!("Initialized emulator with ROM {}", name);
-        } else {
-            warn!("No ROM file found at expected location {:?}", rom_path);
-        }
-    }
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EmulatorCommand {
+    Start,
+    Stop,
+    Pause,
+    FrameAdvance,
+    KeyDown(EmulatorInput),
+    KeyUp(EmulatorInput),
+    SaveState(u8),
+    LoadState(u8),
+    Rewind(bool),
+    SetSpeed(EmulationSpeed),
+    RecordMovie(u8),
+    PlayMovie(u8),
+    StopMovie,
+    /// A debugger command, along with an id that the reply to it carries.
+    Debug(u32, DebugCommand),
 }
 
-#[tauri::command]
-pub fn start_emulator(state: State<Mutex<AppState>>) {
-    info!("Starting emulator...");
-    let state = state.lock().unwrap();
-    if let Some(ref emulator_handle) = state.emulator_handle {
-        emulator_handle.start();
-    } else {
-        warn!("No emulator loaded!")
-    }
+/// How fast emulation runs relative to real hardware.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EmulationSpeed {
+    Multiplier(f32),
+    Unthrottled,
 }
 
-#[tauri::command]
-pub fn pause_emulator(state: State<Mutex<AppState>>) {
-    info!("Stopping emulator");
-    let state = state.lock().unwrap();
-    if let Some(ref emulator_handle) = state.emulator_handle {
-        emulator_handle.pause();
-    } else {
-        warn!("No emulator loaded!")
+impl EmulationSpeed {
+    pub const MIN_MULTIPLIER: f32 = 0.25;
+    pub const MAX_MULTIPLIER: f32 = 8.0;
+
+    /// Constructs a throttled speed, clamped to the supported range of multipliers. NaN is taken
+    /// as real time.
+    pub fn multiplier(multiplier: f32) -> Self {
+        match multiplier.is_nan() {
+            true => EmulationSpeed::default(),
+            false => EmulationSpeed::Multiplier(
+                multiplier.clamp(Self::MIN_MULTIPLIER, Self::MAX_MULTIPLIER),
+            ),
+        }
     }
-}
 
-#[tauri::command]
-pub fn stop_emulator(state: State<Mutex<AppState>>) {
-    info!("Stopping emulator");
-    let mut state = state.lock().unwrap();
-    if let Some(ref mut emulator_handle) = state.emulator_handle {
-        emulator_handle.stop();
-    } else {
-        warn!("No emulator loaded!")
+    /// This speed with its multiplier clamped as `multiplier` does, for speeds constructed
+    /// directly from the variant.
+    pub fn clamped(self) -> Self {
+        match self {
+            EmulationSpeed::Multiplier(multiplier) => Self::multiplier(multiplier),
+            EmulationSpeed::Unthrottled => self,
+        }
     }
-}
 
-#[tauri::command]
-pub fn register_input(state: State<Mutex<AppState>>, key: String, down: bool) {
-    let input = match key.as_str() {
-        "start" => EmulatorInput::Start,
-        "select" => EmulatorInput::Select,
-        "a" => EmulatorInput::A,
-        "b" => EmulatorInput::B,
-        "up" => EmulatorInput::Up,
-        "down" => EmulatorInput::Down,
-        "left" => EmulatorInput::Left,
-        "right" => EmulatorInput::Right,
-        _ => panic!("Invalid input key string {}", key),
-    };
-
-    let state = state.lock().unwrap();
-    if let Some(ref emulator_handle) = state.emulator_handle {
-        emulator_handle.send_input(input, down);
-    } else {
-        warn!("No emulator loaded!")
+    /// Whether this speed matches real hardware.
+    pub fn is_real_time(&self) -> bool {
+        *self == EmulationSpeed::Multiplier(1.0)
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum EmulatorCommand {
-    Start,
-    Stop,
-    Pause,
-    KeyDown(EmulatorInput),
-    KeyUp(EmulatorInput),
+impl Default for EmulationSpeed {
+    fn default() -> Self {
+        EmulationSpeed::Multiplier(1.0)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -144,19 +110,31 @@ pub struct EmulatorHandle {
 }
 
 impl EmulatorHandle {
-    pub fn new<E: Emulator>(app_handle: AppHandle, rom_path: PathBuf) -> Self {
+    /// Spawns a new emulator thread. The emulator is constructed on that thread with the provided
+    /// closure once the first Start command is received, and is handed the receiving end of the
+    /// command channel to use as its input source. Any other command sent before then is ignored,
+    /// except for Stop, which ends the thread without constructing the emulator.
+    pub fn new<E, F>(build: F) -> Self
+    where
+        E: Emulator,
+        F: FnOnce(Receiver<EmulatorCommand>) -> Result<E, anyhow::Error> + Send + 'static,
+    {
         let (tx, rx) = crossbeam::channel::bounded(0);
 
-        let rom = fs::read(&rom_path).unwrap();
-
         let thread_handle = std::thread::spawn(move || {
-            let mut emulator = E::new(rom, app_handle);
-
-            if let Ok(command) = rx.recv() {
-                if command == EmulatorCommand::Start {
-                    emulator.start(&rx).unwrap()
+            // Commands sent before the emulator exists have nothing to act on, so are dropped
+            loop {
+                match rx.recv() {
+                    Ok(EmulatorCommand::Start) => break,
+                    Ok(EmulatorCommand::Stop) | Err(_) => return,
+                    Ok(_) => continue,
                 }
             }
+
+            match build(rx) {
+                Ok(mut emulator) => emulator.start().unwrap(),
+                Err(e) => error!("Unable to initialize emulator. {:?}", e),
+            }
         });
 
         Self {
@@ -166,15 +144,21 @@ impl EmulatorHandle {
     }
 
     pub fn start(&self) {
-        self.sender.send(EmulatorCommand::Start).unwrap();
+        self.send(EmulatorCommand::Start);
     }
 
     pub fn pause(&self) {
-        self.sender.send(EmulatorCommand::Pause).unwrap();
+        self.send(EmulatorCommand::Pause);
+    }
+
+    /// Runs exactly one frame and then pauses. If the emulator is running, it pauses at the end of
+    /// the current frame instead.
+    pub fn frame_advance(&self) {
+        self.send(EmulatorCommand::FrameAdvance);
     }
 
     pub fn stop(&mut self) {
-        self.sender.send(EmulatorCommand::Stop).unwrap();
+        self.send(EmulatorCommand::Stop);
         if let Some(thread_handle) = self.thread_handle.take() {
             thread_handle.join().unwrap();
         }
@@ -187,6 +171,50 @@ impl EmulatorHandle {
             false => EmulatorCommand::KeyUp(input),
         };
 
-        self.sender.send(command).unwrap();
+        self.send(command);
+    }
+
+    pub fn save_state(&self, slot: u8) {
+        self.send(EmulatorCommand::SaveState(slot));
+    }
+
+    pub fn load_state(&self, slot: u8) {
+        self.send(EmulatorCommand::LoadState(slot));
+    }
+
+    /// Steps backwards through rewind history for as long as rewind is active.
+    pub fn rewind(&self, active: bool) {
+        self.send(EmulatorCommand::Rewind(active));
+    }
+
+    pub fn set_speed(&self, speed: EmulationSpeed) {
+        self.send(EmulatorCommand::SetSpeed(speed));
+    }
+
+    /// Restarts the emulator and records input into the numbered movie slot until stopped.
+    pub fn record_movie(&self, slot: u8) {
+        self.send(EmulatorCommand::RecordMovie(slot));
+    }
+
+    /// Restarts the emulator and replays the movie in the numbered slot.
+    pub fn play_movie(&self, slot: u8) {
+        self.send(EmulatorCommand::PlayMovie(slot));
+    }
+
+    /// Stops recording or playing a movie, writing any recording to its slot.
+    pub fn stop_movie(&self) {
+        self.send(EmulatorCommand::StopMovie);
+    }
+
+    /// Sends a command to the debugger, identified by `request` in the reply. Stepping commands
+    /// resume the emulator until they complete, and are answered once they do.
+    pub fn debug(&self, request: u32, command: DebugCommand) {
+        self.send(EmulatorCommand::Debug(request, command));
+    }
+
+    fn send(&self, command: EmulatorCommand) {
+        if let Err(e) = self.sender.send(command) {
+            error!("Unable to send {:?} to emulator thread. {:?}", command, e);
+        }
     }
 }
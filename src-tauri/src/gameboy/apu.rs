@@ -2,12 +2,16 @@ use std::thread::JoinHandle;
 
 use arraydeque::{ArrayDeque, Saturating};
 use bitflags::bitflags;
-use channel::{AudioChannel, NoiseChannel, PulseChannel, WaveChannel};
-use crossbeam::channel::{Receiver, Sender};
+use channel::{
+    AudioChannel, NoiseChannel, NoiseChannelSnapshot, PulseChannel, PulseChannelSnapshot,
+    WaveChannel, WaveChannelSnapshot,
+};
+use crossbeam::channel::{Receiver, Sender, TrySendError};
 use log::info;
 use rodio::{OutputStream, Sink, Source};
+use serde::{Deserialize, Serialize};
 
-use super::{memory::Register, GlobalConstants};
+use super::{memory::Register, state::Snapshot, GlobalConstants};
 
 pub mod channel;
 
@@ -30,30 +34,21 @@ const fn ceil_f32(n: f32) -> f32 {
     }
 }
 
-#[allow(clippy::upper_case_acronyms)]
-pub struct APU {
-    enabled: bool,
+/// An AudioSink receives mixed mono samples at SAMPLE_RATE from the APU once per audio frame.
+pub trait AudioSink {
+    fn push_samples(&mut self, samples: [i16; SAMPLE_BUFFER_SIZE]);
+}
+
+/// An AudioSink that plays samples through the default output device on a dedicated audio thread.
+pub struct SpeakerAudioSink {
     thread_handle: Option<JoinHandle<()>>,
-    channel1: PulseChannel,
-    channel2: PulseChannel,
-    channel3: WaveChannel,
-    channel4: NoiseChannel,
-    apu_clock: u32,
     channel_tx: Sender<AudioThreadMessage>,
-    nr51: NR51,
-    left_volume: u8,
-    right_volume: u8,
 }
 
-impl APU {
+impl SpeakerAudioSink {
     pub fn new() -> Self {
         let (channel_tx, channel_rx) = crossbeam::channel::bounded(16);
 
-        let channel1 = PulseChannel::new();
-        let channel2 = PulseChannel::new();
-        let channel3 = WaveChannel::new();
-        let channel4 = NoiseChannel::new();
-
         let output_channel = OutputChannel::new(SAMPLE_RATE, channel_rx);
 
         let thread_handle = std::thread::spawn(move || {
@@ -65,21 +60,88 @@ impl APU {
             sink.sleep_until_end();
         });
 
+        SpeakerAudioSink {
+            thread_handle: Some(thread_handle),
+            channel_tx,
+        }
+    }
+}
+
+impl Default for SpeakerAudioSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioSink for SpeakerAudioSink {
+    fn push_samples(&mut self, samples: [i16; SAMPLE_BUFFER_SIZE]) {
+        // Emulation is paced against wall-clock time, so the audio thread should never fall far
+        // behind. If it does, drop samples rather than blocking the emulator thread.
+        if let Err(TrySendError::Disconnected(_)) = self
+            .channel_tx
+            .try_send(AudioThreadMessage::SampleBuffer(samples))
+        {
+            panic!("Audio thread terminated unexpectedly.");
+        }
+    }
+}
+
+impl Drop for SpeakerAudioSink {
+    fn drop(&mut self) {
+        self.channel_tx.send(AudioThreadMessage::Shutdown).unwrap();
+        if let Some(thread_handle) = self.thread_handle.take() {
+            thread_handle.join().unwrap();
+        }
+        info!("Gracefully terminated audio thread.");
+    }
+}
+
+/// An AudioSink that discards all samples, for running the emulator without sound.
+#[derive(Debug, Default)]
+pub struct NullAudioSink;
+
+impl AudioSink for NullAudioSink {
+    fn push_samples(&mut self, _samples: [i16; SAMPLE_BUFFER_SIZE]) {}
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct APU {
+    enabled: bool,
+    channel1: PulseChannel,
+    channel2: PulseChannel,
+    channel3: WaveChannel,
+    channel4: NoiseChannel,
+    apu_clock: u32,
+    audio_sink: Box<dyn AudioSink>,
+    muted: bool,
+    nr51: NR51,
+    left_volume: u8,
+    right_volume: u8,
+}
+
+impl APU {
+    pub fn new(audio_sink: Box<dyn AudioSink>) -> Self {
         APU {
             enabled: false,
-            thread_handle: Some(thread_handle),
-            channel1,
-            channel2,
-            channel3,
-            channel4,
+            channel1: PulseChannel::new(),
+            channel2: PulseChannel::new(),
+            channel3: WaveChannel::new(),
+            channel4: NoiseChannel::new(),
             apu_clock: 0,
-            channel_tx,
+            audio_sink,
+            muted: false,
             nr51: NR51::empty(),
             left_volume: 0,
             right_volume: 0,
         }
     }
 
+    /// Stops mixed samples from being sent to the audio sink, while still emulating the channels.
+    /// Used when running at anything other than real-time speed.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
     fn mix_channels(&mut self) -> [i16; SAMPLE_BUFFER_SIZE] {
         let buffer1 = self.channel1.get_samples();
         let buffer2 = self.channel2.get_samples();
@@ -107,19 +169,46 @@ impl APU {
     }
 }
 
-impl Default for APU {
-    fn default() -> Self {
-        Self::new()
-    }
+#[derive(Serialize, Deserialize)]
+pub struct APUSnapshot {
+    enabled: bool,
+    channel1: PulseChannelSnapshot,
+    channel2: PulseChannelSnapshot,
+    channel3: WaveChannelSnapshot,
+    channel4: NoiseChannelSnapshot,
+    apu_clock: u32,
+    nr51: NR51,
+    left_volume: u8,
+    right_volume: u8,
 }
 
-impl Drop for APU {
-    fn drop(&mut self) {
-        self.channel_tx.send(AudioThreadMessage::Shutdown).unwrap();
-        if let Some(thread_handle) = self.thread_handle.take() {
-            thread_handle.join().unwrap();
+impl Snapshot for APU {
+    type State = APUSnapshot;
+
+    fn save_state(&self) -> APUSnapshot {
+        APUSnapshot {
+            enabled: self.enabled,
+            channel1: self.channel1.save_state(),
+            channel2: self.channel2.save_state(),
+            channel3: self.channel3.save_state(),
+            channel4: self.channel4.save_state(),
+            apu_clock: self.apu_clock,
+            nr51: self.nr51,
+            left_volume: self.left_volume,
+            right_volume: self.right_volume,
         }
-        info!("Gracefully terminated audio thread.");
+    }
+
+    fn load_state(&mut self, state: APUSnapshot) {
+        self.enabled = state.enabled;
+        self.channel1.load_state(state.channel1);
+        self.channel2.load_state(state.channel2);
+        self.channel3.load_state(state.channel3);
+        self.channel4.load_state(state.channel4);
+        self.apu_clock = state.apu_clock;
+        self.nr51 = state.nr51;
+        self.left_volume = state.left_volume;
+        self.right_volume = state.right_volume;
     }
 }
 
@@ -168,11 +257,10 @@ impl Register for APU {
 
         self.apu_clock += cycles;
         if self.apu_clock >= AUDIO_FRAME_LENGTH {
-            let buffer = self.mix_channels();
-
-            self.channel_tx
-                .send(AudioThreadMessage::SampleBuffer(buffer))
-                .unwrap();
+            if !self.muted {
+                let buffer = self.mix_channels();
+                self.audio_sink.push_samples(buffer);
+            }
 
             self.apu_clock -= AUDIO_FRAME_LENGTH;
         }
@@ -258,7 +346,7 @@ bitflags! {
     }
 
     #[repr(transparent)]
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
     struct NR51: u8 {
         const CH1_RIGHT = 0b0000_0001;
         const CH2_RIGHT = 0b0000_0010;
@@ -1,4 +1,5 @@
 use blip_buf::BlipBuf;
+use serde::{Deserialize, Serialize};
 
 pub trait AudioChannel: Register {
     fn is_enabled(&self) -> bool;
@@ -194,7 +195,50 @@ impl AudioChannel for PulseChannel {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Serialize, Deserialize)]
+pub struct PulseChannelSnapshot {
+    enabled: bool,
+    sweep: Sweep,
+    wave_duty: WaveDuty,
+    length_timer: LengthTimer,
+    volume_envelope: VolumeEnvelope,
+    period: Period,
+    current_amplitude: u8,
+    last_amplitude: u8,
+    clock: u32,
+}
+
+impl Snapshot for PulseChannel {
+    type State = PulseChannelSnapshot;
+
+    fn save_state(&self) -> PulseChannelSnapshot {
+        PulseChannelSnapshot {
+            enabled: self.enabled,
+            sweep: self.sweep.clone(),
+            wave_duty: self.wave_duty,
+            length_timer: self.length_timer.clone(),
+            volume_envelope: self.volume_envelope.clone(),
+            period: self.period.clone(),
+            current_amplitude: self.current_amplitude,
+            last_amplitude: self.last_amplitude,
+            clock: self.clock,
+        }
+    }
+
+    fn load_state(&mut self, state: PulseChannelSnapshot) {
+        self.enabled = state.enabled;
+        self.sweep = state.sweep;
+        self.wave_duty = state.wave_duty;
+        self.length_timer = state.length_timer;
+        self.volume_envelope = state.volume_envelope;
+        self.period = state.period;
+        self.current_amplitude = state.current_amplitude;
+        self.last_amplitude = state.last_amplitude;
+        self.clock = state.clock;
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum WaveDuty {
     Duty12_5,
     Duty25,
@@ -363,6 +407,50 @@ impl AudioChannel for WaveChannel {
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct WaveChannelSnapshot {
+    enabled: bool,
+    dac_enabled: bool,
+    length_timer: LengthTimer,
+    volume: WaveChannelVolume,
+    period: Period,
+    wave_ram: [u8; 16],
+    current_amplitude: u8,
+    last_amplitude: u8,
+    clock: u32,
+}
+
+impl Snapshot for WaveChannel {
+    type State = WaveChannelSnapshot;
+
+    fn save_state(&self) -> WaveChannelSnapshot {
+        WaveChannelSnapshot {
+            enabled: self.enabled,
+            dac_enabled: self.dac_enabled,
+            length_timer: self.length_timer.clone(),
+            volume: self.volume,
+            period: self.period.clone(),
+            wave_ram: self.wave_ram,
+            current_amplitude: self.current_amplitude,
+            last_amplitude: self.last_amplitude,
+            clock: self.clock,
+        }
+    }
+
+    fn load_state(&mut self, state: WaveChannelSnapshot) {
+        self.enabled = state.enabled;
+        self.dac_enabled = state.dac_enabled;
+        self.length_timer = state.length_timer;
+        self.volume = state.volume;
+        self.period = state.period;
+        self.wave_ram = state.wave_ram;
+        self.current_amplitude = state.current_amplitude;
+        self.last_amplitude = state.last_amplitude;
+        self.clock = state.clock;
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum WaveChannelVolume {
     Mute,
     Volume100,
@@ -498,15 +586,53 @@ impl AudioChannel for NoiseChannel {
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct NoiseChannelSnapshot {
+    enabled: bool,
+    length_timer: LengthTimer,
+    volume_envelope: VolumeEnvelope,
+    current_amplitude: u8,
+    last_amplitude: u8,
+    clock: u32,
+    lsfr: Lsfr,
+}
+
+impl Snapshot for NoiseChannel {
+    type State = NoiseChannelSnapshot;
+
+    fn save_state(&self) -> NoiseChannelSnapshot {
+        NoiseChannelSnapshot {
+            enabled: self.enabled,
+            length_timer: self.length_timer.clone(),
+            volume_envelope: self.volume_envelope.clone(),
+            current_amplitude: self.current_amplitude,
+            last_amplitude: self.last_amplitude,
+            clock: self.clock,
+            lsfr: self.lsfr.clone(),
+        }
+    }
+
+    fn load_state(&mut self, state: NoiseChannelSnapshot) {
+        self.enabled = state.enabled;
+        self.length_timer = state.length_timer;
+        self.volume_envelope = state.volume_envelope;
+        self.current_amplitude = state.current_amplitude;
+        self.last_amplitude = state.last_amplitude;
+        self.clock = state.clock;
+        self.lsfr = state.lsfr;
+    }
+}
+
 #[repr(transparent)]
 struct LSFRUpdate(bool);
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum LSFRWidth {
     Bit15,
     Bit7,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Lsfr {
     value: u16,
     width: LSFRWidth,
@@ -581,6 +707,7 @@ impl From<&Lsfr> for u8 {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LengthTimer {
     enabled: bool,
     initial_length_timer: u8,
@@ -641,6 +768,7 @@ impl LengthTimer {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Period {
     period_divider: u16,
     period_reset: u16,
@@ -704,11 +832,11 @@ impl Period {
     }
 }
 
-use crate::gameboy::{memory::Register, GlobalConstants};
+use crate::gameboy::{memory::Register, state::Snapshot, GlobalConstants};
 
 use super::{SAMPLE_BUFFER_SIZE, SAMPLE_RATE};
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Sweep {
     pace: u8,
     direction: SweepDirection,
@@ -765,12 +893,13 @@ impl From<&Sweep> for u8 {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 enum SweepDirection {
     Increase,
     Decrease,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VolumeEnvelope {
     current_volume: u8,
     initial_volume: u8,
@@ -878,7 +1007,7 @@ impl From<&VolumeEnvelope> for u8 {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 enum VolumeEnvelopeDirection {
     Increasing,
     Decreasing,
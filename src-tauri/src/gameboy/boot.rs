@@ -0,0 +1,70 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+
+use super::Model;
+
+/// The DMG boot ROM bundled with the emulator.
+pub const EMBEDDED_BOOT_ROM: &[u8; 256] = include_bytes!("./memory/dmg_boot.bin");
+
+/// The size of the boot ROMs of the DMG family and the SGB.
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+
+/// The size of the CGB boot ROM. 0x0100..=0x01FF is never mapped, as the cartridge header
+/// is visible there while it runs.
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+/// How a Gameboy starts up.
+#[derive(Debug, Default)]
+pub enum Boot {
+    /// Runs the bundled boot ROM for DMG games. Models no boot ROM is bundled for skip the boot.
+    #[default]
+    Embedded,
+    /// Runs a boot ROM dump, emulating the model it was dumped from.
+    Rom(BootRom),
+    /// Starts at the cartridge entry point, with the registers, IO and VRAM set as the boot ROM
+    /// of the model leaves them.
+    Skip,
+}
+
+/// A boot ROM dump along with the model it belongs to.
+#[derive(Debug, Clone)]
+pub struct BootRom {
+    model: Model,
+    data: Vec<u8>,
+}
+
+impl BootRom {
+    /// Validates that a boot ROM has the size of one dumped from the given model.
+    pub fn new(model: Model, data: Vec<u8>) -> Result<Self, anyhow::Error> {
+        let expected_size = match model {
+            Model::CGB => CGB_BOOT_ROM_SIZE,
+            Model::DMG0 | Model::DMG | Model::MGB | Model::SGB => DMG_BOOT_ROM_SIZE,
+        };
+        if data.len() != expected_size {
+            bail!(
+                "{:?} boot ROMs are {} bytes, but this one is {} bytes.",
+                model,
+                expected_size,
+                data.len()
+            );
+        }
+
+        Ok(BootRom { model, data })
+    }
+
+    /// Loads a boot ROM dumped from the given model from a file.
+    pub fn from_file(model: Model, path: &Path) -> Result<Self, anyhow::Error> {
+        let data =
+            fs::read(path).with_context(|| format!("Failed to read boot ROM {:?}.", path))?;
+        Self::new(model, data)
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
@@ -4,9 +4,17 @@ mod test;
 use arraydeque::{ArrayDeque, Saturating};
 use bitflags::bitflags;
 use log::trace;
+use serde::{Deserialize, Serialize};
 use std::ops::{Deref, DerefMut};
 
-use super::memory::SharedMemoryController;
+use super::{
+    debugger::{Access, MemoryAccess},
+    disassembler::{disassemble, Location},
+    memory::SharedMemoryController,
+    state::Snapshot,
+    trace::{TraceLine, TraceSink},
+    Model,
+};
 
 const OP_QUEUE_SIZE: usize = 16;
 
@@ -29,6 +37,26 @@ pub struct CPU {
     interrupt_dispatch: InterruptDispatchState,
     operation_queue: OperationQueue<Operation, OP_QUEUE_SIZE>,
     internal_buffer: Vec<u8>,
+    software_breakpoint: bool,
+    /// Whether reads and writes of memory by instructions are recorded for the debugger.
+    record_memory_accesses: bool,
+    memory_accesses: Vec<MemoryAccess>,
+    trace_sink: Option<Box<dyn TraceSink>>,
+}
+
+/// The values of the CPU registers.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
 }
 
 impl CPU {
@@ -56,6 +84,10 @@ impl CPU {
             interrupt_dispatch: InterruptDispatchState::Waiting,
             operation_queue: OperationQueue::new(),
             internal_buffer: Vec::new(),
+            software_breakpoint: false,
+            record_memory_accesses: false,
+            memory_accesses: Vec::new(),
+            trace_sink: None,
         }
     }
 
@@ -74,7 +106,7 @@ impl CPU {
             OpTarget::ProgramCounterHigh => ((self.program_counter & 0xFF00) >> 8) as u8,
             OpTarget::StackPointerLow => (self.stack_pointer & 0xFF) as u8,
             OpTarget::StackPointerHigh => ((self.stack_pointer & 0xFF00) >> 8) as u8,
-            OpTarget::MemoryAddress(address) => self.read_memory(*address),
+            OpTarget::MemoryAddress(address) => self.read_data(*address),
             OpTarget::Immediate(value) => *value,
             OpTarget::InternalBuffer => self
                 .internal_buffer
@@ -111,7 +143,7 @@ impl CPU {
                 self.internal_buffer.push(value);
             }
             OpTarget::Immediate(_) => {}
-            OpTarget::MemoryAddress(address) => self.write_memory(address, value),
+            OpTarget::MemoryAddress(address) => self.write_data(address, value),
             OpTarget::None => {}
         }
     }
@@ -404,10 +436,10 @@ impl CPU {
                 Operation::StackPush(read_from) => {
                     let value = self.read_target(&read_from);
                     self.stack_pointer = self.stack_pointer.wrapping_sub(1);
-                    self.write_memory(self.stack_pointer, value);
+                    self.write_data(self.stack_pointer, value);
                 }
                 Operation::StackPop(write_to) => {
-                    let value = self.read_memory(self.stack_pointer);
+                    let value = self.read_data(self.stack_pointer);
                     self.stack_pointer = self.stack_pointer.wrapping_add(1);
                     self.write_target(write_to, value);
                 }
@@ -539,11 +571,102 @@ impl CPU {
         }
     }
 
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.register_a,
+            f: self.register_f.bits(),
+            b: self.register_b,
+            c: self.register_c,
+            d: self.register_d,
+            e: self.register_e,
+            h: self.register_h,
+            l: self.register_l,
+            sp: self.stack_pointer,
+            pc: self.program_counter,
+        }
+    }
+
+    /// Returns true if LD B,B has been executed since this was last called. Test ROMs and
+    /// homebrew use it as a software breakpoint.
+    pub fn take_software_breakpoint(&mut self) -> bool {
+        std::mem::take(&mut self.software_breakpoint)
+    }
+
+    /// Returns the address of the instruction the CPU starts on its next cycle, or None if it is
+    /// partway through an instruction, halted, or about to dispatch an interrupt instead.
+    pub fn next_instruction_address(&self) -> Option<u16> {
+        if !self.operation_queue.is_empty()
+            || self.state == CPUState::Halted
+            || self.interrupt_dispatch != InterruptDispatchState::Waiting
+        {
+            return None;
+        }
+
+        let interrupt_pending = self.read_memory(0xFFFF) & self.read_memory(0xFF0F) & 0x1F != 0;
+        if self.ime == IMEState::Enabled && interrupt_pending {
+            return None;
+        }
+
+        Some(self.program_counter)
+    }
+
+    /// Sets whether reads and writes of memory by instructions are recorded. Instruction fetches
+    /// and interrupt handling aren't.
+    pub fn record_memory_accesses(&mut self, record: bool) {
+        self.record_memory_accesses = record;
+        if !record {
+            self.memory_accesses.clear();
+        }
+    }
+
+    pub fn take_memory_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(&mut self.memory_accesses)
+    }
+
+    /// Sets the sink the state is sent to as each instruction starts.
+    pub fn set_trace_sink(&mut self, trace_sink: Option<Box<dyn TraceSink>>) {
+        self.trace_sink = trace_sink;
+    }
+
+    /// Whether there is a trace sink that still wants lines.
+    pub fn tracing(&self) -> bool {
+        self.trace_sink.is_some()
+    }
+
     pub fn reboot(&mut self) {
         self.program_counter = 0;
     }
 
+    /// Starts execution at the cartridge entry point, with the registers set as the boot ROM of
+    /// the given model leaves them. Games check register A to detect which model they run on.
+    /// The DMG and MGB boot ROMs leave the H and C flags set unless the header checksum is zero.
+    pub fn skip_boot(&mut self, model: Model, header_checksum: u8) {
+        let checksum_flags = match header_checksum {
+            0 => 0x0080,
+            _ => 0x00B0,
+        };
+        let (af, bc, de, hl) = match model {
+            Model::DMG0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::DMG => (0x0100 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::MGB => (0xFF00 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::SGB => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::CGB => (0x1180, 0x0000, 0xFF56, 0x000D),
+        };
+
+        self.register_a = (af >> 8) as u8;
+        self.register_f = CPUFlags::from_bits_truncate(af as u8);
+        self.register_b = (bc >> 8) as u8;
+        self.register_c = bc as u8;
+        self.register_d = (de >> 8) as u8;
+        self.register_e = de as u8;
+        self.register_h = (hl >> 8) as u8;
+        self.register_l = hl as u8;
+        self.stack_pointer = 0xFFFE;
+        self.program_counter = 0x0100;
+    }
+
     fn load_operation(&mut self) {
+        let address = self.program_counter;
         let next_instruction = match self.state {
             CPUState::HaltBug => {
                 self.state = CPUState::Ready;
@@ -552,13 +675,7 @@ impl CPU {
             _ => self.read_next_pc(),
         };
 
-        trace!(
-            "CPU: {:#05x} | {:#03x} .. {:#03x} .. {:#03x}",
-            self.program_counter - 1,
-            next_instruction,
-            self.read_memory(self.program_counter),
-            self.read_memory(self.program_counter + 1),
-        );
+        self.trace_instruction(address);
 
         use ArithmeticOperation::*;
         use OpTarget::*;
@@ -779,39 +896,44 @@ impl CPU {
 
             // STOP n8 | 2 4 | - - - -
             0x10 => {
-                let button_held = self.read_memory(0xFF00) & 0x0F != 0;
-                let interrupt_pending = self.read_memory(0xFFFF) == self.read_memory(0xFF0F);
+                // On CGB, a speed switch armed through KEY1 is performed instead of stopping
+                let switched_speed = self.memory.write().unwrap().switch_speed();
+                if switched_speed {
+                    self.read_next_pc();
+                    self.operation_queue.push_back(Nop);
+                } else {
+                    let button_held = self.read_memory(0xFF00) & 0x0F != 0;
+                    let interrupt_pending = self.read_memory(0xFFFF) == self.read_memory(0xFF0F);
 
-                // TODO: CGB implementation will require this be reworked
+                    match (button_held, interrupt_pending) {
+                        (true, true) => {
+                            // STOP is a one-byte op code, mode doesn't change, DIV is not reset
+                            self.operation_queue.push_back(Nop);
+                        }
+                        (true, false) => {
+                            // STOP is a two-byte opcode, HALT is eneter, DIV not reset
+                            self.operation_queue.extend([Nop, Nop]);
+                            self.state = CPUState::Halted;
+                        }
+                        (false, true) => {
+                            // STOP is one-byte opcode, STOP mode is entered, DIV is reset
 
-                match (button_held, interrupt_pending) {
-                    (true, true) => {
-                        // STOP is a one-byte op code, mode doesn't change, DIV is not reset
-                        self.operation_queue.push_back(Nop);
-                    }
-                    (true, false) => {
-                        // STOP is a two-byte opcode, HALT is eneter, DIV not reset
-                        self.operation_queue.extend([Nop, Nop]);
-                        self.state = CPUState::Halted;
-                    }
-                    (false, true) => {
-                        // STOP is one-byte opcode, STOP mode is entered, DIV is reset
+                            self.operation_queue.push_back(Nop);
+                            self.write_memory(0xFF04, 0x01);
+                            self.state = CPUState::Halted;
+                        }
+                        (false, false) => {
+                            // STOP is a two-byte opcode, STOP mode is entered, DIV is reset
 
-                        self.operation_queue.push_back(Nop);
-                        self.write_memory(0xFF04, 0x01);
-                        self.state = CPUState::Halted;
+                            self.operation_queue.extend([Nop, Nop]);
+                            self.write_memory(0xFF04, 0x01);
+                            self.state = CPUState::Halted;
+                        }
                     }
-                    (false, false) => {
-                        // STOP is a two-byte opcode, STOP mode is entered, DIV is reset
 
-                        self.operation_queue.extend([Nop, Nop]);
-                        self.write_memory(0xFF04, 0x01);
-                        self.state = CPUState::Halted;
-                    }
+                    self.state = CPUState::Halted;
+                    self.operation_queue.push_back(Nop);
                 }
-
-                self.state = CPUState::Halted;
-                self.operation_queue.push_back(Nop);
             }
 
             // LD DE, n16 | 3 12 | - - - -
@@ -1619,8 +1741,15 @@ impl CPU {
                 self.operation_queue.push_back(Nop);
             }
 
+            // LD B,B | 1 4 | - - - -
+            // Does nothing, but serves as a software breakpoint.
+            0x40 => {
+                self.software_breakpoint = true;
+                self.operation_queue.push_back(Nop);
+            }
+
             // LD r8, r8
-            0x40..=0x7F => {
+            0x41..=0x7F => {
                 let read_from = match next_instruction & 0b0000_0111 {
                     0 => RegisterB,
                     1 => RegisterC,
@@ -2846,6 +2975,34 @@ impl CPU {
         ((self.register_h as u16) << 8) | (self.register_l as u16)
     }
 
+    /// Logs the instruction at an address as it starts, and sends the state before it runs to the
+    /// trace sink, if there is one.
+    fn trace_instruction(&mut self, address: u16) {
+        trace!(
+            "CPU: {}",
+            disassemble(Location::new(None, address), |address| self
+                .read_memory(address))
+        );
+
+        if self.trace_sink.is_none() {
+            return;
+        }
+
+        let line = TraceLine {
+            registers: Registers {
+                pc: address,
+                ..self.registers()
+            },
+            pcmem: [0, 1, 2, 3].map(|offset| self.read_memory(address.wrapping_add(offset))),
+        };
+        if let Some(trace_sink) = &mut self.trace_sink {
+            trace_sink.trace(&line);
+            if trace_sink.finished() {
+                self.trace_sink = None;
+            }
+        }
+    }
+
     fn read_next_pc(&mut self) -> u8 {
         let byte = self.memory.read().unwrap().read_byte(self.program_counter);
         self.program_counter = self.program_counter.wrapping_add(1);
@@ -2865,6 +3022,96 @@ impl CPU {
     fn write_memory(&self, address: u16, value: u8) {
         self.memory.write().unwrap().write_byte(address, value);
     }
+
+    /// Reads memory on behalf of an instruction, recording the access if requested.
+    fn read_data(&mut self, address: u16) -> u8 {
+        let value = self.read_memory(address);
+        if self.record_memory_accesses {
+            self.memory_accesses.push(MemoryAccess {
+                address,
+                access: Access::Read,
+                value,
+            });
+        }
+        value
+    }
+
+    /// Writes memory on behalf of an instruction, recording the access if requested.
+    fn write_data(&mut self, address: u16, value: u8) {
+        self.write_memory(address, value);
+        if self.record_memory_accesses {
+            self.memory_accesses.push(MemoryAccess {
+                address,
+                access: Access::Write,
+                value,
+            });
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CPUSnapshot {
+    register_a: u8,
+    register_b: u8,
+    register_c: u8,
+    register_d: u8,
+    register_e: u8,
+    register_f: CPUFlags,
+    register_h: u8,
+    register_l: u8,
+    stack_pointer: u16,
+    program_counter: u16,
+    ime: IMEState,
+    state: CPUState,
+    pending_cycles: i32,
+    interrupt_dispatch: InterruptDispatchState,
+    operation_queue: Vec<Operation>,
+    internal_buffer: Vec<u8>,
+}
+
+impl Snapshot for CPU {
+    type State = CPUSnapshot;
+
+    fn save_state(&self) -> CPUSnapshot {
+        CPUSnapshot {
+            register_a: self.register_a,
+            register_b: self.register_b,
+            register_c: self.register_c,
+            register_d: self.register_d,
+            register_e: self.register_e,
+            register_f: self.register_f,
+            register_h: self.register_h,
+            register_l: self.register_l,
+            stack_pointer: self.stack_pointer,
+            program_counter: self.program_counter,
+            ime: self.ime.clone(),
+            state: self.state.clone(),
+            pending_cycles: self.pending_cycles,
+            interrupt_dispatch: self.interrupt_dispatch.clone(),
+            operation_queue: self.operation_queue.iter().copied().collect(),
+            internal_buffer: self.internal_buffer.clone(),
+        }
+    }
+
+    fn load_state(&mut self, state: CPUSnapshot) {
+        self.register_a = state.register_a;
+        self.register_b = state.register_b;
+        self.register_c = state.register_c;
+        self.register_d = state.register_d;
+        self.register_e = state.register_e;
+        self.register_f = state.register_f;
+        self.register_h = state.register_h;
+        self.register_l = state.register_l;
+        self.stack_pointer = state.stack_pointer;
+        self.program_counter = state.program_counter;
+        self.ime = state.ime;
+        self.state = state.state;
+        self.pending_cycles = state.pending_cycles;
+        self.interrupt_dispatch = state.interrupt_dispatch;
+        self.operation_queue.clear();
+        self.operation_queue.extend(state.operation_queue);
+        self.internal_buffer = state.internal_buffer;
+    }
 }
 
 fn check_half_carry_add(a: u8, b: u8) -> bool {
@@ -2887,21 +3134,21 @@ fn check_half_carry_sbc(a: u8, b: u8, c: bool) -> bool {
     (a & 0xF) < ((b & 0xF) + (c as u8))
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 enum IMEState {
     Disabled,
     WillEnable,
     Enabled,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 enum CPUState {
     Ready,
     HaltBug,
     Halted,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 enum InterruptDispatchState {
     Waiting,
     Dispatching {
@@ -2916,7 +3163,7 @@ enum InterruptDispatchState {
 
 bitflags! {
     #[repr(transparent)]
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
     struct CPUFlags: u8 {
         const ZERO = 0b1000_0000;
         const SUBTRACT = 0b0100_0000;
@@ -2925,7 +3172,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum Operation {
     ReadWriteWithFlags {
         read_from: OpTarget,
@@ -2950,7 +3197,7 @@ enum Operation {
     InterruptDispatch,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum OpTarget {
     MemoryAddress(u16),
     Immediate(u8),
@@ -2970,7 +3217,7 @@ pub enum OpTarget {
     None,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum ArithmeticOperation {
     Increment,
     Decrement,
@@ -0,0 +1,483 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Cheat, CheatCode, Instruction, MemoryRegion, OamEntry, Registers, SearchComparison,
+    SearchResults, SearchWidth, TilemapImage, ViewerImage, ViewerPalette,
+};
+
+/// Opcodes of the instructions that return from a call: RET, RETI and RET cc.
+const RETURN_OPCODES: [u8; 6] = [0xC9, 0xD9, 0xC0, 0xC8, 0xD0, 0xD8];
+
+/// Opcodes of CALL and CALL cc, which are three bytes long.
+const CALL_OPCODES: [u8; 5] = [0xCD, 0xC4, 0xCC, 0xD4, 0xDC];
+
+/// The registers a breakpoint condition can test, including the 16-bit pairs.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum RegisterName {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Compares a register against a value, so that a breakpoint only stops execution when it holds.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Condition {
+    pub register: RegisterName,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn matches(&self, registers: &Registers) -> bool {
+        let pair = |high: u8, low: u8| ((high as u16) << 8) | low as u16;
+        let register = match self.register {
+            RegisterName::A => registers.a as u16,
+            RegisterName::F => registers.f as u16,
+            RegisterName::B => registers.b as u16,
+            RegisterName::C => registers.c as u16,
+            RegisterName::D => registers.d as u16,
+            RegisterName::E => registers.e as u16,
+            RegisterName::H => registers.h as u16,
+            RegisterName::L => registers.l as u16,
+            RegisterName::AF => pair(registers.a, registers.f),
+            RegisterName::BC => pair(registers.b, registers.c),
+            RegisterName::DE => pair(registers.d, registers.e),
+            RegisterName::HL => pair(registers.h, registers.l),
+            RegisterName::SP => registers.sp,
+            RegisterName::PC => registers.pc,
+        };
+
+        match self.comparison {
+            Comparison::Equal => register == self.value,
+            Comparison::NotEqual => register != self.value,
+            Comparison::Less => register < self.value,
+            Comparison::LessOrEqual => register <= self.value,
+            Comparison::Greater => register > self.value,
+            Comparison::GreaterOrEqual => register >= self.value,
+        }
+    }
+}
+
+/// Stops execution before the instruction at an address runs.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Breakpoint {
+    pub address: u16,
+    /// Only stops if this holds as the instruction is about to run.
+    pub condition: Option<Condition>,
+}
+
+/// Stops execution when the CPU accesses an address in a range in any of the selected ways.
+/// Reads and writes stop execution right after the access, partway through the instruction.
+/// Executing stops before the instruction runs. Instruction fetches don't count as reads.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Watchpoint {
+    pub start: u16,
+    /// The last address watched, inclusive.
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// A read or write of memory by the CPU.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MemoryAccess {
+    pub address: u16,
+    pub access: Access,
+    pub value: u8,
+}
+
+/// Why execution stopped.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum StopReason {
+    /// Paused by the user.
+    Paused,
+    Breakpoint(u16),
+    Watchpoint {
+        address: u16,
+        access: Access,
+        /// The value read or written, or the opcode about to be executed.
+        value: u8,
+    },
+    /// A step command completed.
+    Step,
+    /// The frames requested by RunToFrame have run.
+    Frame,
+}
+
+/// Commands that control the debugger. Stepping commands resume execution until they complete.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum DebugCommand {
+    AddBreakpoint(Breakpoint),
+    RemoveBreakpoint(u16),
+    AddWatchpoint(Watchpoint),
+    RemoveWatchpoint(Watchpoint),
+    /// Runs a single instruction.
+    StepInstruction,
+    /// Runs a single instruction, running any call or restart it makes through to its return.
+    StepOver,
+    /// Runs until the current function returns.
+    StepOut,
+    /// Runs the given number of frames.
+    RunToFrame(u32),
+    /// Reports the current state without changing anything.
+    Inspect,
+    /// Renders the tiles in a VRAM bank with a palette.
+    ViewTiles {
+        bank: u8,
+        palette: ViewerPalette,
+    },
+    /// Renders the tile map at 0x9800 (0) or 0x9C00 (1).
+    ViewTilemap(u8),
+    /// Decodes and renders the OAM entries.
+    ViewOam,
+    /// Reads `length` bytes of a memory region from `start`.
+    ReadMemory {
+        region: MemoryRegion,
+        start: usize,
+        length: usize,
+    },
+    /// Writes a byte to a memory region.
+    WriteMemory {
+        region: MemoryRegion,
+        offset: usize,
+        value: u8,
+    },
+    /// Adds an enabled cheat, or enables it if it was already added.
+    AddCheat(CheatCode),
+    RemoveCheat(CheatCode),
+    SetCheatEnabled(CheatCode, bool),
+    /// Reports the cheats without changing anything.
+    ListCheats,
+    /// Starts a RAM search with every value in WRAM, HRAM and cartridge RAM as a candidate.
+    StartSearch {
+        width: SearchWidth,
+        signed: bool,
+    },
+    /// Keeps the RAM search candidates that pass a comparison against their current value.
+    NarrowSearch(SearchComparison),
+    /// Reports the RAM search candidates without changing anything.
+    ListSearchResults,
+    /// Adds GameShark cheats that hold a RAM search result at a value.
+    AddSearchCheat {
+        region: MemoryRegion,
+        offset: usize,
+        value: i32,
+    },
+}
+
+impl DebugCommand {
+    /// Whether the command resumes execution until it completes.
+    pub fn resumes(&self) -> bool {
+        matches!(
+            self,
+            DebugCommand::StepInstruction
+                | DebugCommand::StepOver
+                | DebugCommand::StepOut
+                | DebugCommand::RunToFrame(_)
+        )
+    }
+}
+
+/// The CPU flags, decoded from register F.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Flags {
+    pub zero: bool,
+    pub subtract: bool,
+    pub half_carry: bool,
+    pub carry: bool,
+}
+
+impl From<u8> for Flags {
+    fn from(f: u8) -> Self {
+        Flags {
+            zero: f & 0x80 != 0,
+            subtract: f & 0x40 != 0,
+            half_carry: f & 0x20 != 0,
+            carry: f & 0x10 != 0,
+        }
+    }
+}
+
+/// What the debugger reports whenever execution stops or a command is handled.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DebuggerState {
+    pub registers: Registers,
+    pub flags: Flags,
+    /// Why execution stopped, or None while it is running.
+    pub stop_reason: Option<StopReason>,
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// The instructions from the program counter on, disassembled.
+    pub instructions: Vec<Instruction>,
+}
+
+/// What the emulator sends back to the frontend. Stopping execution reports the debugger state,
+/// as does every command that doesn't resume execution, except for viewer commands which reply
+/// with what they render, memory commands which reply with the memory they access, cheat commands
+/// which reply with the cheats, and RAM search commands which reply with the candidates.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum DebugEvent {
+    State(DebuggerState),
+    Tiles(ViewerImage),
+    Tilemap(TilemapImage),
+    Oam(Vec<OamEntry>),
+    /// The bytes read by a memory command, or None if the range doesn't exist.
+    Memory(Option<Vec<u8>>),
+    Cheats(Vec<Cheat>),
+    Search(SearchResults),
+}
+
+/// A DebugEvent along with the id of the request it answers. Events the emulator reports
+/// unprompted, such as when it pauses, answer no request.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DebugReply {
+    pub request: Option<u32>,
+    pub event: DebugEvent,
+}
+
+/// A step command in progress.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Step {
+    Instruction,
+    /// Stops once execution is back at the instruction after a call, with the call's stack frame
+    /// popped. Recursive calls reach the same address with a deeper stack.
+    Over {
+        return_address: u16,
+        stack_pointer: u16,
+    },
+    /// Stops after a return that pops the stack above where it was.
+    Out {
+        stack_pointer: u16,
+    },
+}
+
+/// Decides where execution stops. The Gameboy consults it before each instruction and after each
+/// memory access the CPU makes, but only while it is active, so that it costs nothing otherwise.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    step: Option<Step>,
+    frames_remaining: Option<u32>,
+    /// The address execution resumed at, which shouldn't immediately stop again.
+    resume_address: Option<u16>,
+    previous_opcode: Option<u8>,
+    stop_reason: Option<StopReason>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            step: None,
+            frames_remaining: None,
+            resume_address: None,
+            previous_opcode: None,
+            stop_reason: None,
+        }
+    }
+
+    /// Whether anything could stop execution before the next instruction.
+    pub fn is_active(&self) -> bool {
+        !self.breakpoints.is_empty() || !self.watchpoints.is_empty() || self.step.is_some()
+    }
+
+    /// Whether the CPU needs to report its memory accesses.
+    pub fn watches_memory(&self) -> bool {
+        self.watchpoints.iter().any(|w| w.read || w.write)
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.retain(|b| b.address != breakpoint.address);
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.retain(|b| b.address != address);
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.retain(|w| *w != watchpoint);
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn step_instruction(&mut self) {
+        self.step = Some(Step::Instruction);
+    }
+
+    /// Starts stepping over the instruction at the program counter, which has the given opcode.
+    pub fn step_over(&mut self, registers: &Registers, opcode: u8) {
+        let length = match opcode {
+            opcode if CALL_OPCODES.contains(&opcode) => 3,
+            // RST
+            opcode if opcode & 0xC7 == 0xC7 => 1,
+            _ => return self.step_instruction(),
+        };
+
+        self.step = Some(Step::Over {
+            return_address: registers.pc.wrapping_add(length),
+            stack_pointer: registers.sp,
+        });
+    }
+
+    pub fn step_out(&mut self, registers: &Registers) {
+        self.step = Some(Step::Out {
+            stack_pointer: registers.sp,
+        });
+    }
+
+    pub fn run_frames(&mut self, frames: u32) {
+        self.frames_remaining = Some(frames.max(1));
+    }
+
+    /// Clears the stop, so that execution continues from the given instruction boundary without
+    /// stopping there again.
+    pub fn resume(&mut self, address: Option<u16>) {
+        self.stop_reason = None;
+        self.resume_address = address;
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop_reason.is_some()
+    }
+
+    pub fn take_stop_reason(&mut self) -> Option<StopReason> {
+        self.stop_reason.take()
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        info!("Debugger stopped. {:?}", reason);
+        self.step = None;
+        self.frames_remaining = None;
+        self.stop_reason = Some(reason);
+    }
+
+    /// Called before the instruction at the program counter runs. Returns true if execution
+    /// should stop first.
+    pub fn check_instruction(&mut self, registers: &Registers, opcode: u8) -> bool {
+        let address = registers.pc;
+        let previous_opcode = self.previous_opcode.replace(opcode);
+        if self.resume_address.take() == Some(address) {
+            return false;
+        }
+
+        let step_complete = match self.step {
+            Some(Step::Instruction) => true,
+            Some(Step::Over {
+                return_address,
+                stack_pointer,
+            }) => address == return_address && registers.sp >= stack_pointer,
+            Some(Step::Out { stack_pointer }) => {
+                previous_opcode.is_some_and(|opcode| RETURN_OPCODES.contains(&opcode))
+                    && registers.sp > stack_pointer
+            }
+            None => false,
+        };
+
+        let reason = if step_complete {
+            Some(StopReason::Step)
+        } else if self
+            .breakpoints
+            .iter()
+            .any(|b| b.address == address && b.condition.is_none_or(|c| c.matches(registers)))
+        {
+            Some(StopReason::Breakpoint(address))
+        } else {
+            self.watchpoints
+                .iter()
+                .any(|w| w.execute && w.contains(address))
+                .then_some(StopReason::Watchpoint {
+                    address,
+                    access: Access::Execute,
+                    value: opcode,
+                })
+        };
+
+        match reason {
+            Some(reason) => {
+                self.stop(reason);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Called after the CPU reads or writes memory.
+    pub fn check_access(&mut self, access: MemoryAccess) {
+        let watched = self.watchpoints.iter().any(|w| {
+            w.contains(access.address)
+                && match access.access {
+                    Access::Read => w.read,
+                    Access::Write => w.write,
+                    Access::Execute => w.execute,
+                }
+        });
+
+        if watched && !self.stopped() {
+            self.stop(StopReason::Watchpoint {
+                address: access.address,
+                access: access.access,
+                value: access.value,
+            });
+        }
+    }
+
+    /// Called at every frame boundary, counting down the frames of a RunToFrame.
+    pub fn end_frame(&mut self) {
+        match self.frames_remaining {
+            Some(1) => self.stop(StopReason::Frame),
+            Some(frames) => self.frames_remaining = Some(frames - 1),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The size of a switchable ROM bank.
+const ROM_BANK_SIZE: usize = 0x4000;
+
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const REGISTER_PAIRS: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const STACK_REGISTER_PAIRS: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CONDITIONS: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU_OPERATIONS: [&str; 8] = [
+    "ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP ",
+];
+const ROTATIONS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const ACCUMULATOR_OPERATIONS: [&str; 8] =
+    ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+
+/// An address, along with the ROM bank it is in for addresses in cartridge ROM. Written as
+/// `bank:address` in hex, such as 01:4000, or just the address.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Location {
+    pub bank: Option<u16>,
+    pub address: u16,
+}
+
+impl Location {
+    pub fn new(bank: Option<u16>, address: u16) -> Self {
+        Location { bank, address }
+    }
+
+    /// The offset of the location in a ROM file. Without a bank, addresses in the switchable bank
+    /// are taken to be in bank 1. Another bank can be given for the fixed bank too, as MBC1 can
+    /// map one there. Returns None for addresses outside cartridge ROM.
+    pub fn rom_offset(&self) -> Option<usize> {
+        let (default_bank, offset) = match self.address {
+            0x0000..=0x3FFF => (0, self.address as usize),
+            0x4000..=0x7FFF => (1, self.address as usize - ROM_BANK_SIZE),
+            _ => return None,
+        };
+
+        Some(self.bank.unwrap_or(default_bank) as usize * ROM_BANK_SIZE + offset)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.address),
+            None => write!(f, "{:04X}", self.address),
+        }
+    }
+}
+
+impl FromStr for Location {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |hex: &str| {
+            let hex = hex.trim_start_matches("0x").trim_start_matches('$');
+            u16::from_str_radix(hex, 16).with_context(|| format!("Invalid hex number {:?}.", hex))
+        };
+
+        match s.split_once(':') {
+            Some((bank, address)) => Ok(Location::new(Some(parse(bank)?), parse(address)?)),
+            None => Ok(Location::new(None, parse(s)?)),
+        }
+    }
+}
+
+/// A decoded SM83 instruction.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Instruction {
+    pub location: Location,
+    /// The opcode followed by any operands, including the 0xCB prefix.
+    pub bytes: Vec<u8>,
+    /// The mnemonic and its operands, such as `LD A,(0xC000)`. Relative jumps show the address
+    /// they jump to.
+    pub text: String,
+}
+
+impl Instruction {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<_> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(
+            f,
+            "{:<7}  {:<8}  {}",
+            self.location,
+            bytes.join(" "),
+            self.text
+        )
+    }
+}
+
+/// The length in bytes of the instruction with the given opcode, including its operands.
+pub fn instruction_length(opcode: u8) -> u16 {
+    match opcode {
+        // LD rr,nn; LD (nn),SP; JP; CALL; LD (nn),A; LD A,(nn)
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 => 3,
+        0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA => 3,
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => 3,
+        0xEA | 0xFA => 3,
+        // LD r,n; JR; STOP; CB prefix; LDH; ADD SP,e; LD HL,SP+e; ALU A,n
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => 2,
+        0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0x10 | 0xCB => 2,
+        0xE0 | 0xF0 | 0xE8 | 0xF8 => 2,
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => 2,
+        _ => 1,
+    }
+}
+
+/// Decodes the instruction at an address, reading its bytes with `read_byte`. The location is
+/// only used to label the instruction.
+pub fn disassemble(location: Location, read_byte: impl Fn(u16) -> u8) -> Instruction {
+    let address = location.address;
+    let opcode = read_byte(address);
+    let bytes: Vec<u8> = (0..instruction_length(opcode))
+        .map(|offset| read_byte(address.wrapping_add(offset)))
+        .collect();
+
+    let n = bytes.get(1).copied().unwrap_or(0);
+    let nn = u16::from_le_bytes([n, bytes.get(2).copied().unwrap_or(0)]);
+    // The offset of relative jumps, and of arithmetic on SP
+    let e = n as i8;
+    let jump_target = address.wrapping_add(2).wrapping_add(e as u16);
+
+    let x = opcode >> 6;
+    let y = ((opcode >> 3) & 0x07) as usize;
+    let z = (opcode & 0x07) as usize;
+    let p = y >> 1;
+    let q = y & 0x01;
+
+    let text = match (x, z) {
+        (0, 0) => match y {
+            0 => "NOP".to_string(),
+            1 => format!("LD ({:#06x}),SP", nn),
+            2 => "STOP".to_string(),
+            3 => format!("JR {:#06x}", jump_target),
+            _ => format!("JR {},{:#06x}", CONDITIONS[y - 4], jump_target),
+        },
+        (0, 1) => match q {
+            0 => format!("LD {},{:#06x}", REGISTER_PAIRS[p], nn),
+            _ => format!("ADD HL,{}", REGISTER_PAIRS[p]),
+        },
+        (0, 2) => {
+            let pointer = ["(BC)", "(DE)", "(HL+)", "(HL-)"][p];
+            match q {
+                0 => format!("LD {},A", pointer),
+                _ => format!("LD A,{}", pointer),
+            }
+        }
+        (0, 3) => match q {
+            0 => format!("INC {}", REGISTER_PAIRS[p]),
+            _ => format!("DEC {}", REGISTER_PAIRS[p]),
+        },
+        (0, 4) => format!("INC {}", REGISTERS[y]),
+        (0, 5) => format!("DEC {}", REGISTERS[y]),
+        (0, 6) => format!("LD {},{:#04x}", REGISTERS[y], n),
+        (0, _) => ACCUMULATOR_OPERATIONS[y].to_string(),
+        (1, 6) if y == 6 => "HALT".to_string(),
+        (1, _) => format!("LD {},{}", REGISTERS[y], REGISTERS[z]),
+        (2, _) => format!("{}{}", ALU_OPERATIONS[y], REGISTERS[z]),
+        (_, 0) => match y {
+            0..=3 => format!("RET {}", CONDITIONS[y]),
+            4 => format!("LDH ({:#06x}),A", 0xFF00 | n as u16),
+            5 => format!("ADD SP,{}", e),
+            6 => format!("LDH A,({:#06x})", 0xFF00 | n as u16),
+            _ => format!("LD HL,SP{:+}", e),
+        },
+        (_, 1) => match (q, p) {
+            (0, _) => format!("POP {}", STACK_REGISTER_PAIRS[p]),
+            (_, 0) => "RET".to_string(),
+            (_, 1) => "RETI".to_string(),
+            (_, 2) => "JP HL".to_string(),
+            _ => "LD SP,HL".to_string(),
+        },
+        (_, 2) => match y {
+            0..=3 => format!("JP {},{:#06x}", CONDITIONS[y], nn),
+            4 => "LDH (C),A".to_string(),
+            5 => format!("LD ({:#06x}),A", nn),
+            6 => "LDH A,(C)".to_string(),
+            _ => format!("LD A,({:#06x})", nn),
+        },
+        (_, 3) => match y {
+            0 => format!("JP {:#06x}", nn),
+            1 => disassemble_prefixed(n),
+            6 => "DI".to_string(),
+            7 => "EI".to_string(),
+            _ => format!("DB {:#04x}", opcode),
+        },
+        (_, 4) => match y {
+            0..=3 => format!("CALL {},{:#06x}", CONDITIONS[y], nn),
+            _ => format!("DB {:#04x}", opcode),
+        },
+        (_, 5) => match (q, p) {
+            (0, _) => format!("PUSH {}", STACK_REGISTER_PAIRS[p]),
+            (_, 0) => format!("CALL {:#06x}", nn),
+            _ => format!("DB {:#04x}", opcode),
+        },
+        (_, 6) => format!("{}{:#04x}", ALU_OPERATIONS[y], n),
+        _ => format!("RST {:#04x}", y * 8),
+    };
+
+    Instruction {
+        location,
+        bytes,
+        text,
+    }
+}
+
+/// Decodes the instruction following a 0xCB prefix.
+fn disassemble_prefixed(opcode: u8) -> String {
+    let y = ((opcode >> 3) & 0x07) as usize;
+    let register = REGISTERS[(opcode & 0x07) as usize];
+
+    match opcode >> 6 {
+        0 => format!("{} {}", ROTATIONS[y], register),
+        1 => format!("BIT {},{}", y, register),
+        2 => format!("RES {},{}", y, register),
+        _ => format!("SET {},{}", y, register),
+    }
+}
+
+/// Decodes consecutive instructions from a ROM file, starting at a location in one of its banks,
+/// and labels each with its bank. Stops early at the end of the bank.
+pub fn disassemble_rom(rom: &[u8], location: Location, count: usize) -> Vec<Instruction> {
+    let Some(start) = location.rom_offset() else {
+        return Vec::new();
+    };
+    let bank = (start / ROM_BANK_SIZE) as u16;
+    // The address the bank is mapped at, either 0x0000 or 0x4000
+    let window = (location.address as usize) & ROM_BANK_SIZE;
+    let read_byte = |address: u16| {
+        let offset = bank as usize * ROM_BANK_SIZE + address as usize - window;
+        rom.get(offset).copied().unwrap_or(0xFF)
+    };
+
+    let mut instructions = Vec::new();
+    let mut address = location.address as usize;
+    while instructions.len() < count && address < window + ROM_BANK_SIZE {
+        let instruction = disassemble(Location::new(Some(bank), address as u16), read_byte);
+        address += instruction.length() as usize;
+        instructions.push(instruction);
+    }
+
+    instructions
+}
@@ -1,15 +1,16 @@
-use tauri::{AppHandle, Emitter};
-
 /// A Display provides functions to render scanlines and present frames.
 pub trait Display {
-    // TODO: Color should probably be determined by PPU, not indexed by display
-
-    /// Push a pixel to the current scanline
-    fn push_pixel(&mut self, x: u8, y: u8, color: u8);
+    /// Push a pixel to the current scanline. The color is in RGB555 format, with red in the lowest
+    /// five bits, followed by green and then blue.
+    fn push_pixel(&mut self, x: u8, y: u8, color: u16);
 
     /// Presents the rendered frame on the screen. Should be called during VBlank.
     fn present(&mut self);
 
+    /// Changes the dimensions of the frames pushed from now on. Frames are 160x144 unless this is
+    /// called. SGB frames, which include the border, are 256x224.
+    fn resize(&mut self, _width: u16, _height: u16) {}
+
     /// Renders a debug box around the specified coordinates
     #[cfg(debug_assertions)]
     #[allow(unused_variables)]
@@ -19,45 +20,12 @@ pub trait Display {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Color(pub u8, pub u8, pub u8, pub u8);
 
-pub struct WebviewDisplay {
-    app_handle: AppHandle,
-    buffer: [[u8; 160]; 144],
-}
-
-impl WebviewDisplay {
-    pub fn new(app_handle: AppHandle) -> Self {
-        WebviewDisplay {
-            app_handle,
-            buffer: [[0; 160]; 144],
-        }
-    }
-}
-
-impl Display for WebviewDisplay {
-    fn push_pixel(&mut self, x: u8, y: u8, color: u8) {
-        debug_assert!(
-            color <= 3,
-            "Invalid color index {} provided to Display.",
-            color
-        );
-        debug_assert!(
-            (0..160).contains(&x),
-            "Invalid x-coordinate {} provided to Display.",
-            x
-        );
-        debug_assert!(
-            (0..144).contains(&y),
-            "Invalid y-coordinate {} provided to Display.",
-            y
-        );
+/// A Display that discards all output, for running the emulator without a screen.
+#[derive(Debug, Default)]
+pub struct NullDisplay;
 
-        self.buffer[y as usize][x as usize] = color;
-    }
+impl Display for NullDisplay {
+    fn push_pixel(&mut self, _x: u8, _y: u8, _color: u16) {}
 
-    fn present(&mut self) {
-        let flat_buffer: Vec<u8> = self.buffer.iter().flatten().copied().collect();
-        self.app_handle
-            .emit("gb-present-frame", flat_buffer)
-            .unwrap();
-    }
+    fn present(&mut self) {}
 }
@@ -1,10 +1,15 @@
 use bitflags::bitflags;
-use log::{debug, trace};
+use log::trace;
+use serde::{Deserialize, Serialize};
 
 use crate::emulator::EmulatorInput;
 
-use super::memory::{Interrupt, Register};
+use super::{
+    memory::{Interrupt, Register},
+    state::Snapshot,
+};
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Joypad {
     dpad: JOYP,
     buttons: JOYP,
@@ -29,32 +34,37 @@ impl Joypad {
         }
     }
 
-    pub fn keydown(&mut self, input: EmulatorInput) {
-        debug!("Registered key down: {:?}", input);
-        match input {
-            EmulatorInput::Up => self.dpad.remove(JOYP::UP_SELECT),
-            EmulatorInput::Down => self.dpad.remove(JOYP::DOWN_START),
-            EmulatorInput::Left => self.dpad.remove(JOYP::LEFT_B),
-            EmulatorInput::Right => self.dpad.remove(JOYP::RIGHT_A),
-            EmulatorInput::Start => self.buttons.remove(JOYP::DOWN_START),
-            EmulatorInput::Select => self.buttons.remove(JOYP::UP_SELECT),
-            EmulatorInput::B => self.buttons.remove(JOYP::LEFT_B),
-            EmulatorInput::A => self.buttons.remove(JOYP::RIGHT_A),
-        }
+    /// Sets which buttons are currently held down.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        trace!("Registered buttons: {:?}", buttons);
+        self.dpad
+            .set(JOYP::UP_SELECT, !buttons.contains(Buttons::UP));
+        self.dpad
+            .set(JOYP::DOWN_START, !buttons.contains(Buttons::DOWN));
+        self.dpad
+            .set(JOYP::LEFT_B, !buttons.contains(Buttons::LEFT));
+        self.dpad
+            .set(JOYP::RIGHT_A, !buttons.contains(Buttons::RIGHT));
+        self.buttons
+            .set(JOYP::DOWN_START, !buttons.contains(Buttons::START));
+        self.buttons
+            .set(JOYP::UP_SELECT, !buttons.contains(Buttons::SELECT));
+        self.buttons
+            .set(JOYP::LEFT_B, !buttons.contains(Buttons::B));
+        self.buttons
+            .set(JOYP::RIGHT_A, !buttons.contains(Buttons::A));
     }
+}
 
-    pub fn keyup(&mut self, input: EmulatorInput) {
-        debug!("Registered key up: {:?}", input);
-        match input {
-            EmulatorInput::Up => self.dpad.insert(JOYP::UP_SELECT),
-            EmulatorInput::Down => self.dpad.insert(JOYP::DOWN_START),
-            EmulatorInput::Left => self.dpad.insert(JOYP::LEFT_B),
-            EmulatorInput::Right => self.dpad.insert(JOYP::RIGHT_A),
-            EmulatorInput::Start => self.buttons.insert(JOYP::DOWN_START),
-            EmulatorInput::Select => self.buttons.insert(JOYP::UP_SELECT),
-            EmulatorInput::B => self.buttons.insert(JOYP::LEFT_B),
-            EmulatorInput::A => self.buttons.insert(JOYP::RIGHT_A),
-        }
+impl Snapshot for Joypad {
+    type State = Joypad;
+
+    fn save_state(&self) -> Joypad {
+        self.clone()
+    }
+
+    fn load_state(&mut self, state: Joypad) {
+        *self = state;
     }
 }
 
@@ -89,7 +99,7 @@ impl Register for Joypad {
 
 bitflags! {
     #[repr(transparent)]
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
     struct JOYP: u8 {
         const SELECT_BUTTONS = 0b0010_0000;
         const SELECT_DPAD = 0b0001_0000;
@@ -99,3 +109,34 @@ bitflags! {
         const RIGHT_A = 0b0000_0001;
     }
 }
+
+bitflags! {
+    /// The set of buttons held down during a frame.
+    #[repr(transparent)]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+    pub struct Buttons: u8 {
+        const A = 0b0000_0001;
+        const B = 0b0000_0010;
+        const SELECT = 0b0000_0100;
+        const START = 0b0000_1000;
+        const RIGHT = 0b0001_0000;
+        const LEFT = 0b0010_0000;
+        const UP = 0b0100_0000;
+        const DOWN = 0b1000_0000;
+    }
+}
+
+impl From<EmulatorInput> for Buttons {
+    fn from(input: EmulatorInput) -> Self {
+        match input {
+            EmulatorInput::A => Buttons::A,
+            EmulatorInput::B => Buttons::B,
+            EmulatorInput::Select => Buttons::SELECT,
+            EmulatorInput::Start => Buttons::START,
+            EmulatorInput::Right => Buttons::RIGHT,
+            EmulatorInput::Left => Buttons::LEFT,
+            EmulatorInput::Up => Buttons::UP,
+            EmulatorInput::Down => Buttons::DOWN,
+        }
+    }
+}
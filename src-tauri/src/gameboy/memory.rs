@@ -1,15 +1,34 @@
 pub mod cartridge;
+mod cheats;
+pub mod clock;
+mod editor;
+mod hdma;
 mod mbc;
+mod search;
 
+use anyhow::bail;
 use bitflags::bitflags;
-use cartridge::Cartridge;
+use cartridge::{Cartridge, CartridgeSnapshot};
+use hdma::{HDMA, HDMA_BLOCK_SIZE};
 use log::{info, trace};
+use serde::{Deserialize, Serialize};
 
 use std::{rc::Rc, sync::RwLock};
 
-use super::{apu::APU, joypad::Joypad, ppu::PPU, serial::Serial, timer::Timer, GlobalConstants};
-
-const BOOT_ROM: &[u8; 256] = include_bytes!("./memory/dmg_boot.bin");
+pub use cheats::{Cheat, CheatCode, Cheats};
+pub use editor::MemoryRegion;
+pub use search::{RamSearch, SearchComparison, SearchResult, SearchResults, SearchWidth};
+
+use super::{
+    apu::{APUSnapshot, APU},
+    boot::EMBEDDED_BOOT_ROM,
+    joypad::Joypad,
+    ppu::{PPUSnapshot, PPU},
+    serial::{Serial, SerialSnapshot},
+    state::Snapshot,
+    timer::Timer,
+    GlobalConstants, Model,
+};
 
 pub type SharedMemoryController = Rc<RwLock<dyn MemoryController>>;
 
@@ -18,6 +37,13 @@ pub trait MemoryController {
     fn write_byte(&mut self, address: u16, value: u8);
 
     fn tick(&mut self, _cycles: u32) {}
+
+    /// Called when the CPU executes STOP. Performs a CGB speed switch if one has been armed through
+    /// KEY1, returning true if the speed changed.
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
+
     fn trigger_interrupt(&mut self, interrupt: Interrupt) {
         trace!("Triggered interrupt: {:?}", interrupt);
         let mut if_register = Interrupt::from_bits_truncate(self.read_byte(0xFF0F));
@@ -36,7 +62,7 @@ pub trait Register {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum DMAState {
     Active(u16, u16, u8),
     Inactive,
@@ -44,7 +70,7 @@ pub enum DMAState {
 
 bitflags! {
     #[repr(transparent)]
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
     pub struct Interrupt: u8 {
         const VBLANK = 0b0000_0001;
         const LCD = 0b0000_0010;
@@ -54,12 +80,55 @@ bitflags! {
     }
 }
 
+/// The registers the boot ROM leaves set when it hands over to the cartridge. NR52 comes first so
+/// the APU is powered on. The channel trigger bits are left clear, so the end of the boot sound
+/// isn't played.
+const POST_BOOT_REGISTERS: [(u16, u8); 25] = [
+    (0xFF26, 0x80),
+    (0xFF10, 0x80),
+    (0xFF11, 0xBF),
+    (0xFF12, 0xF3),
+    (0xFF13, 0xFF),
+    (0xFF14, 0x3F),
+    (0xFF16, 0x3F),
+    (0xFF17, 0x00),
+    (0xFF18, 0xFF),
+    (0xFF19, 0x3F),
+    (0xFF1A, 0x7F),
+    (0xFF1B, 0xFF),
+    (0xFF1C, 0x9F),
+    (0xFF1D, 0xFF),
+    (0xFF1E, 0x3F),
+    (0xFF20, 0xFF),
+    (0xFF21, 0x00),
+    (0xFF22, 0x00),
+    (0xFF23, 0x3F),
+    (0xFF24, 0x77),
+    (0xFF25, 0xF3),
+    (0xFF07, 0xF8),
+    (0xFF0F, 0xE1),
+    (0xFF40, 0x91),
+    (0xFF47, 0xFC),
+];
+
+/// The ® tile the DMG boot ROM draws next to the logo, one byte per row.
+const REGISTERED_TRADEMARK: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
 pub struct MemoryBus {
+    model: Model,
     boot_mode: bool,
+    boot_rom: Vec<u8>,
     cartridge: Cartridge,
+    cheats: Cheats,
     internal_memory: [u8; 16384],
+    wram_banks: [u8; 0x6000],
+    svbk: u8,
+    speed_switch_armed: bool,
+    double_speed: bool,
     dma_state: DMAState,
+    hdma: HDMA,
     pending_cycles: i32,
+    ly_stubbed: bool,
     timer: Timer,
     joypad: Rc<RwLock<Joypad>>,
     serial: Serial,
@@ -70,6 +139,8 @@ pub struct MemoryBus {
 impl MemoryBus {
     pub fn builder() -> MemoryBusBuilder {
         MemoryBusBuilder {
+            model: None,
+            boot_rom: None,
             ppu: None,
             apu: None,
             cartridge: None,
@@ -79,18 +150,172 @@ impl MemoryBus {
         }
     }
 
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    /// The cartridge ROM bank currently mapped at an address, or None if the address isn't in
+    /// cartridge ROM or the boot ROM is mapped over it.
+    pub fn rom_bank(&self, address: u16) -> Option<u16> {
+        let boot_rom_mapped = self.boot_mode
+            && (address <= 0x00FF
+                || ((0x0200..=0x08FF).contains(&address) && self.boot_rom.len() > 0x100));
+
+        match boot_rom_mapped {
+            true => None,
+            false => self.cartridge.rom_bank(address),
+        }
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    /// Returns true if the PPU has entered VBlank since this was last called.
+    pub fn take_frame_complete(&mut self) -> bool {
+        self.ppu.take_frame_complete()
+    }
+
+    pub fn set_audio_muted(&mut self, muted: bool) {
+        self.apu.set_muted(muted);
+    }
+
+    /// Makes LY always read 0x90 to the CPU, as gameboy-doctor expects. Its reference logs were
+    /// made that way so that they don't depend on PPU timing.
+    pub fn set_ly_stubbed(&mut self, stubbed: bool) {
+        self.ly_stubbed = stubbed;
+    }
+
+    /// Leaves boot mode without running the boot ROM, with the registers, DIV and VRAM set as the
+    /// boot ROM of the model leaves them.
+    pub fn skip_boot(&mut self) {
+        self.boot_mode = false;
+
+        // The SGB and CGB boot ROMs don't leave the logo behind
+        if matches!(self.model, Model::DMG0 | Model::DMG | Model::MGB) {
+            self.load_boot_logo();
+        }
+        for (address, value) in POST_BOOT_REGISTERS {
+            self.raw_write(address, value);
+        }
+
+        // How long the SGB and CGB boot ROMs run for varies, so DIV is left as is
+        match self.model {
+            Model::DMG0 => self.timer.set_divider(0x1800),
+            Model::DMG | Model::MGB => self.timer.set_divider(0xABCC),
+            Model::SGB | Model::CGB => {}
+        }
+    }
+
+    /// Decodes the logo from the cartridge header into tiles 1-24 and places them on the
+    /// background map, along with the ® tile.
+    fn load_boot_logo(&mut self) {
+        for index in 0..48u16 {
+            let byte = self.cartridge.read(0x0104 + index);
+
+            // Each nibble is a row of four pixels, which are doubled in both directions
+            for (row, nibble) in [byte >> 4, byte & 0x0F].into_iter().enumerate() {
+                let doubled = (0..4).fold(0, |doubled, bit| {
+                    doubled | (((nibble >> bit) & 0x01) * 0b11) << (bit * 2)
+                });
+                let address = 0x8010 + index * 8 + row as u16 * 4;
+                self.ppu.write(address, doubled);
+                self.ppu.write(address + 2, doubled);
+            }
+        }
+        for (row, byte) in REGISTERED_TRADEMARK.into_iter().enumerate() {
+            self.ppu.write(0x8190 + row as u16 * 2, byte);
+        }
+
+        for tile in 1..=12u8 {
+            self.ppu.write(0x9903 + tile as u16, tile);
+            self.ppu.write(0x9923 + tile as u16, tile + 12);
+        }
+        self.ppu.write(0x9910, 0x19);
+    }
+
+    /// Converts a number of CPU t-cycles to t-cycles at normal speed. In CGB double speed mode,
+    /// the CPU runs twice as fast while the PPU, APU and cartridge keep their normal rate.
+    pub fn normal_speed_cycles(&self, cycles: u32) -> u32 {
+        match self.double_speed {
+            true => cycles / 2,
+            false => cycles,
+        }
+    }
+
+    /// Whether the CPU is halted while VRAM DMA copies a block.
+    pub fn cpu_stalled(&self) -> bool {
+        self.hdma.stalled()
+    }
+
+    /// Copies the next block of a VRAM DMA transfer into the currently mapped VRAM bank.
+    fn copy_vram_dma_block(&mut self) {
+        let speed_factor = match self.double_speed {
+            true => 2,
+            false => 1,
+        };
+        let (source, destination) = self.hdma.next_block(speed_factor);
+
+        for offset in 0..HDMA_BLOCK_SIZE {
+            let byte = self.raw_read(source.wrapping_add(offset));
+            self.ppu.write(destination + offset, byte);
+        }
+    }
+
+    /// The WRAM bank mapped to 0xD000..=0xDFFF. Bank 0 can't be selected there, so selecting it
+    /// maps bank 1.
+    #[inline]
+    fn wram_bank(&self) -> usize {
+        match self.svbk & 0x07 {
+            0 => 1,
+            bank => bank as usize,
+        }
+    }
+
     fn raw_read(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x00FF if self.boot_mode => BOOT_ROM[address as usize],
-            0x0000..=0x7FFF => self.cartridge.read(address),
+            0x0000..=0x00FF if self.boot_mode => self.boot_rom[address as usize],
+            // The CGB boot ROM continues after the cartridge header
+            0x0200..=0x08FF if self.boot_mode && self.boot_rom.len() > 0x100 => {
+                self.boot_rom[address as usize]
+            }
+            0x0000..=0x7FFF => {
+                let value = self.cartridge.read(address);
+                self.cheats
+                    .patch_rom(address, value, || self.cartridge.rom_bank(address))
+            }
             0x8000..=0x9FFF => self.ppu.read(address),
             0xA000..=0xBFFF => self.cartridge.read(address),
             0xFE00..=0xFE9F => self.ppu.read(address),
             0xFF01..=0xFF02 => self.serial.read(address),
             0xFF04..=0xFF07 => self.timer.read(address),
+            0xFF44 if self.ly_stubbed => 0x90,
             0xFF40..=0xFF4B => self.ppu.read(address),
-            0xFF00 => self.joypad.read().unwrap().read(address),
+            0xFF00 => {
+                let value = self.joypad.read().unwrap().read(address);
+                // In SGB multiplayer mode, the selected controller is reported while no buttons
+                // are selected. Only the first controller is connected.
+                match self.ppu.sgb().and_then(|sgb| sgb.joypad_id()) {
+                    Some(id) if value & 0x30 == 0x30 => (value & 0xF0) | id,
+                    Some(0x0F) | None => value,
+                    Some(_) => value | 0x0F,
+                }
+            }
             0xFF10..=0xFF3F if GlobalConstants::AUDIO_ENABLED => self.apu.read(address),
+            // Banks 2-7 are only reachable in CGB mode. Bank 1 lives in internal memory.
+            0xD000..=0xDFFF if self.wram_bank() > 1 => {
+                self.wram_banks[(self.wram_bank() - 2) * 0x1000 + (address - 0xD000) as usize]
+            }
+            0xFF4D if self.model == Model::CGB => {
+                ((self.double_speed as u8) << 7) | 0x7E | self.speed_switch_armed as u8
+            }
+            0xFF4F | 0xFF68..=0xFF6B if self.model == Model::CGB => self.ppu.read(address),
+            0xFF70 if self.model == Model::CGB => 0xF8 | self.svbk,
+            0xFF51..=0xFF55 if self.model == Model::CGB => self.hdma.read(address),
             0xC000..=0xFFFF => self.internal_memory[(address - 0xC000) as usize],
         }
     }
@@ -122,7 +347,25 @@ impl MemoryBus {
             0xFF04..=0xFF07 => self.timer.write(address, value),
             0xFE00..=0xFE9F => self.ppu.write(address, value),
             0xFF40..=0xFF4B => self.ppu.write(address, value),
-            0xFF00 => self.joypad.write().unwrap().write(address, value),
+            0xFF00 => {
+                self.joypad.write().unwrap().write(address, value);
+                if let Some(sgb) = self.ppu.sgb_mut() {
+                    sgb.write_joypad(value);
+                }
+            }
+            0xD000..=0xDFFF if self.wram_bank() > 1 => {
+                self.wram_banks[(self.wram_bank() - 2) * 0x1000 + (address - 0xD000) as usize] =
+                    value;
+            }
+            0xFF4D if self.model == Model::CGB => self.speed_switch_armed = value & 0x01 != 0,
+            0xFF4F | 0xFF68..=0xFF6B if self.model == Model::CGB => self.ppu.write(address, value),
+            0xFF70 if self.model == Model::CGB => self.svbk = value & 0x07,
+            0xFF51..=0xFF55 if self.model == Model::CGB => {
+                // General purpose transfers complete immediately, halting the CPU meanwhile
+                for _ in 0..self.hdma.write(address, value) {
+                    self.copy_vram_dma_block();
+                }
+            }
             0xC000..=0xFFFF => {
                 self.internal_memory[(address - 0xC000) as usize] = value;
             }
@@ -161,11 +404,19 @@ impl MemoryBus {
 
 impl MemoryController for MemoryBus {
     fn tick(&mut self, cycles: u32) {
-        self.ppu.tick(cycles);
+        let normal_speed_cycles = self.normal_speed_cycles(cycles);
+
+        self.cartridge.tick(normal_speed_cycles);
+        self.ppu.tick(normal_speed_cycles);
         self.timer.tick(cycles);
         self.serial.tick(cycles);
         self.joypad.write().unwrap().tick(cycles);
-        self.apu.tick(cycles);
+        self.apu.tick(normal_speed_cycles);
+        self.hdma.tick(cycles);
+
+        if self.ppu.take_hblank_started() && self.hdma.hblank_active() {
+            self.copy_vram_dma_block();
+        }
 
         self.check_interrupts();
 
@@ -190,6 +441,25 @@ impl MemoryController for MemoryBus {
         }
     }
 
+    fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch_armed {
+            return false;
+        }
+
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+        info!(
+            "Switched to {} speed.",
+            if self.double_speed {
+                "double"
+            } else {
+                "normal"
+            }
+        );
+
+        true
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         match (address, &self.dma_state) {
             (_, DMAState::Inactive) => self.raw_read(address),
@@ -210,6 +480,81 @@ impl MemoryController for MemoryBus {
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct MemoryBusSnapshot {
+    boot_mode: bool,
+    cartridge: CartridgeSnapshot,
+    internal_memory: Vec<u8>,
+    wram_banks: Vec<u8>,
+    svbk: u8,
+    speed_switch_armed: bool,
+    double_speed: bool,
+    dma_state: DMAState,
+    hdma: HDMA,
+    pending_cycles: i32,
+    timer: Timer,
+    joypad: Joypad,
+    serial: SerialSnapshot,
+    apu: APUSnapshot,
+    ppu: PPUSnapshot,
+}
+
+impl Snapshot for MemoryBus {
+    type State = MemoryBusSnapshot;
+
+    fn save_state(&self) -> MemoryBusSnapshot {
+        MemoryBusSnapshot {
+            boot_mode: self.boot_mode,
+            cartridge: self.cartridge.save_state(),
+            internal_memory: self.internal_memory.to_vec(),
+            wram_banks: self.wram_banks.to_vec(),
+            svbk: self.svbk,
+            speed_switch_armed: self.speed_switch_armed,
+            double_speed: self.double_speed,
+            dma_state: self.dma_state.clone(),
+            hdma: self.hdma.clone(),
+            pending_cycles: self.pending_cycles,
+            timer: self.timer.save_state(),
+            joypad: self.joypad.read().unwrap().save_state(),
+            serial: self.serial.save_state(),
+            apu: self.apu.save_state(),
+            ppu: self.ppu.save_state(),
+        }
+    }
+
+    fn load_state(&mut self, state: MemoryBusSnapshot) {
+        self.boot_mode = state.boot_mode;
+        self.cartridge.load_state(state.cartridge);
+        self.internal_memory.copy_from_slice(&state.internal_memory);
+        self.wram_banks.copy_from_slice(&state.wram_banks);
+        self.svbk = state.svbk;
+        self.speed_switch_armed = state.speed_switch_armed;
+        self.double_speed = state.double_speed;
+        self.dma_state = state.dma_state;
+        self.hdma = state.hdma;
+        self.pending_cycles = state.pending_cycles;
+        self.timer.load_state(state.timer);
+        self.joypad.write().unwrap().load_state(state.joypad);
+        self.serial.load_state(state.serial);
+        self.apu.load_state(state.apu);
+        self.ppu.load_state(state.ppu);
+    }
+}
+
+impl MemoryBus {
+    /// Checks that a snapshot holds the whole of internal memory, so that a truncated or corrupt
+    /// one is rejected before any of it is restored.
+    pub fn check_state(&self, state: &MemoryBusSnapshot) -> Result<(), anyhow::Error> {
+        if state.internal_memory.len() != self.internal_memory.len()
+            || state.wram_banks.len() != self.wram_banks.len()
+        {
+            bail!("Save state memory is truncated or corrupt.");
+        }
+
+        Ok(())
+    }
+}
+
 pub struct TestMemoryBus {
     memory: [u8; 0x10000],
 }
@@ -251,6 +596,8 @@ impl TestMemoryBus {
 }
 
 pub struct MemoryBusBuilder {
+    model: Option<Model>,
+    boot_rom: Option<Vec<u8>>,
     ppu: Option<PPU>,
     apu: Option<APU>,
     cartridge: Option<Cartridge>,
@@ -260,6 +607,18 @@ pub struct MemoryBusBuilder {
 }
 
 impl MemoryBusBuilder {
+    /// The hardware model, which decides whether CGB registers and WRAM banks are available.
+    /// Defaults to DMG.
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = Some(model);
+        self
+    }
+    /// The boot ROM mapped over the start of the cartridge until 0xFF50 is written. Defaults to the
+    /// embedded DMG boot ROM.
+    pub fn boot_rom(mut self, boot_rom: Vec<u8>) -> Self {
+        self.boot_rom = Some(boot_rom);
+        self
+    }
     pub fn ppu(mut self, ppu: PPU) -> Self {
         self.ppu = Some(ppu);
         self
@@ -303,11 +662,20 @@ impl MemoryBusBuilder {
         );
 
         MemoryBus {
+            model: self.model.unwrap_or(Model::DMG),
             boot_mode: true,
+            boot_rom: self.boot_rom.unwrap_or_else(|| EMBEDDED_BOOT_ROM.to_vec()),
             cartridge: self.cartridge.unwrap(),
+            cheats: Cheats::default(),
             internal_memory: [0; 16384],
+            wram_banks: [0; 0x6000],
+            svbk: 0,
+            speed_switch_armed: false,
+            double_speed: false,
             dma_state: DMAState::Inactive,
+            hdma: HDMA::new(),
             pending_cycles: 0,
+            ly_stubbed: false,
             timer: self.timer.unwrap(),
             joypad: self.joypad.unwrap(),
             serial: self.serial.unwrap(),
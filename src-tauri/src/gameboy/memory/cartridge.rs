@@ -1,12 +1,13 @@
 use std::{
     fmt::Debug,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
     thread::JoinHandle,
 };
 
 use anyhow::bail;
 use log::info;
+use serde::{Deserialize, Serialize};
 
 use std::{
     fs,
@@ -16,21 +17,44 @@ use std::{
 use crossbeam::channel::Sender;
 use log::error;
 
+use crate::gameboy::{state::Snapshot, RumbleSink};
+
 use super::{
-    mbc::{BankType, NoMBC, MBC, MBC1, MBC2, MBC3},
+    clock::Clock,
+    mbc::{
+        BankType, MBCSnapshot, NoMBC, MBC, MBC1, MBC2, MBC3, MBC5, RTC_FOOTER_SIZE,
+        RTC_LEGACY_FOOTER_SIZE,
+    },
     Register,
 };
 
 #[derive(Debug)]
 pub struct Cartridge {
+    title: String,
     mbc: Box<dyn MBC>,
     rom: Vec<u8>,
     ram: Arc<RwLock<Vec<u8>>>,
     persister: Option<Persister>,
+    persistent: bool,
+    rumble: Box<dyn RumbleSink>,
 }
 
 impl Cartridge {
-    pub fn new(rom: Vec<u8>, mut save_data_path: PathBuf) -> Result<Self, anyhow::Error> {
+    /// Constructs a cartridge from ROM data. If a save directory is provided, battery-backed RAM
+    /// is loaded from and persisted to a save file named after the cartridge title. Cartridges
+    /// with a real time clock read the time from the provided clock, and persist the clock
+    /// registers in a footer after the RAM. Cartridges with a rumble motor notify the provided
+    /// sink when it starts or stops.
+    pub fn new(
+        rom: Vec<u8>,
+        save_directory: Option<PathBuf>,
+        rtc_clock: Box<dyn Clock>,
+        rumble: Box<dyn RumbleSink>,
+    ) -> Result<Self, anyhow::Error> {
+        if rom.len() < 0x150 {
+            bail!("ROM is too small to contain a cartridge header.");
+        }
+
         let mbc_type = rom[0x147];
         let title: String = rom[0x0134..=0x0143]
             .iter()
@@ -38,10 +62,7 @@ impl Cartridge {
             .map(|byte| *byte as char)
             .collect();
 
-        save_data_path.push(format!("{}.sav", &title));
-        info!("Preparing save data at location {:?}", save_data_path);
-
-        let mbc: Box<dyn MBC> = match mbc_type {
+        let mut mbc: Box<dyn MBC> = match mbc_type {
             0x00 => Box::new(NoMBC::new()),
             0x01..=0x03 => {
                 info!("Constructed cartridge with MBC1.");
@@ -53,7 +74,11 @@ impl Cartridge {
             }
             0x0F..=0x13 => {
                 info!("Constructed cartridge with MBC3.");
-                Box::new(MBC3::new())
+                Box::new(MBC3::new(rtc_clock))
+            }
+            0x19..=0x1E => {
+                info!("Constructed cartridge with MBC5.");
+                Box::new(MBC5::new(mbc_type >= 0x1C))
             }
             other => bail!("Unsupported MBC type {}.", other),
         };
@@ -71,8 +96,18 @@ impl Cartridge {
         let ram = vec![0; ram_size];
         let ram = Arc::new(RwLock::new(ram));
 
-        let persister = match mbc_type {
-            0x03 | 0x06 | 0x0F | 0x10 | 0x13 => Some(Persister::new(save_data_path, ram.clone())),
+        let persister = match (mbc_type, save_directory) {
+            (0x03 | 0x06 | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E, Some(save_directory)) => {
+                let save_data_path = save_directory.join(format!("{}.sav", &title));
+                info!("Preparing save data at location {:?}", save_data_path);
+
+                let has_rtc = matches!(mbc_type, 0x0F | 0x10);
+                if let Some(footer) = load_save_data(&save_data_path, &ram, has_rtc) {
+                    mbc.load_rtc_footer(&footer);
+                }
+
+                Some(Persister::new(save_data_path, ram.clone()))
+            }
             _ => None,
         };
 
@@ -83,12 +118,136 @@ impl Cartridge {
         );
 
         Ok(Cartridge {
+            title,
             mbc,
             rom,
             ram,
             persister,
+            persistent: true,
+            rumble,
         })
     }
+
+    /// The title stored in the cartridge header.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The global checksum stored in the cartridge header at 0x014E..=0x014F.
+    pub fn global_checksum(&self) -> u16 {
+        ((self.rom[0x014E] as u16) << 8) | (self.rom[0x014F] as u16)
+    }
+
+    /// Whether the header marks the game as enhanced for, or exclusive to, the CGB.
+    pub fn supports_cgb(&self) -> bool {
+        self.rom[0x0143] & 0x80 != 0
+    }
+
+    /// Whether the header marks the game as supporting SGB functions. The old licensee code must
+    /// be 0x33 for the SGB flag to count.
+    pub fn supports_sgb(&self) -> bool {
+        self.rom[0x0146] == 0x03 && self.rom[0x014B] == 0x33
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// The ROM bank currently mapped at an address, or None if it isn't in cartridge ROM.
+    pub fn rom_bank(&self, address: u16) -> Option<u16> {
+        match self.mbc.translate_address(address) {
+            Some((physical_address, BankType::ROM)) if !self.rom.is_empty() => {
+                Some((physical_address as usize % self.rom.len() / 0x4000) as u16)
+            }
+            _ => None,
+        }
+    }
+
+    /// A copy of the current contents of cartridge RAM.
+    pub fn ram(&self) -> Vec<u8> {
+        self.ram.read().unwrap().clone()
+    }
+
+    /// Patches a byte of the loaded ROM. The ROM file itself is left unchanged.
+    pub fn write_rom(&mut self, offset: usize, value: u8) {
+        let rom_size = self.rom.len();
+        self.rom[offset % rom_size] = value;
+    }
+
+    /// The size of cartridge RAM in bytes.
+    pub fn ram_size(&self) -> usize {
+        self.ram.read().unwrap().len()
+    }
+
+    /// Reads a byte of cartridge RAM, regardless of banking or whether RAM is enabled.
+    pub fn read_ram(&self, offset: usize) -> u8 {
+        self.ram.read().unwrap()[offset]
+    }
+
+    /// Writes a byte of cartridge RAM, regardless of banking or whether RAM is enabled. The write
+    /// is persisted like any other.
+    pub fn write_ram(&mut self, offset: usize, value: u8) {
+        self.ram.write().unwrap()[offset] = value;
+        self.persist();
+    }
+
+    /// Replaces the contents of cartridge RAM without persisting them.
+    pub fn set_ram(&mut self, data: &[u8]) {
+        // The RAM is shared with the persister, so it must be updated in place
+        let mut ram = self.ram.write().unwrap();
+        ram.clear();
+        ram.extend_from_slice(data);
+    }
+
+    /// Controls whether writes to battery-backed RAM are persisted to the save file. Disabled while
+    /// replaying a movie so that the replayed run does not overwrite the player's save.
+    pub fn set_persistent(&mut self, persistent: bool) {
+        self.persistent = persistent;
+    }
+
+    /// Notifies the persister, if persisting, that the save data has changed.
+    fn persist(&mut self) {
+        if let (Some(persister), true) = (&mut self.persister, self.persistent) {
+            persister.write_data(self.mbc.rtc_footer());
+        }
+    }
+
+    /// Restarts the cartridge RTC, if there is one, at the given Unix timestamp.
+    pub fn seed_rtc(&mut self, seed: i64) {
+        self.mbc.seed_rtc(seed);
+    }
+
+    /// Returns the cartridge RTC, if it was seeded, to the clock it was constructed with.
+    pub fn restore_rtc(&mut self) {
+        self.mbc.restore_rtc();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CartridgeSnapshot {
+    mbc: MBCSnapshot,
+    ram: Vec<u8>,
+}
+
+impl Snapshot for Cartridge {
+    type State = CartridgeSnapshot;
+
+    fn save_state(&self) -> CartridgeSnapshot {
+        CartridgeSnapshot {
+            mbc: self.mbc.save_state(),
+            ram: self.ram.read().unwrap().clone(),
+        }
+    }
+
+    fn load_state(&mut self, state: CartridgeSnapshot) {
+        let rumbling = self.mbc.rumble();
+        self.mbc.load_state(state.mbc);
+        self.set_ram(&state.ram);
+
+        if self.mbc.rumble() != rumbling {
+            self.rumble.set_rumble(self.mbc.rumble());
+        }
+    }
 }
 
 impl Register for Cartridge {
@@ -113,22 +272,33 @@ impl Register for Cartridge {
     }
 
     fn write(&mut self, address: u16, value: u8) {
+        let rumbling = self.mbc.rumble();
         self.mbc.handle_control_write(address, value);
 
-        if let Some((physical_address, BankType::RAM)) = self.mbc.translate_address(address) {
-            let ram_size = self.ram.read().unwrap().len();
+        if self.mbc.rumble() != rumbling {
+            self.rumble.set_rumble(self.mbc.rumble());
+        }
 
-            if ram_size == 0 {
-                return;
-            }
+        match self.mbc.translate_address(address) {
+            Some((physical_address, BankType::RAM)) => {
+                let ram_size = self.ram.read().unwrap().len();
 
-            self.ram.write().unwrap()[physical_address as usize % ram_size] = value;
+                if ram_size == 0 {
+                    return;
+                }
 
-            if let Some(persister) = &mut self.persister {
-                persister.write_data();
+                self.ram.write().unwrap()[physical_address as usize % ram_size] = value;
+                self.persist();
             }
+            // Setting the clock must be persisted too, even if RAM is never written afterwards
+            Some((_, BankType::RTC(_))) => self.persist(),
+            _ => {}
         }
     }
+
+    fn tick(&mut self, cycles: u32) {
+        self.mbc.tick(cycles);
+    }
 }
 
 const DEBOUNCE_TIME_SECS: u64 = 3;
@@ -141,55 +311,78 @@ pub struct Persister {
 
 #[derive(Debug)]
 enum PersistMessage {
-    WriteNotify,
+    WriteNotify(Option<[u8; RTC_FOOTER_SIZE]>),
     Shutdown,
 }
 
-impl Persister {
-    pub fn new(path: PathBuf, ram: Arc<RwLock<Vec<u8>>>) -> Self {
-        let (tx, rx) = crossbeam::channel::bounded(16);
+/// Loads existing save data into cartridge RAM. For cartridges with a real time clock, returns the
+/// clock footer stored after the RAM, if the save file has one.
+fn load_save_data(path: &Path, ram: &Arc<RwLock<Vec<u8>>>, has_rtc: bool) -> Option<Vec<u8>> {
+    let mut existing_save_data = fs::read(path).unwrap_or_default();
+    if existing_save_data.is_empty() {
+        return None;
+    }
 
-        let existing_save_data = fs::read(&path).unwrap_or_default();
+    let mut ram = ram.write().unwrap();
+    let footer_size = existing_save_data.len().saturating_sub(ram.len());
+    let footer = match footer_size {
+        RTC_FOOTER_SIZE | RTC_LEGACY_FOOTER_SIZE if has_rtc => {
+            Some(existing_save_data.split_off(ram.len()))
+        }
+        _ => None,
+    };
 
-        if !existing_save_data.is_empty() {
-            let mut ram = ram.write().unwrap();
+    ram.resize(existing_save_data.len(), 0);
+    ram.copy_from_slice(&existing_save_data);
 
-            ram.resize(existing_save_data.len(), 0);
-            ram.copy_from_slice(&existing_save_data);
+    info!("Loaded existing game save from {:?}", path);
 
-            info!("Loaded existing game save from {:?}", &path);
-        }
+    footer
+}
+
+impl Persister {
+    pub fn new(path: PathBuf, ram: Arc<RwLock<Vec<u8>>>) -> Self {
+        let (tx, rx) = crossbeam::channel::bounded(16);
 
         let thread_handle = std::thread::spawn(move || {
             let ram = ram.clone();
+            let save = |footer: &Option<[u8; RTC_FOOTER_SIZE]>| {
+                let mut data = ram.read().unwrap().clone();
+                if let Some(footer) = footer {
+                    data.extend_from_slice(footer);
+                }
+
+                info!(
+                    "Saving game data to {:?} following cartridge RAM write.",
+                    &path
+                );
+                fs::write(&path, data).expect("Unable to write to file.");
+            };
 
             'running: loop {
                 match rx.recv() {
-                    Ok(PersistMessage::WriteNotify) => {
+                    Ok(PersistMessage::WriteNotify(mut footer)) => {
                         let mut debounce_time =
                             Instant::now() + Duration::from_secs(DEBOUNCE_TIME_SECS);
 
                         // Spin until debounce time has passed with no more requests
                         while Instant::now() < debounce_time {
                             match rx.try_recv() {
-                                Ok(PersistMessage::WriteNotify) => {
+                                Ok(PersistMessage::WriteNotify(latest_footer)) => {
+                                    footer = latest_footer;
                                     debounce_time =
                                         Instant::now() + Duration::from_secs(DEBOUNCE_TIME_SECS);
                                 }
                                 Ok(PersistMessage::Shutdown) => {
+                                    // Flush the pending write rather than losing it
+                                    save(&footer);
                                     break 'running;
                                 }
                                 Err(_) => {}
                             }
                         }
 
-                        let ram = ram.read().unwrap().clone();
-
-                        info!(
-                            "Saving game data to {:?} following cartridge RAM write.",
-                            &path
-                        );
-                        fs::write(&path, ram).expect("Unable to write to file.");
+                        save(&footer);
                     }
                     Ok(PersistMessage::Shutdown) => break 'running,
                     Err(_) => continue,
@@ -203,8 +396,9 @@ impl Persister {
         }
     }
 
-    pub fn write_data(&mut self) {
-        if let Err(e) = self.tx.try_send(PersistMessage::WriteNotify) {
+    /// Schedules a write of cartridge RAM, followed by the given RTC footer if there is one.
+    pub fn write_data(&mut self, rtc_footer: Option<[u8; RTC_FOOTER_SIZE]>) {
+        if let Err(e) = self.tx.try_send(PersistMessage::WriteNotify(rtc_footer)) {
             error!("Unable to save data to file. {:?}", e);
         }
     }
@@ -0,0 +1,324 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use anyhow::{bail, Context};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{MemoryBus, MemoryRegion, Model};
+
+/// A Game Genie or GameShark code.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum CheatCode {
+    /// Patches reads of cartridge ROM, written as `VVA-AAA` or `VVA-AAA-CCC`. With a compare
+    /// value, only reads of that value are patched, which picks out the bank the code was made
+    /// for among those that can be mapped at the address. A bank can be given too, written as a
+    /// prefix such as `05:`, which limits the patch to when that bank is mapped.
+    GameGenie {
+        bank: Option<u16>,
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+        /// The middle digit of the compare group, which the Game Genie ignores. Kept so the code
+        /// is written back as it was entered.
+        checksum: u8,
+    },
+    /// Writes RAM once a frame, written as `BBVVAAAA` with the address low byte first. The bank
+    /// selects the cartridge RAM bank written, if the cartridge has it, and banks from 0x80 select
+    /// a WRAM bank in CGB mode, from their lowest bits.
+    GameShark { bank: u8, value: u8, address: u16 },
+}
+
+impl fmt::Display for CheatCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CheatCode::GameGenie {
+                bank,
+                address,
+                value,
+                compare,
+                checksum,
+            } => {
+                if let Some(bank) = bank {
+                    write!(f, "{:02X}:", bank)?;
+                }
+                write!(
+                    f,
+                    "{:02X}{:X}-{:X}{:X}{:X}",
+                    value,
+                    (address >> 8) & 0x0F,
+                    (address >> 4) & 0x0F,
+                    address & 0x0F,
+                    (address >> 12) ^ 0x0F
+                )?;
+                if let Some(compare) = compare {
+                    let encoded = (compare ^ 0xBA).rotate_left(2);
+                    write!(f, "-{:X}{:X}{:X}", encoded >> 4, checksum, encoded & 0x0F)?;
+                }
+                Ok(())
+            }
+            CheatCode::GameShark {
+                bank,
+                value,
+                address,
+            } => write!(
+                f,
+                "{:02X}{:02X}{:02X}{:02X}",
+                bank,
+                value,
+                address & 0xFF,
+                address >> 8
+            ),
+        }
+    }
+}
+
+impl FromStr for CheatCode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (bank, code) = match s.split_once(':') {
+            Some((bank, code)) => {
+                let bank = u16::from_str_radix(bank, 16)
+                    .with_context(|| format!("Invalid bank {:?}.", bank))?;
+                (Some(bank), code)
+            }
+            None => (None, s),
+        };
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<_>>>()
+            .with_context(|| format!("Invalid cheat code {:?}.", s))?;
+        let byte = |index: usize| (digits[index] << 4) | digits[index + 1];
+
+        match digits.len() {
+            6 | 9 => {
+                let address = (((digits[5] ^ 0x0F) as u16) << 12)
+                    | ((digits[2] as u16) << 8)
+                    | ((digits[3] as u16) << 4)
+                    | digits[4] as u16;
+                if address > 0x7FFF {
+                    bail!("Game Genie code {:?} doesn't patch cartridge ROM.", s);
+                }
+                // The compare value is rotated and scrambled
+                let compare = (digits.len() == 9)
+                    .then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+
+                Ok(CheatCode::GameGenie {
+                    bank,
+                    address,
+                    value: byte(0),
+                    compare,
+                    checksum: digits.get(7).copied().unwrap_or(0),
+                })
+            }
+            8 if bank.is_none() => {
+                let address = u16::from_le_bytes([byte(4), byte(6)]);
+                if address < 0x8000 {
+                    bail!("GameShark code {:?} doesn't write RAM.", s);
+                }
+
+                Ok(CheatCode::GameShark {
+                    bank: byte(0),
+                    value: byte(2),
+                    address,
+                })
+            }
+            _ => bail!("{:?} isn't a Game Genie or GameShark code.", s),
+        }
+    }
+}
+
+impl TryFrom<String> for CheatCode {
+    type Error = anyhow::Error;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        code.parse()
+    }
+}
+
+impl From<CheatCode> for String {
+    fn from(code: CheatCode) -> Self {
+        code.to_string()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Cheat {
+    pub code: CheatCode,
+    pub enabled: bool,
+}
+
+/// The cheats for the loaded ROM, in the order they were added.
+#[derive(Debug, Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    /// Whether any enabled cheat patches ROM, so reads can skip checking when none do.
+    patches_rom: bool,
+}
+
+impl Cheats {
+    /// Loads cheats from a file written by write_file.
+    pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let json =
+            fs::read_to_string(path).with_context(|| format!("Unable to read {:?}.", path))?;
+        let cheats = serde_json::from_str(&json)
+            .with_context(|| format!("Invalid cheats file {:?}.", path))?;
+
+        let mut cheats = Cheats {
+            cheats,
+            patches_rom: false,
+        };
+        cheats.update();
+
+        Ok(cheats)
+    }
+
+    /// Writes the cheats to a file as JSON, with each code as it is written.
+    pub fn write_file(&self, path: &Path) -> Result<(), anyhow::Error> {
+        fs::write(path, serde_json::to_string_pretty(&self.cheats)?)
+            .with_context(|| format!("Unable to write {:?}.", path))
+    }
+
+    /// Enables exactly the given codes, such as those a movie was recorded with.
+    pub fn from_codes(codes: &[CheatCode]) -> Self {
+        let mut cheats = Cheats::default();
+        for code in codes {
+            cheats.add(*code);
+        }
+
+        cheats
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// The codes that are currently applied.
+    pub fn enabled_codes(&self) -> Vec<CheatCode> {
+        self.enabled().collect()
+    }
+
+    /// Adds an enabled cheat. Adding a code that is already present enables it.
+    pub fn add(&mut self, code: CheatCode) {
+        match self.cheats.iter_mut().find(|cheat| cheat.code == code) {
+            Some(cheat) => cheat.enabled = true,
+            None => self.cheats.push(Cheat {
+                code,
+                enabled: true,
+            }),
+        }
+        self.update();
+    }
+
+    pub fn remove(&mut self, code: CheatCode) {
+        self.cheats.retain(|cheat| cheat.code != code);
+        self.update();
+    }
+
+    pub fn set_enabled(&mut self, code: CheatCode, enabled: bool) {
+        for cheat in self.cheats.iter_mut().filter(|cheat| cheat.code == code) {
+            cheat.enabled = enabled;
+        }
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let patches_rom = self
+            .enabled()
+            .any(|code| matches!(code, CheatCode::GameGenie { .. }));
+        self.patches_rom = patches_rom;
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = CheatCode> + '_ {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .map(|cheat| cheat.code)
+    }
+
+    /// Applies Game Genie codes to a byte read from cartridge ROM. The bank mapped at the address
+    /// is only looked up for codes that are limited to a bank.
+    pub fn patch_rom(&self, address: u16, value: u8, mapped_bank: impl Fn() -> Option<u16>) -> u8 {
+        if !self.patches_rom {
+            return value;
+        }
+
+        for code in self.enabled() {
+            if let CheatCode::GameGenie {
+                bank,
+                address: patched_address,
+                value: patched_value,
+                compare,
+                ..
+            } = code
+            {
+                if patched_address == address
+                    && compare.is_none_or(|compare| compare == value)
+                    && bank.is_none_or(|bank| mapped_bank() == Some(bank))
+                {
+                    return patched_value;
+                }
+            }
+        }
+
+        value
+    }
+}
+
+impl MemoryBus {
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    /// Writes the values of enabled GameShark codes, as the GameShark does once a frame.
+    pub fn apply_game_shark(&mut self) {
+        let writes: Vec<_> = self
+            .cheats
+            .enabled()
+            .filter_map(|code| match code {
+                CheatCode::GameShark {
+                    bank,
+                    value,
+                    address,
+                } => Some((bank, value, address)),
+                CheatCode::GameGenie { .. } => None,
+            })
+            .collect();
+
+        for (bank, value, address) in writes {
+            let (region, offset) = match (self.model, bank, address) {
+                (Model::CGB, 0x80.., 0xD000..=0xDFFF) => (
+                    MemoryRegion::Wram((bank & 0x07).max(1)),
+                    (address - 0xD000) as usize,
+                ),
+                (_, _, 0xA000..=0xBFFF)
+                    if self.region_size(MemoryRegion::CartridgeRam(bank)).is_some() =>
+                {
+                    (
+                        MemoryRegion::CartridgeRam(bank),
+                        (address - 0xA000) as usize,
+                    )
+                }
+                _ => (MemoryRegion::Cpu, address as usize),
+            };
+
+            // Rewriting a value that is already set would persist cartridge RAM every frame
+            let changed = self
+                .read_memory(region, offset, 1)
+                .is_ok_and(|current| current[0] != value);
+            if changed {
+                if let Err(e) = self.write_memory(region, offset, &[value]) {
+                    warn!("Unable to apply GameShark code. {:?}", e);
+                }
+            }
+        }
+    }
+}
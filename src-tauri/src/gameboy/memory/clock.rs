@@ -0,0 +1,149 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::gameboy::GlobalConstants;
+
+/// A Clock is the source of the current time for a cartridge real time clock.
+pub trait Clock: Debug + Send + Sync {
+    /// The current time.
+    fn now(&self) -> NaiveDateTime;
+
+    /// Advances the clock by a number of emulated t-cycles. Clocks that don't follow emulated time
+    /// ignore this.
+    fn tick(&mut self, _cycles: u32) {}
+
+    /// Captures the state of this clock.
+    fn save_state(&self) -> ClockSnapshot;
+
+    /// Restores a previously captured state. Clocks that are driven externally, such as the wall
+    /// clock, ignore this.
+    fn load_state(&mut self, _state: ClockSnapshot) {}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClockSnapshot {
+    Wall,
+    Emulated { seed: i64, cycles: u64 },
+    Fixed(NaiveDateTime),
+    Manual(NaiveDateTime),
+}
+
+/// Follows the host's wall-clock time, like the battery-powered clock on a real cartridge.
+#[derive(Debug, Default)]
+pub struct WallClock;
+
+impl Clock for WallClock {
+    fn now(&self) -> NaiveDateTime {
+        Utc::now().naive_utc()
+    }
+
+    fn save_state(&self) -> ClockSnapshot {
+        ClockSnapshot::Wall
+    }
+}
+
+/// Starts at a fixed Unix timestamp and advances with emulated cycles. Time passes faster when
+/// fast-forwarding, stops while paused, and is captured in save states, so the same inputs always
+/// produce the same in-game time.
+#[derive(Debug)]
+pub struct EmulatedClock {
+    seed: i64,
+    cycles: u64,
+}
+
+impl EmulatedClock {
+    pub fn new(seed: i64) -> Self {
+        EmulatedClock { seed, cycles: 0 }
+    }
+
+    /// Constructs an emulated clock that starts at the current wall-clock time.
+    pub fn starting_now() -> Self {
+        Self::new(Utc::now().timestamp())
+    }
+}
+
+impl Clock for EmulatedClock {
+    fn now(&self) -> NaiveDateTime {
+        let secs = self.seed + (self.cycles / GlobalConstants::SYSTEM_CLOCK_RATE as u64) as i64;
+        DateTime::from_timestamp(secs, 0)
+            .unwrap_or_default()
+            .naive_utc()
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+
+    fn save_state(&self) -> ClockSnapshot {
+        ClockSnapshot::Emulated {
+            seed: self.seed,
+            cycles: self.cycles,
+        }
+    }
+
+    fn load_state(&mut self, state: ClockSnapshot) {
+        match state {
+            ClockSnapshot::Emulated { seed, cycles } => {
+                self.seed = seed;
+                self.cycles = cycles;
+            }
+            other => warn!("Ignoring incompatible clock state {:?}.", other),
+        }
+    }
+}
+
+/// Always reports the same time.
+#[derive(Debug)]
+pub struct FixedClock(NaiveDateTime);
+
+impl FixedClock {
+    pub fn new(time: NaiveDateTime) -> Self {
+        FixedClock(time)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+
+    fn save_state(&self) -> ClockSnapshot {
+        ClockSnapshot::Fixed(self.0)
+    }
+}
+
+/// Reports a time that is only changed by hand. Clones share the same time, so the host can keep
+/// a clone to adjust the in-game time while the emulator runs.
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<RwLock<NaiveDateTime>>);
+
+impl ManualClock {
+    pub fn new(time: NaiveDateTime) -> Self {
+        ManualClock(Arc::new(RwLock::new(time)))
+    }
+
+    pub fn set(&self, time: NaiveDateTime) {
+        *self.0.write().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.0.write().unwrap();
+        *time = time.checked_add_signed(duration).unwrap_or(*time);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.0.read().unwrap()
+    }
+
+    fn save_state(&self) -> ClockSnapshot {
+        ClockSnapshot::Manual(self.now())
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use super::{MemoryBus, Model};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const CARTRIDGE_RAM_BANK_SIZE: usize = 0x2000;
+const WRAM_BANK_SIZE: usize = 0x1000;
+
+/// HRAM spans 0xFF80..=0xFFFE, at the end of internal memory.
+const HRAM_OFFSET: usize = 0x3F80;
+const HRAM_SIZE: usize = 0x7F;
+
+/// Memory that can be read and written by the memory editor. Offsets into the CPU's address space
+/// are addresses, while offsets into a bank start from 0 at the start of the bank.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum MemoryRegion {
+    /// The 64KiB address space as the CPU sees it, with the banks that are currently mapped.
+    Cpu,
+    Rom(u16),
+    CartridgeRam(u8),
+    /// Only bank 0 exists outside CGB mode.
+    Vram(u8),
+    /// Banks 0 and 1, or 0 to 7 in CGB mode.
+    Wram(u8),
+    Hram,
+}
+
+impl MemoryBus {
+    /// Reads `length` bytes of a region from an offset. Reading has no side effects, and unlike
+    /// reads by the CPU, nothing is hidden while OAM DMA is running. Fails if the bank doesn't
+    /// exist or the range runs past its end.
+    pub fn read_memory(
+        &self,
+        region: MemoryRegion,
+        start: usize,
+        length: usize,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.check_range(region, start, length)?;
+
+        Ok((start..start + length)
+            .map(|offset| self.peek(region, offset))
+            .collect())
+    }
+
+    /// Writes bytes to a region from an offset. In the CPU's address space, writes to registers
+    /// take effect as they would from the CPU, but writes to cartridge ROM patch the bank that is
+    /// mapped there rather than controlling the MBC.
+    pub fn write_memory(
+        &mut self,
+        region: MemoryRegion,
+        start: usize,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        self.check_range(region, start, data.len())?;
+
+        for (offset, value) in (start..).zip(data) {
+            self.poke(region, offset, *value)?;
+        }
+        Ok(())
+    }
+
+    /// The size of a region in bytes, or None if the bank doesn't exist.
+    pub(super) fn region_size(&self, region: MemoryRegion) -> Option<usize> {
+        let bank_size = |bank: usize, bank_size: usize, total_size: usize| {
+            let start = bank * bank_size;
+            (start < total_size).then(|| (total_size - start).min(bank_size))
+        };
+
+        match region {
+            MemoryRegion::Cpu => Some(0x10000),
+            MemoryRegion::Rom(bank) => {
+                bank_size(bank as usize, ROM_BANK_SIZE, self.cartridge.rom().len())
+            }
+            MemoryRegion::CartridgeRam(bank) => bank_size(
+                bank as usize,
+                CARTRIDGE_RAM_BANK_SIZE,
+                self.cartridge.ram_size(),
+            ),
+            MemoryRegion::Vram(bank) => self.ppu.vram_bank(bank).map(<[u8]>::len),
+            MemoryRegion::Wram(bank) => (bank < self.wram_bank_count()).then_some(WRAM_BANK_SIZE),
+            MemoryRegion::Hram => Some(HRAM_SIZE),
+        }
+    }
+
+    fn check_range(&self, region: MemoryRegion, start: usize, length: usize) -> anyhow::Result<()> {
+        let Some(size) = self.region_size(region) else {
+            bail!("{:?} doesn't exist.", region);
+        };
+        if start.checked_add(length).is_none_or(|end| end > size) {
+            bail!(
+                "{:#X} bytes from {:#X} runs past the end of {:?}, which is {:#X} bytes.",
+                length,
+                start,
+                region,
+                size
+            );
+        }
+
+        Ok(())
+    }
+
+    fn wram_bank_count(&self) -> u8 {
+        match self.model {
+            Model::CGB => 8,
+            _ => 2,
+        }
+    }
+
+    /// Reads a byte of a region, at an offset already checked to be inside it.
+    fn peek(&self, region: MemoryRegion, offset: usize) -> u8 {
+        match region {
+            MemoryRegion::Cpu => self.raw_read(offset as u16),
+            MemoryRegion::Rom(bank) => self.cartridge.rom()[bank as usize * ROM_BANK_SIZE + offset],
+            MemoryRegion::CartridgeRam(bank) => self
+                .cartridge
+                .read_ram(bank as usize * CARTRIDGE_RAM_BANK_SIZE + offset),
+            MemoryRegion::Vram(bank) => self.ppu.vram_bank(bank).unwrap()[offset],
+            // Bank 1 lives in internal memory after bank 0, and the rest in their own array
+            MemoryRegion::Wram(bank @ 0..=1) => {
+                self.internal_memory[bank as usize * WRAM_BANK_SIZE + offset]
+            }
+            MemoryRegion::Wram(bank) => {
+                self.wram_banks[(bank as usize - 2) * WRAM_BANK_SIZE + offset]
+            }
+            MemoryRegion::Hram => self.internal_memory[HRAM_OFFSET + offset],
+        }
+    }
+
+    /// Writes a byte of a region, at an offset already checked to be inside it.
+    fn poke(&mut self, region: MemoryRegion, offset: usize, value: u8) -> anyhow::Result<()> {
+        match region {
+            MemoryRegion::Cpu if offset < 0x8000 => {
+                let address = offset as u16;
+                let Some(bank) = self.rom_bank(address) else {
+                    bail!("The boot ROM is mapped at {:#06X}.", address);
+                };
+                let offset = bank as usize * ROM_BANK_SIZE + offset % ROM_BANK_SIZE;
+                self.cartridge.write_rom(offset, value);
+            }
+            MemoryRegion::Cpu => self.raw_write(offset as u16, value),
+            MemoryRegion::Rom(bank) => self
+                .cartridge
+                .write_rom(bank as usize * ROM_BANK_SIZE + offset, value),
+            MemoryRegion::CartridgeRam(bank) => self
+                .cartridge
+                .write_ram(bank as usize * CARTRIDGE_RAM_BANK_SIZE + offset, value),
+            MemoryRegion::Vram(bank) => self.ppu.vram_bank_mut(bank).unwrap()[offset] = value,
+            MemoryRegion::Wram(bank @ 0..=1) => {
+                self.internal_memory[bank as usize * WRAM_BANK_SIZE + offset] = value
+            }
+            MemoryRegion::Wram(bank) => {
+                self.wram_banks[(bank as usize - 2) * WRAM_BANK_SIZE + offset] = value
+            }
+            MemoryRegion::Hram => self.internal_memory[HRAM_OFFSET + offset] = value,
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+/// The number of bytes copied per block of a VRAM DMA transfer.
+pub const HDMA_BLOCK_SIZE: u16 = 0x10;
+
+/// The number of t-cycles at normal speed the CPU is halted for while a block is copied.
+const BLOCK_CYCLES: u32 = 32;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+enum HDMAMode {
+    Inactive,
+    /// Copies one block at the start of every HBlank.
+    HBlank,
+}
+
+/// The CGB VRAM DMA registers, HDMA1-HDMA5 (0xFF51..=0xFF55). A general purpose transfer copies
+/// everything at once while the CPU is halted. An HBlank transfer copies one block per HBlank,
+/// halting the CPU only while each block is copied.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HDMA {
+    source: u16,
+    destination: u16,
+    /// The number of blocks left to copy, minus one, as reported through HDMA5
+    remaining: u8,
+    mode: HDMAMode,
+    stall_cycles: u32,
+}
+
+impl HDMA {
+    pub fn new() -> Self {
+        HDMA {
+            source: 0,
+            destination: 0,
+            remaining: 0x7F,
+            mode: HDMAMode::Inactive,
+            stall_cycles: 0,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            // Bit 7 is clear while an HBlank transfer is active. Once a transfer completes this
+            // reads 0xFF, and after cancelling it reports the blocks that were left.
+            0xFF55 => match self.mode {
+                HDMAMode::HBlank => self.remaining & 0x7F,
+                HDMAMode::Inactive => 0x80 | self.remaining,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes to an HDMA register. Returns the number of blocks to copy immediately when a
+    /// general purpose transfer is started.
+    pub fn write(&mut self, address: u16, value: u8) -> u8 {
+        match address {
+            0xFF51 => self.source = (self.source & 0x00FF) | ((value as u16) << 8),
+            0xFF52 => self.source = (self.source & 0xFF00) | ((value & 0xF0) as u16),
+            0xFF53 => {
+                self.destination = (self.destination & 0x00FF) | (((value & 0x1F) as u16) << 8)
+            }
+            0xFF54 => self.destination = (self.destination & 0xFF00) | ((value & 0xF0) as u16),
+            0xFF55 => match (self.mode, value & 0x80 != 0) {
+                (HDMAMode::HBlank, false) => {
+                    trace!("Cancelled HBlank DMA with {} blocks left.", self.remaining);
+                    self.mode = HDMAMode::Inactive;
+                }
+                (_, true) => {
+                    self.remaining = value & 0x7F;
+                    trace!(
+                        "Started HBlank DMA of {} blocks from {:#06x} to {:#06x}.",
+                        self.remaining as u16 + 1,
+                        self.source,
+                        0x8000 | self.destination
+                    );
+                    self.mode = HDMAMode::HBlank;
+                }
+                (HDMAMode::Inactive, false) => {
+                    self.remaining = value & 0x7F;
+                    trace!(
+                        "Started general purpose DMA of {} blocks from {:#06x} to {:#06x}.",
+                        self.remaining as u16 + 1,
+                        self.source,
+                        0x8000 | self.destination
+                    );
+                    return self.remaining + 1;
+                }
+            },
+            _ => {}
+        }
+
+        0
+    }
+
+    /// Whether an HBlank transfer is in progress, in which case a block is copied every HBlank.
+    pub fn hblank_active(&self) -> bool {
+        self.mode == HDMAMode::HBlank
+    }
+
+    /// Advances past a copied block, returning the source and destination addresses it was
+    /// copied from and to. `speed_factor` is two in double speed mode, where the CPU is halted
+    /// for twice as many of its own cycles.
+    pub fn next_block(&mut self, speed_factor: u32) -> (u16, u16) {
+        let block = (self.source, 0x8000 | self.destination);
+
+        self.source = self.source.wrapping_add(HDMA_BLOCK_SIZE);
+        self.destination = (self.destination + HDMA_BLOCK_SIZE) & 0x1FF0;
+        self.stall_cycles += BLOCK_CYCLES * speed_factor;
+
+        self.remaining = self.remaining.wrapping_sub(1) & 0x7F;
+        if self.remaining == 0x7F {
+            // The final block has been copied
+            self.mode = HDMAMode::Inactive;
+        }
+
+        block
+    }
+
+    /// Whether the CPU is halted while a block is copied.
+    pub fn stalled(&self) -> bool {
+        self.stall_cycles > 0
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.stall_cycles = self.stall_cycles.saturating_sub(cycles);
+    }
+}
+
+impl Default for HDMA {
+    fn default() -> Self {
+        Self::new()
+    }
+}
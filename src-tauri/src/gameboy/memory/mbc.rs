@@ -1,9 +1,52 @@
 use std::fmt::Debug;
 
+use serde::{Deserialize, Serialize};
+
 #[allow(clippy::upper_case_acronyms)]
 pub trait MBC: Debug + Send + Sync {
     fn translate_address(&self, address: u16) -> Option<(u32, BankType)>;
     fn handle_control_write(&mut self, address: u16, value: u8);
+
+    /// Advances any timekeeping hardware on the cartridge by the given number of t-cycles.
+    fn tick(&mut self, _cycles: u32) {}
+
+    /// Restarts any real time clock on the cartridge at the given Unix timestamp, after which it
+    /// advances with emulated cycles rather than wall-clock time. The clock it replaces is kept
+    /// until restore_rtc is called.
+    fn seed_rtc(&mut self, _seed: i64) {}
+
+    /// Returns any real time clock seeded with seed_rtc to the clock it replaced.
+    fn restore_rtc(&mut self) {}
+
+    /// Encodes any real time clock on the cartridge as a save file footer.
+    fn rtc_footer(&self) -> Option<[u8; RTC_FOOTER_SIZE]> {
+        None
+    }
+
+    /// Restores any real time clock on the cartridge from a save file footer.
+    fn load_rtc_footer(&mut self, _footer: &[u8]) {}
+
+    /// Whether the rumble motor on the cartridge is currently running.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// Captures the bank registers and any other internal state of this MBC.
+    fn save_state(&self) -> MBCSnapshot;
+
+    /// Restores a previously captured state. States captured from a different MBC type are
+    /// ignored.
+    fn load_state(&mut self, state: MBCSnapshot);
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MBCSnapshot {
+    NoMBC,
+    MBC1(MBC1),
+    MBC2(MBC2),
+    MBC3(MBC3Snapshot),
+    MBC5(MBC5),
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -14,9 +57,9 @@ pub enum BankType {
     RTC(u8),
 }
 
-use log::trace;
+use log::{trace, warn};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MBC1 {
     bank_1_register: u8,
     bank_2_register: u8,
@@ -24,7 +67,7 @@ pub struct MBC1 {
     banking_mode: BankingMode,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum BankingMode {
     Simple,
     Advanced,
@@ -115,9 +158,20 @@ impl MBC for MBC1 {
             _ => {}
         }
     }
+
+    fn save_state(&self) -> MBCSnapshot {
+        MBCSnapshot::MBC1(self.clone())
+    }
+
+    fn load_state(&mut self, state: MBCSnapshot) {
+        match state {
+            MBCSnapshot::MBC1(mbc) => *self = mbc,
+            other => warn!("Ignoring incompatible MBC state {:?} for MBC1.", other),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct MBC2 {
     ram_enabled: bool,
     rom_bank: u8,
@@ -173,35 +227,59 @@ impl MBC for MBC2 {
             }
         }
     }
+
+    fn save_state(&self) -> MBCSnapshot {
+        MBCSnapshot::MBC2(self.clone())
+    }
+
+    fn load_state(&mut self, state: MBCSnapshot) {
+        match state {
+            MBCSnapshot::MBC2(mbc) => *self = mbc,
+            other => warn!("Ignoring incompatible MBC state {:?} for MBC2.", other),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug)]
 pub struct MBC3 {
     rom_bank: u8,
     ram_rtc_bank: u8,
     ram_enabled: bool,
     latch_pending: bool,
     rtc: RTC,
+    /// The RTC replaced by seed_rtc, if it has been seeded.
+    unseeded_rtc: Option<RTC>,
 }
 
 impl MBC3 {
-    pub fn new() -> Self {
+    /// Constructs an MBC3 whose real time clock reads the time from the provided clock.
+    pub fn new(clock: Box<dyn Clock>) -> Self {
         MBC3 {
             rom_bank: 1,
             ram_rtc_bank: 0,
             ram_enabled: false,
             latch_pending: false,
-            rtc: RTC::new(),
+            rtc: RTC::new(clock),
+            unseeded_rtc: None,
         }
     }
 }
 
 impl Default for MBC3 {
     fn default() -> Self {
-        Self::new()
+        Self::new(Box::new(WallClock))
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MBC3Snapshot {
+    rom_bank: u8,
+    ram_rtc_bank: u8,
+    ram_enabled: bool,
+    latch_pending: bool,
+    rtc: RTCSnapshot,
+}
+
 impl MBC for MBC3 {
     fn translate_address(&self, address: u16) -> Option<(u32, BankType)> {
         match address {
@@ -265,6 +343,136 @@ impl MBC for MBC3 {
             _ => {}
         }
     }
+
+    fn tick(&mut self, cycles: u32) {
+        self.rtc.tick(cycles);
+    }
+
+    fn seed_rtc(&mut self, seed: i64) {
+        let rtc = std::mem::replace(&mut self.rtc, RTC::new(Box::new(EmulatedClock::new(seed))));
+        // Seeding again keeps the clock from before the first seed
+        self.unseeded_rtc.get_or_insert(rtc);
+    }
+
+    fn restore_rtc(&mut self) {
+        if let Some(rtc) = self.unseeded_rtc.take() {
+            self.rtc = rtc;
+        }
+    }
+
+    fn rtc_footer(&self) -> Option<[u8; RTC_FOOTER_SIZE]> {
+        Some(self.rtc.to_footer())
+    }
+
+    fn load_rtc_footer(&mut self, footer: &[u8]) {
+        self.rtc.load_footer(footer);
+    }
+
+    fn save_state(&self) -> MBCSnapshot {
+        MBCSnapshot::MBC3(MBC3Snapshot {
+            rom_bank: self.rom_bank,
+            ram_rtc_bank: self.ram_rtc_bank,
+            ram_enabled: self.ram_enabled,
+            latch_pending: self.latch_pending,
+            rtc: self.rtc.save_state(),
+        })
+    }
+
+    fn load_state(&mut self, state: MBCSnapshot) {
+        match state {
+            MBCSnapshot::MBC3(state) => {
+                self.rom_bank = state.rom_bank;
+                self.ram_rtc_bank = state.ram_rtc_bank;
+                self.ram_enabled = state.ram_enabled;
+                self.latch_pending = state.latch_pending;
+                self.rtc.load_state(state.rtc);
+            }
+            other => warn!("Ignoring incompatible MBC state {:?} for MBC3.", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MBC5 {
+    rom_bank: u16,
+    ram_bank: u8,
+    ram_enabled: bool,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl MBC5 {
+    /// Constructs an MBC5. On rumble cartridges, bit 3 of the RAM bank register drives the motor
+    /// rather than selecting a bank.
+    pub fn new(has_rumble: bool) -> Self {
+        MBC5 {
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            has_rumble,
+            rumble: false,
+        }
+    }
+}
+
+impl Default for MBC5 {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl MBC for MBC5 {
+    fn translate_address(&self, address: u16) -> Option<(u32, BankType)> {
+        match address {
+            0x0000..=0x3FFF => Some((address as u32, BankType::ROM)),
+            0x4000..=0x7FFF => {
+                // Unlike earlier MBCs, bank 0 can be mapped here
+                let bank_address = ((self.rom_bank as u32) * 0x4000) + ((address as u32) - 0x4000);
+                Some((bank_address, BankType::ROM))
+            }
+            0xA000..=0xBFFF if self.ram_enabled => {
+                let bank_address = ((self.ram_bank as u32) * 0x2000) + ((address as u32) - 0xA000);
+                Some((bank_address, BankType::RAM))
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_control_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            // The lower 8 bits of the 9-bit ROM bank number
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x0100) | value as u16,
+            // The 9th bit of the ROM bank number
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0x00FF) | (((value & 0x01) as u16) << 8)
+            }
+            0x4000..=0x5FFF if self.has_rumble => {
+                self.ram_bank = value & 0x07;
+                if self.rumble != (value & 0x08 != 0) {
+                    self.rumble = value & 0x08 != 0;
+                    trace!("Rumble motor {}.", if self.rumble { "on" } else { "off" });
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    fn save_state(&self) -> MBCSnapshot {
+        MBCSnapshot::MBC5(self.clone())
+    }
+
+    fn load_state(&mut self, state: MBCSnapshot) {
+        match state {
+            MBCSnapshot::MBC5(state) => *self = state,
+            other => warn!("Ignoring incompatible MBC state {:?} for MBC5.", other),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -292,12 +500,30 @@ impl MBC for NoMBC {
     }
 
     fn handle_control_write(&mut self, _address: u16, _value: u8) {}
+
+    fn save_state(&self) -> MBCSnapshot {
+        MBCSnapshot::NoMBC
+    }
+
+    fn load_state(&mut self, _state: MBCSnapshot) {}
 }
 
-use chrono::{Days, Duration, NaiveDateTime, Timelike, Utc};
+use chrono::{Days, Duration, NaiveDateTime, Timelike};
+
+use super::clock::{Clock, ClockSnapshot, EmulatedClock, WallClock};
+
+/// The size of the RTC footer appended to save files, in the format used by VBA and BGB. It holds
+/// the current and latched clock registers as little endian u32s, followed by the Unix timestamp
+/// at which it was written as a little endian u64.
+pub const RTC_FOOTER_SIZE: usize = 48;
+
+/// Older versions of the footer store the timestamp as a u32.
+pub const RTC_LEGACY_FOOTER_SIZE: usize = 44;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug)]
 pub struct RTC {
     real_time_offset_secs: i64,
     activation_date: NaiveDateTime,
@@ -305,23 +531,175 @@ pub struct RTC {
     halted_time: NaiveDateTime,
     pub halted: bool,
     pub day_carry: bool,
+    clock: Box<dyn Clock>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RTCSnapshot {
+    real_time_offset_secs: i64,
+    activation_date: NaiveDateTime,
+    latched_time: Option<NaiveDateTime>,
+    halted_time: NaiveDateTime,
+    halted: bool,
+    day_carry: bool,
+    clock: ClockSnapshot,
 }
 
 impl RTC {
-    pub fn new() -> Self {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
         RTC {
             real_time_offset_secs: 0,
-            activation_date: Utc::now().naive_utc(),
+            activation_date: clock.now(),
             latched_time: None,
-            halted_time: Utc::now().naive_utc(),
+            halted_time: clock.now(),
             halted: false,
             day_carry: false,
+            clock,
+        }
+    }
+
+    /// Advances the clock by the given number of t-cycles, if it follows emulated time.
+    pub fn tick(&mut self, cycles: u32) {
+        self.clock.tick(cycles);
+    }
+
+    fn now(&self) -> NaiveDateTime {
+        self.clock.now()
+    }
+
+    pub fn save_state(&self) -> RTCSnapshot {
+        RTCSnapshot {
+            real_time_offset_secs: self.real_time_offset_secs,
+            activation_date: self.activation_date,
+            latched_time: self.latched_time,
+            halted_time: self.halted_time,
+            halted: self.halted,
+            day_carry: self.day_carry,
+            clock: self.clock.save_state(),
+        }
+    }
+
+    pub fn load_state(&mut self, state: RTCSnapshot) {
+        self.real_time_offset_secs = state.real_time_offset_secs;
+        self.activation_date = state.activation_date;
+        self.latched_time = state.latched_time;
+        self.halted_time = state.halted_time;
+        self.halted = state.halted;
+        self.day_carry = state.day_carry;
+        self.clock.load_state(state.clock);
+    }
+
+    /// The current value of the clock counter, which stays frozen while halted.
+    fn counter(&self) -> NaiveDateTime {
+        let time = if self.halted {
+            self.halted_time
+        } else {
+            self.now()
+        };
+
+        time.checked_add_signed(Duration::seconds(self.real_time_offset_secs))
+            .unwrap_or(time)
+    }
+
+    /// The seconds, minutes, hours, day low and day high registers for a given counter value.
+    fn registers(&self, time: Option<NaiveDateTime>) -> [u32; 5] {
+        let Some(time) = time else {
+            return [0; 5];
+        };
+
+        let days = time
+            .signed_duration_since(self.activation_date)
+            .num_days()
+            .max(0) as u32;
+        let mut day_high = (days >> 8) & 0x01;
+        if self.halted {
+            day_high |= 0b0100_0000;
+        }
+        if self.day_carry || days >= 512 {
+            day_high |= 0b1000_0000;
+        }
+
+        [
+            time.second(),
+            time.minute(),
+            time.hour(),
+            days & 0xFF,
+            day_high,
+        ]
+    }
+
+    /// Encodes the clock registers in the VBA/BGB save file footer format.
+    pub fn to_footer(&self) -> [u8; RTC_FOOTER_SIZE] {
+        let mut footer = [0; RTC_FOOTER_SIZE];
+
+        let current = self.registers(Some(self.counter()));
+        let latched = self.registers(self.latched_time);
+        for (i, value) in current.iter().chain(latched.iter()).enumerate() {
+            footer[i * 4..(i + 1) * 4].copy_from_slice(&value.to_le_bytes());
         }
+
+        let timestamp = self.now().and_utc().timestamp();
+        footer[40..].copy_from_slice(&timestamp.to_le_bytes());
+
+        footer
+    }
+
+    /// Restores the clock registers from a VBA/BGB save file footer. Unless the clock was halted,
+    /// the time that has passed since the footer was written is added on, as the battery would
+    /// have kept the clock running while the game was off.
+    pub fn load_footer(&mut self, footer: &[u8]) {
+        let timestamp = match footer.len() {
+            RTC_FOOTER_SIZE => i64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            RTC_LEGACY_FOOTER_SIZE => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as i64,
+            other => {
+                warn!("Ignoring RTC footer with unexpected size {}.", other);
+                return;
+            }
+        };
+
+        let register =
+            |i: usize| u32::from_le_bytes(footer[i * 4..(i + 1) * 4].try_into().unwrap());
+        let total_secs = |first: usize| {
+            let days = ((register(first + 4) & 0x01) << 8) | (register(first + 3) & 0xFF);
+            days as i64 * SECONDS_PER_DAY
+                + register(first + 2) as i64 * 3600
+                + register(first + 1) as i64 * 60
+                + register(first) as i64
+        };
+
+        let day_high = register(4);
+        self.halted = day_high & 0b0100_0000 != 0;
+        self.day_carry = day_high & 0b1000_0000 != 0;
+
+        let now = self.now();
+        let elapsed = match self.halted {
+            true => 0,
+            false => (now.and_utc().timestamp() - timestamp).max(0),
+        };
+        let current_secs = total_secs(0) + elapsed;
+
+        // Offset the counter so its time of day matches the registers, then place the activation
+        // date at the midnight that many days before
+        self.real_time_offset_secs = current_secs.rem_euclid(SECONDS_PER_DAY)
+            - now.and_utc().timestamp().rem_euclid(SECONDS_PER_DAY);
+        self.halted_time = now;
+        self.activation_date = self
+            .counter()
+            .checked_sub_signed(Duration::seconds(current_secs))
+            .unwrap_or(self.activation_date);
+        self.latched_time = self
+            .activation_date
+            .checked_add_signed(Duration::seconds(total_secs(5)));
+
+        trace!(
+            "Restored RTC from save file with {} seconds elapsed since it was written.",
+            elapsed
+        );
     }
 
     /// A latch saves the current time for reading.
     pub fn latch(&mut self) {
-        let now = Utc::now().naive_utc();
+        let now = self.now();
         let offset = Duration::seconds(self.real_time_offset_secs);
         if let Some(latched) = now.checked_add_signed(offset) {
             self.latched_time = Some(latched);
@@ -394,14 +772,14 @@ impl RTC {
     /// of the halt and adding a real time offset to account for it.
     pub fn halt(&mut self) {
         self.halted = true;
-        self.halted_time = Utc::now().naive_utc();
+        self.halted_time = self.now();
     }
 
     /// Unhalts the RTC. Based on the duration since the halt began, a number of offset seconds are
     /// added so that time resumes at the same time it was halted.
     pub fn unhalt(&mut self) {
         self.halted = false;
-        let unhalt_time = Utc::now().naive_utc();
+        let unhalt_time = self.now();
         self.real_time_offset_secs -= unhalt_time
             .signed_duration_since(self.halted_time)
             .num_seconds();
@@ -409,27 +787,27 @@ impl RTC {
 
     /// Writes seconds to the RTC. This creates a +/- offset based on the current real time seconds.
     pub fn write_secs(&mut self, secs: u8) {
-        let current_secs = Utc::now().naive_utc().second() as i64;
+        let current_secs = self.now().second() as i64;
         self.real_time_offset_secs += (secs as i64) - current_secs;
     }
 
     /// Writes minutes to the RTC. This creates a +/- offset based on the current real time minutes.
     pub fn write_minutes(&mut self, minutes: u8) {
-        let current_minutes = Utc::now().naive_utc().minute() as i64;
+        let current_minutes = self.now().minute() as i64;
         self.real_time_offset_secs += ((minutes as i64) - current_minutes) * 60;
     }
 
     /// Writes hours to the RTC. This creates a +/- offset based on the current real time hours.
     pub fn write_hours(&mut self, hours: u8) {
-        let current_hours = Utc::now().naive_utc().hour() as i64;
+        let current_hours = self.now().hour() as i64;
         self.real_time_offset_secs += ((hours as i64) - current_hours) * 3600;
     }
 
     /// Writes to the day low value. If the day high bit is set, it will remain unchanged.
     pub fn write_day_low(&mut self, days: u8) {
         let current_days = {
-            let diff_days = Utc::now()
-                .naive_utc()
+            let diff_days = self
+                .now()
                 .signed_duration_since(self.activation_date)
                 .num_days();
             if diff_days < 0 {
@@ -441,8 +819,8 @@ impl RTC {
 
         let new_days = days as u16 | (0x0100 & current_days);
 
-        self.activation_date = Utc::now()
-            .naive_utc()
+        self.activation_date = self
+            .now()
             .checked_sub_days(Days::new(new_days as u64))
             .unwrap_or(self.activation_date);
     }
@@ -450,8 +828,8 @@ impl RTC {
     /// Writes to the day high value. Will trigger a halt if the halt bit is set.
     pub fn write_day_high(&mut self, dh: u8) {
         let current_days = {
-            let diff_days = Utc::now()
-                .naive_utc()
+            let diff_days = self
+                .now()
                 .signed_duration_since(self.activation_date)
                 .num_days();
             if diff_days < 0 {
@@ -464,15 +842,15 @@ impl RTC {
         if current_days & 0x0100 != 0 && dh & 0x01 == 0 {
             let new_days = current_days & 0x00FF;
 
-            self.activation_date = Utc::now()
-                .naive_utc()
+            self.activation_date = self
+                .now()
                 .checked_sub_days(Days::new(new_days as u64))
                 .unwrap_or(self.activation_date);
         } else if current_days & 0x0100 == 0 && dh & 0x01 != 0 {
             let new_days = current_days | 0x0100;
 
-            self.activation_date = Utc::now()
-                .naive_utc()
+            self.activation_date = self
+                .now()
                 .checked_sub_days(Days::new(new_days as u64))
                 .unwrap_or(self.activation_date);
         }
@@ -491,6 +869,6 @@ impl RTC {
 
 impl Default for RTC {
     fn default() -> Self {
-        Self::new()
+        Self::new(Box::new(WallClock))
     }
 }
@@ -0,0 +1,187 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use super::{CheatCode, MemoryBus, MemoryRegion};
+
+/// How many bytes each value searched for spans. Words are little endian, as the CPU stores them.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SearchWidth {
+    #[default]
+    Byte,
+    Word,
+}
+
+impl SearchWidth {
+    fn bytes(self) -> usize {
+        match self {
+            SearchWidth::Byte => 1,
+            SearchWidth::Word => 2,
+        }
+    }
+
+    /// Reads a value of this width from the start of some bytes.
+    fn decode(self, bytes: &[u8], signed: bool) -> i32 {
+        match (self, signed) {
+            (SearchWidth::Byte, false) => bytes[0] as i32,
+            (SearchWidth::Byte, true) => bytes[0] as i8 as i32,
+            (SearchWidth::Word, false) => u16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+            (SearchWidth::Word, true) => i16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+        }
+    }
+}
+
+/// What a narrowing pass compares each value against. Every comparison but Value is against the
+/// value at the previous pass.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SearchComparison {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(i32),
+}
+
+impl SearchComparison {
+    fn matches(self, previous: i32, current: i32) -> bool {
+        match self {
+            SearchComparison::Equal => current == previous,
+            SearchComparison::Changed => current != previous,
+            SearchComparison::Increased => current > previous,
+            SearchComparison::Decreased => current < previous,
+            SearchComparison::Value(value) => current == value,
+        }
+    }
+}
+
+/// A value that is still a candidate, at an offset into a region that the memory viewer can read.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub region: MemoryRegion,
+    pub offset: usize,
+    /// The value as of the last pass.
+    pub value: i32,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub width: SearchWidth,
+    pub signed: bool,
+    /// How many candidates remain, which may be more than are listed.
+    pub count: usize,
+    pub results: Vec<SearchResult>,
+}
+
+/// Searches WRAM, HRAM and cartridge RAM for values, starting from every value in them and
+/// narrowing the candidates down with each pass. Passes are made whenever asked, so the game can
+/// run between them.
+#[derive(Debug, Default)]
+pub struct RamSearch {
+    width: SearchWidth,
+    signed: bool,
+    candidates: Vec<SearchResult>,
+}
+
+impl RamSearch {
+    /// Starts a new search with every value in searchable memory as a candidate.
+    pub fn start(&mut self, memory: &MemoryBus, width: SearchWidth, signed: bool) {
+        self.width = width;
+        self.signed = signed;
+        self.candidates = memory
+            .searchable_memory()
+            .iter()
+            .flat_map(|(region, bytes)| {
+                (0..=bytes.len().saturating_sub(width.bytes())).map(move |offset| SearchResult {
+                    region: *region,
+                    offset,
+                    value: width.decode(&bytes[offset..], signed),
+                })
+            })
+            .collect();
+    }
+
+    /// Keeps the candidates whose current value passes the comparison, and remembers their
+    /// current value for the next pass.
+    pub fn narrow(&mut self, memory: &MemoryBus, comparison: SearchComparison) {
+        let snapshot = memory.searchable_memory();
+        let candidates = std::mem::take(&mut self.candidates);
+
+        self.candidates = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let (_, bytes) = snapshot
+                    .iter()
+                    .find(|(region, _)| *region == candidate.region)?;
+                let value = self.width.decode(&bytes[candidate.offset..], self.signed);
+                comparison
+                    .matches(candidate.value, value)
+                    .then_some(SearchResult { value, ..candidate })
+            })
+            .collect();
+    }
+
+    /// The remaining candidates, listing at most `limit` of them.
+    pub fn results(&self, limit: usize) -> SearchResults {
+        SearchResults {
+            width: self.width,
+            signed: self.signed,
+            count: self.candidates.len(),
+            results: self.candidates.iter().take(limit).copied().collect(),
+        }
+    }
+
+    /// GameShark codes that hold a value at an offset into a searched region, with a code per
+    /// byte of the search width. The codes carry the bank, so they write it whichever is mapped.
+    pub fn cheat_codes(
+        &self,
+        memory: &MemoryBus,
+        region: MemoryRegion,
+        offset: usize,
+        value: i32,
+    ) -> anyhow::Result<Vec<CheatCode>> {
+        let width = self.width.bytes();
+        match memory.region_size(region) {
+            Some(size) if offset + width <= size => {}
+            _ => bail!("{:?} has no value at {:#X}.", region, offset),
+        }
+
+        let (bank, address) = match region {
+            MemoryRegion::Wram(0) => (0x01, 0xC000 + offset),
+            // Banks from 0x80 select the WRAM bank in CGB mode
+            MemoryRegion::Wram(bank) => (0x80 | bank, 0xD000 + offset),
+            MemoryRegion::Hram => (0x01, 0xFF80 + offset),
+            MemoryRegion::CartridgeRam(bank) => (bank, 0xA000 + offset),
+            _ => bail!("{:?} isn't searched.", region),
+        };
+
+        let bytes = (value as u16).to_le_bytes();
+        Ok(bytes[..width]
+            .iter()
+            .zip(address..)
+            .map(|(value, address)| CheatCode::GameShark {
+                bank,
+                value: *value,
+                address: address as u16,
+            })
+            .collect())
+    }
+}
+
+impl MemoryBus {
+    /// The contents of every bank of WRAM, HRAM and cartridge RAM.
+    fn searchable_memory(&self) -> Vec<(MemoryRegion, Vec<u8>)> {
+        let banks = move |region: fn(u8) -> MemoryRegion| {
+            (0..)
+                .map(region)
+                .take_while(move |region| self.region_size(*region).is_some())
+        };
+
+        banks(MemoryRegion::Wram)
+            .chain([MemoryRegion::Hram])
+            .chain(banks(MemoryRegion::CartridgeRam))
+            .map(|region| {
+                let size = self.region_size(region).unwrap();
+                (region, self.read_memory(region, 0, size).unwrap())
+            })
+            .collect()
+    }
+}
@@ -1,95 +1,1128 @@
 mod apu;
+mod boot;
 mod cpu;
+mod debugger;
+mod disassembler;
 mod display;
 mod joypad;
 mod memory;
+mod movie;
+mod pacer;
 mod ppu;
+mod printer;
+mod rewind;
+mod rumble;
 mod serial;
+mod sgb;
+mod state;
 mod timer;
+mod trace;
 
-use std::{rc::Rc, sync::RwLock};
+#[cfg(test)]
+mod test;
 
+use std::{
+    fs, mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::RwLock,
+};
+
+use anyhow::{anyhow, bail};
 use apu::APU;
+use chrono::Utc;
 use cpu::CPU;
-use crossbeam::channel::Receiver;
-use display::WebviewDisplay;
-use joypad::Joypad;
-use memory::{cartridge::Cartridge, MemoryBus, MemoryController, SharedMemoryController};
+use crossbeam::channel::Sender;
+use debugger::Debugger;
+use joypad::{Buttons, Joypad};
+use log::{debug, error, info, warn};
+use memory::{cartridge::Cartridge, Cheats, MemoryBus, MemoryController, RamSearch};
+use movie::MovieSession;
+use pacer::FramePacer;
 use ppu::PPU;
+use rewind::RewindBuffer;
+use serde::{Deserialize, Serialize};
 use serial::Serial;
-use tauri::{AppHandle, Manager};
+use state::{SaveState, Snapshot};
 use timer::Timer;
 
-use crate::emulator::{Emulator, EmulatorCommand};
+use crate::emulator::{EmulationSpeed, Emulator, EmulatorCommand, InputSource};
+
+pub use apu::{AudioSink, NullAudioSink, SpeakerAudioSink};
+pub use boot::{Boot, BootRom};
+pub use cpu::Registers;
+pub use debugger::{
+    Access, Breakpoint, Comparison, Condition, DebugCommand, DebugEvent, DebugReply, DebuggerState,
+    Flags, RegisterName, StopReason, Watchpoint,
+};
+pub use disassembler::{disassemble, disassemble_rom, instruction_length, Instruction, Location};
+pub use display::{Color, Display, NullDisplay};
+pub use memory::clock::{Clock, EmulatedClock, FixedClock, ManualClock, WallClock};
+pub use memory::{
+    Cheat, CheatCode, MemoryRegion, SearchComparison, SearchResult, SearchResults, SearchWidth,
+};
+pub use movie::{Movie, MOVIE_VERSION};
+pub use ppu::{OamEntry, TilemapImage, ViewerImage, ViewerPalette};
+pub use printer::{PrintSink, PrintedImage, Printer};
+pub use rumble::{NullRumbleSink, RumbleSink};
+pub use serial::{
+    BufferSerialEndpoint, Link, LinkAddress, LinkSerialEndpoint, LogSerialEndpoint, SerialEndpoint,
+};
+pub use state::SAVE_STATE_VERSION;
+pub use trace::{compare_traces, Divergence, FileTraceSink, TraceLine, TraceSink};
 
 pub struct Gameboy {
-    memory: SharedMemoryController,
+    memory: Rc<RwLock<MemoryBus>>,
     joypad: Rc<RwLock<Joypad>>,
     cpu: CPU,
+    input: Box<dyn InputSource>,
+    save_directory: Option<PathBuf>,
+    rewind: Option<RewindBuffer>,
+    rewinding: bool,
+    pacer: FramePacer,
+    pause_pending: bool,
+    buttons: Buttons,
+    movie: Option<MovieSession>,
+    movie_slot: Option<u8>,
+    /// The save RAM from before a movie that has finished playing. The game keeps running on the
+    /// movie's save RAM, without persisting it, until the next reset puts this back or a state load
+    /// replaces it.
+    playback_save_ram: Option<Vec<u8>>,
+    power_on_state: Vec<u8>,
     clock: u32,
+    frame_clock: u32,
+    tick_cycles: u32,
+    debugger: Debugger,
+    debug_events: Option<Sender<DebugReply>>,
+    /// The request that resumed execution, which is answered once execution stops again.
+    debug_request: Option<u32>,
+    ram_search: RamSearch,
 }
 
-impl Emulator for Gameboy {
-    fn new(rom: Vec<u8>, app_handle: AppHandle) -> Self {
-        let save_data_path = app_handle.path().local_data_dir().unwrap();
+impl Gameboy {
+    /// Constructs a new Gameboy with the provided ROM. All platform-specific concerns (where save
+    /// data lives, how frames are displayed, where audio goes, and where input comes from) are
+    /// provided through the config.
+    pub fn new(rom: Vec<u8>, config: GameboyConfig) -> Result<Self, anyhow::Error> {
+        let cartridge = Cartridge::new(
+            rom,
+            config.save_directory.clone(),
+            config.rtc_clock,
+            config.rumble,
+        )?;
+        let model = match (&config.boot, config.model) {
+            (Boot::Rom(boot_rom), _) => boot_rom.model(),
+            (_, Some(model)) => model,
+            _ => match (cartridge.supports_cgb(), cartridge.supports_sgb()) {
+                (true, _) => Model::CGB,
+                (false, true) => Model::SGB,
+                (false, false) => Model::DMG,
+            },
+        };
+        info!("Running in {:?} mode.", model);
+
+        let boot_rom = match config.boot {
+            Boot::Rom(boot_rom) => Some(boot_rom.into_data()),
+            Boot::Embedded if model == Model::DMG => Some(boot::EMBEDDED_BOOT_ROM.to_vec()),
+            // No boot ROM is bundled for other models, so they start from the state it would
+            // leave behind
+            Boot::Embedded | Boot::Skip => None,
+        };
+        let runs_boot_rom = boot_rom.is_some();
+        let header_checksum = cartridge.rom()[0x014D];
+        let title = cartridge.title().to_owned();
+
         let joypad = Rc::new(RwLock::new(Joypad::new()));
-        let display = Box::new(WebviewDisplay::new(app_handle));
-        let memory = MemoryBus::builder()
+        let mut memory = MemoryBus::builder()
+            .model(model)
             .joypad(joypad.clone())
-            .cartridge(Cartridge::new(rom, save_data_path).unwrap())
-            .serial(Serial::new())
-            .apu(APU::new())
-            .ppu(PPU::new(display))
+            .cartridge(cartridge)
+            .serial(Serial::new(config.serial))
+            .apu(APU::new(config.audio))
+            .ppu(PPU::new(config.display, model))
             .timer(Timer::new())
+            .boot_rom(boot_rom.unwrap_or_default())
             .build();
 
+        memory.set_ly_stubbed(config.stub_ly);
+        if let Some(path) = Self::cheats_path(config.save_directory.as_deref(), &title) {
+            if path.exists() {
+                match Cheats::from_file(&path) {
+                    Ok(cheats) => *memory.cheats_mut() = cheats,
+                    Err(e) => warn!("Unable to load cheats. {:?}", e),
+                }
+            }
+        }
         let memory = Rc::new(RwLock::new(memory));
 
-        GameboyBuilder::new()
-            .cpu(CPU::new(memory.clone()))
+        let mut cpu = CPU::new(memory.clone());
+        cpu.set_trace_sink(config.trace);
+
+        let mut gameboy = GameboyBuilder::new()
+            .cpu(cpu)
             .memory(memory)
             .joypad(joypad)
-            .build()
+            .input(config.input)
+            .save_directory(config.save_directory)
+            .rewind(config.rewind)
+            .debug_events(config.debug_events)
+            .build();
+
+        if runs_boot_rom {
+            gameboy.cpu.reboot();
+        } else {
+            gameboy.cpu.skip_boot(model, header_checksum);
+            gameboy.memory.write().unwrap().skip_boot();
+        }
+        gameboy.set_speed(config.speed);
+        gameboy.power_on_state = gameboy.save_state()?;
+
+        Ok(gameboy)
+    }
+
+    /// Advances the system by CYCLE_RESOLUTION t-cycles. Returns true if a frame boundary was
+    /// crossed, which is when the PPU enters VBlank. While the LCD is off no VBlank occurs, so a
+    /// boundary is instead reported once every FRAME_CYCLES t-cycles.
+    pub fn tick(&mut self) -> bool {
+        // The CPU is halted while VRAM DMA copies a block
+        let stalled = self.memory.read().unwrap().cpu_stalled();
+        if !stalled {
+            if self.debugger.is_active() && self.check_instruction() {
+                return false;
+            }
+
+            self.cpu.tick(GlobalConstants::CYCLE_RESOLUTION);
+
+            if self.debugger.watches_memory() {
+                for access in self.cpu.take_memory_accesses() {
+                    self.debugger.check_access(access);
+                }
+            }
+        }
+
+        let mut memory = self.memory.write().unwrap();
+        memory.tick(GlobalConstants::CYCLE_RESOLUTION);
+
+        // Frame timing and pacing follow the PPU, which doesn't speed up in double speed mode
+        self.tick_cycles = memory.normal_speed_cycles(GlobalConstants::CYCLE_RESOLUTION);
+        self.frame_clock += self.tick_cycles;
+        if memory.take_frame_complete() || self.frame_clock >= GlobalConstants::FRAME_CYCLES {
+            self.frame_clock = 0;
+            return true;
+        }
+
+        false
+    }
+
+    /// Whether the CPU state is still being traced. False once the trace sink has finished, or if
+    /// there never was one.
+    pub fn tracing(&self) -> bool {
+        self.cpu.tracing()
+    }
+
+    /// Runs until the next frame boundary, then applies input for the following frame. Returns
+    /// early if the debugger stops execution.
+    pub fn run_frame(&mut self) {
+        while !self.tick() {
+            if self.debugger.stopped() {
+                return;
+            }
+        }
+        self.frame_boundary();
+        self.debugger.end_frame();
+    }
+
+    /// Consults the debugger if the CPU is about to start an instruction. Returns true if
+    /// execution stopped before it.
+    fn check_instruction(&mut self) -> bool {
+        let Some(address) = self.cpu.next_instruction_address() else {
+            return false;
+        };
+        let opcode = self.memory.read().unwrap().read_byte(address);
+
+        self.debugger
+            .check_instruction(&self.cpu.registers(), opcode)
+    }
+
+    /// Handles a debugger command. Stepping commands resume execution from wherever it stopped.
+    pub fn debug(&mut self, command: DebugCommand) {
+        let registers = self.cpu.registers();
+        match command {
+            DebugCommand::AddBreakpoint(breakpoint) => self.debugger.add_breakpoint(breakpoint),
+            DebugCommand::RemoveBreakpoint(address) => self.debugger.remove_breakpoint(address),
+            DebugCommand::AddWatchpoint(watchpoint) => self.debugger.add_watchpoint(watchpoint),
+            DebugCommand::RemoveWatchpoint(watchpoint) => {
+                self.debugger.remove_watchpoint(watchpoint)
+            }
+            DebugCommand::StepInstruction => self.debugger.step_instruction(),
+            DebugCommand::StepOver => {
+                let opcode = self.memory.read().unwrap().read_byte(registers.pc);
+                self.debugger.step_over(&registers, opcode);
+            }
+            DebugCommand::StepOut => self.debugger.step_out(&registers),
+            DebugCommand::RunToFrame(frames) => self.debugger.run_frames(frames),
+            DebugCommand::WriteMemory {
+                region,
+                offset,
+                value,
+            } => {
+                if let Err(e) = self.write_memory(region, offset, &[value]) {
+                    error!("Unable to write memory. {:?}", e);
+                }
+            }
+            DebugCommand::AddCheat(code) => self.update_cheats(|cheats| cheats.add(code)),
+            DebugCommand::RemoveCheat(code) => self.update_cheats(|cheats| cheats.remove(code)),
+            DebugCommand::SetCheatEnabled(code, enabled) => {
+                self.update_cheats(|cheats| cheats.set_enabled(code, enabled))
+            }
+            DebugCommand::StartSearch { width, signed } => {
+                let memory = self.memory.read().unwrap();
+                self.ram_search.start(&memory, width, signed);
+            }
+            DebugCommand::NarrowSearch(comparison) => {
+                let memory = self.memory.read().unwrap();
+                self.ram_search.narrow(&memory, comparison);
+            }
+            DebugCommand::AddSearchCheat {
+                region,
+                offset,
+                value,
+            } => {
+                let memory = self.memory.read().unwrap();
+                let codes = self.ram_search.cheat_codes(&memory, region, offset, value);
+                drop(memory);
+
+                match codes {
+                    Ok(codes) => self.update_cheats(|cheats| {
+                        for code in codes {
+                            cheats.add(code);
+                        }
+                    }),
+                    Err(e) => error!("Unable to add cheat. {:?}", e),
+                }
+            }
+            DebugCommand::Inspect
+            | DebugCommand::ListCheats
+            | DebugCommand::ListSearchResults
+            | DebugCommand::ReadMemory { .. }
+            | DebugCommand::ViewTiles { .. }
+            | DebugCommand::ViewTilemap(_)
+            | DebugCommand::ViewOam => {}
+        }
+
+        self.cpu
+            .record_memory_accesses(self.debugger.watches_memory());
+        if command.resumes() {
+            self.resume_debugger();
+        }
+    }
+
+    /// Returns why the debugger stopped execution, if it has since this was last called.
+    /// Execution stays stopped until a stepping command or resume_debugger.
+    pub fn take_stop_reason(&mut self) -> Option<StopReason> {
+        self.debugger.take_stop_reason()
+    }
+
+    /// Continues execution after the debugger stopped it.
+    pub fn resume_debugger(&mut self) {
+        self.debugger.resume(self.cpu.next_instruction_address());
+    }
+
+    pub fn debugger_state(&self, stop_reason: Option<StopReason>) -> DebuggerState {
+        let registers = self.cpu.registers();
+
+        DebuggerState {
+            registers,
+            flags: Flags::from(registers.f),
+            stop_reason,
+            instructions: self.disassemble(registers.pc, GlobalConstants::DEBUGGER_INSTRUCTIONS),
+            breakpoints: self.debugger.breakpoints().to_vec(),
+            watchpoints: self.debugger.watchpoints().to_vec(),
+        }
+    }
+
+    /// Decodes `count` consecutive instructions starting at an address, as memory is currently
+    /// mapped. Instructions in cartridge ROM are labelled with the bank they're in.
+    pub fn disassemble(&self, address: u16, count: usize) -> Vec<Instruction> {
+        let memory = self.memory.read().unwrap();
+        let mut address = address;
+
+        (0..count)
+            .map(|_| {
+                let location = Location::new(memory.rom_bank(address), address);
+                let instruction = disassemble(location, |address| memory.read_byte(address));
+                address = address.wrapping_add(instruction.length());
+                instruction
+            })
+            .collect()
+    }
+
+    /// Reads `length` bytes of a memory region from an offset, without side effects.
+    pub fn read_memory(
+        &self,
+        region: MemoryRegion,
+        start: usize,
+        length: usize,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.memory
+            .read()
+            .unwrap()
+            .read_memory(region, start, length)
+    }
+
+    /// Writes bytes to a memory region from an offset. Writes to cartridge ROM patch the loaded
+    /// ROM.
+    pub fn write_memory(
+        &mut self,
+        region: MemoryRegion,
+        start: usize,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        self.memory
+            .write()
+            .unwrap()
+            .write_memory(region, start, data)
+    }
+
+    /// The reply to a command that doesn't resume execution: what viewer commands render, the
+    /// memory that memory commands access, the cheats for cheat commands, the results of RAM
+    /// search commands, and the debugger state for everything else.
+    pub fn debug_reply(
+        &self,
+        command: DebugCommand,
+        stop_reason: Option<StopReason>,
+    ) -> DebugEvent {
+        let memory = || self.memory.read().unwrap();
+
+        match command {
+            DebugCommand::ViewTiles { bank, palette } => {
+                DebugEvent::Tiles(memory().ppu().render_tiles(bank, palette))
+            }
+            DebugCommand::ViewTilemap(tilemap) => {
+                DebugEvent::Tilemap(memory().ppu().render_tilemap(tilemap))
+            }
+            DebugCommand::ViewOam => DebugEvent::Oam(memory().ppu().oam_entries()),
+            DebugCommand::ReadMemory {
+                region,
+                start,
+                length,
+            } => DebugEvent::Memory(self.read_memory(region, start, length).ok()),
+            // Reads back what was written, which may differ for registers
+            DebugCommand::WriteMemory { region, offset, .. } => {
+                DebugEvent::Memory(self.read_memory(region, offset, 1).ok())
+            }
+            DebugCommand::AddCheat(_)
+            | DebugCommand::RemoveCheat(_)
+            | DebugCommand::SetCheatEnabled(..)
+            | DebugCommand::AddSearchCheat { .. }
+            | DebugCommand::ListCheats => DebugEvent::Cheats(self.cheats()),
+            DebugCommand::StartSearch { .. }
+            | DebugCommand::NarrowSearch(_)
+            | DebugCommand::ListSearchResults => {
+                DebugEvent::Search(self.ram_search.results(GlobalConstants::SEARCH_RESULTS))
+            }
+            _ => DebugEvent::State(self.debugger_state(stop_reason)),
+        }
+    }
+
+    /// The cheats for the loaded ROM, in the order they were added.
+    pub fn cheats(&self) -> Vec<Cheat> {
+        self.memory.read().unwrap().cheats().list().to_vec()
+    }
+
+    /// Changes the cheats, then writes them alongside the save data so they are loaded with the
+    /// ROM next time. Cheats can't change while a movie is recording or playing, as the movie
+    /// only reproduces the run with the cheats it started with.
+    fn update_cheats(&mut self, update: impl FnOnce(&mut Cheats)) {
+        if self.movie.is_some() {
+            warn!("Unable to change cheats while a movie is recording or playing.");
+            return;
+        }
+
+        let mut memory = self.memory.write().unwrap();
+        update(memory.cheats_mut());
+
+        let title = memory.cartridge().title().to_owned();
+        if let Some(path) = Self::cheats_path(self.save_directory.as_deref(), &title) {
+            if let Err(e) = memory.cheats().write_file(&path) {
+                error!("Unable to save cheats. {:?}", e);
+            }
+        }
+    }
+
+    /// Sends the debugger state to the frontend, if it is listening, answering the request that
+    /// resumed execution if there was one.
+    fn publish_debugger_state(&mut self, stop_reason: Option<StopReason>) {
+        let request = self.debug_request.take();
+        self.publish_debug_event(request, |gameboy| {
+            DebugEvent::State(gameboy.debugger_state(stop_reason))
+        });
+    }
+
+    /// Sends the reply to a debugger request to the frontend, if it is listening.
+    fn publish_debug_reply(
+        &self,
+        request: u32,
+        command: DebugCommand,
+        stop_reason: Option<StopReason>,
+    ) {
+        self.publish_debug_event(Some(request), |gameboy| {
+            gameboy.debug_reply(command, stop_reason)
+        });
+    }
+
+    fn publish_debug_event(&self, request: Option<u32>, event: impl FnOnce(&Self) -> DebugEvent) {
+        if let Some(debug_events) = &self.debug_events {
+            let reply = DebugReply {
+                request,
+                event: event(self),
+            };
+            // Nothing is lost if the frontend has gone away
+            let _ = debug_events.send(reply);
+        }
+    }
+
+    /// The current values of the CPU registers.
+    pub fn registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
+    /// Returns true if LD B,B has been executed since this was last called. Test ROMs and
+    /// homebrew use it as a software breakpoint.
+    pub fn take_software_breakpoint(&mut self) -> bool {
+        self.cpu.take_software_breakpoint()
+    }
+
+    /// Applies input for the frame that is about to start. Input from the host is only ever
+    /// applied here, so that a run can be reproduced exactly by replaying the input of each frame.
+    fn frame_boundary(&mut self) {
+        let buttons = match &mut self.movie {
+            Some(MovieSession::Playing { movie, frame, .. }) => {
+                let buttons = movie.input(*frame);
+                *frame += 1;
+                buttons
+            }
+            Some(MovieSession::Recording(movie)) => {
+                movie.push_input(self.buttons);
+                Some(self.buttons)
+            }
+            None => Some(self.buttons),
+        };
+
+        let buttons = buttons.unwrap_or_else(|| {
+            info!("Movie playback finished.");
+            self.stop_movie();
+            self.buttons
+        });
+
+        self.joypad.write().unwrap().set_buttons(buttons);
+        self.memory.write().unwrap().apply_game_shark();
+    }
+
+    /// Returns the machine to the state it was in at power on. Cartridge RAM is left as it is, as
+    /// it would be on real hardware.
+    fn reset(&mut self) -> Result<(), anyhow::Error> {
+        self.restore_save_ram();
+        let ram = self.memory.read().unwrap().cartridge().ram();
+
+        self.load_state(&self.power_on_state.clone())?;
+        self.frame_clock = 0;
+
+        let mut memory = self.memory.write().unwrap();
+        memory.take_frame_complete();
+        memory.cartridge_mut().set_ram(&ram);
+
+        Ok(())
+    }
+
+    /// Restarts from power on and begins recording the input of every frame. The current save RAM
+    /// and enabled cheats are stored in the movie, and the RTC is restarted from the current time
+    /// so that it can be reproduced exactly on playback.
+    pub fn record_movie(&mut self) -> Result<(), anyhow::Error> {
+        self.stop_movie();
+        self.restore_save_ram();
+
+        let movie = {
+            let memory = self.memory.read().unwrap();
+            let cartridge = memory.cartridge();
+            Movie::new(
+                cartridge.rom(),
+                cartridge.ram(),
+                Utc::now().timestamp(),
+                memory.cheats().enabled_codes(),
+            )
+        };
+
+        self.reset()?;
+        self.memory
+            .write()
+            .unwrap()
+            .cartridge_mut()
+            .seed_rtc(movie.rtc_seed());
+
+        info!("Recording movie.");
+        self.movie = Some(MovieSession::Recording(movie));
+        self.frame_boundary();
+
+        Ok(())
+    }
+
+    /// Restarts from power on with the movie's save RAM, RTC and cheats, then replays its input.
+    /// Save data is not persisted during playback.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), anyhow::Error> {
+        if !movie.matches_rom(self.memory.read().unwrap().cartridge().rom()) {
+            bail!("Movie was recorded with a different ROM.");
+        }
+
+        self.stop_movie();
+        self.reset()?;
+
+        let (save_ram, cheats) = {
+            let mut memory = self.memory.write().unwrap();
+            let cheats = mem::replace(memory.cheats_mut(), Cheats::from_codes(movie.cheats()));
+            let cartridge = memory.cartridge_mut();
+            let save_ram = cartridge.ram();
+            cartridge.set_ram(movie.save_ram());
+            cartridge.seed_rtc(movie.rtc_seed());
+            cartridge.set_persistent(false);
+            (save_ram, cheats)
+        };
+
+        info!("Playing movie of {} frames.", movie.len());
+        self.movie = Some(MovieSession::Playing {
+            movie,
+            frame: 0,
+            save_ram,
+            cheats,
+        });
+        self.frame_boundary();
+
+        Ok(())
+    }
+
+    /// Stops recording or playing back a movie. Returns the movie if one was being recorded. The
+    /// RTC goes back to the configured clock, and after playback the cheats from before it are put
+    /// back. The save RAM from before playback is only put back by the next reset or state load,
+    /// as the game is still running on the movie's, so the replayed run is never persisted.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        let mut memory = self.memory.write().unwrap();
+        memory.cartridge_mut().restore_rtc();
+
+        match self.movie.take() {
+            Some(MovieSession::Recording(movie)) => Some(movie),
+            Some(MovieSession::Playing {
+                save_ram, cheats, ..
+            }) => {
+                *memory.cheats_mut() = cheats;
+                self.playback_save_ram = Some(save_ram);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Puts back the save RAM from before a finished movie playback, if the game is still running
+    /// on the movie's, and persists it again.
+    fn restore_save_ram(&mut self) {
+        if let Some(save_ram) = self.playback_save_ram.take() {
+            let mut memory = self.memory.write().unwrap();
+            let cartridge = memory.cartridge_mut();
+            cartridge.set_ram(&save_ram);
+            cartridge.set_persistent(true);
+        }
+    }
+
+    /// Captures the full machine state, encoded in the versioned save state format. Restoring it
+    /// with load_state resumes execution from exactly this cycle.
+    pub fn save_state(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let memory = self.memory.read().unwrap();
+        let state = SaveState {
+            title: memory.cartridge().title().to_owned(),
+            global_checksum: memory.cartridge().global_checksum(),
+            clock: self.clock,
+            cpu: self.cpu.save_state(),
+            memory: memory.save_state(),
+        };
+
+        state.to_bytes()
+    }
+
+    /// Restores a state produced by save_state. The state must have been captured from the same
+    /// ROM that is currently loaded.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), anyhow::Error> {
+        let state = SaveState::from_bytes(bytes)?;
+
+        let mut memory = self.memory.write().unwrap();
+        if state.title != memory.cartridge().title()
+            || state.global_checksum != memory.cartridge().global_checksum()
+        {
+            bail!(
+                "Save state was captured from {}, not the loaded ROM {}.",
+                state.title,
+                memory.cartridge().title()
+            );
+        }
+
+        memory.check_state(&state.memory)?;
+        memory.load_state(state.memory);
+        self.cpu.load_state(state.cpu);
+        self.clock = state.clock;
+
+        Ok(())
+    }
+
+    /// Writes the current machine state to the numbered slot for the loaded ROM.
+    pub fn save_state_to_slot(&self, slot: u8) -> Result<(), anyhow::Error> {
+        let path = self.state_slot_path(slot)?;
+        fs::write(&path, self.save_state()?)?;
+        info!("Saved state to {:?}", path);
+
+        Ok(())
+    }
+
+    /// Restores the machine state stored in the numbered slot for the loaded ROM.
+    pub fn load_state_from_slot(&mut self, slot: u8) -> Result<(), anyhow::Error> {
+        let path = self.state_slot_path(slot)?;
+        self.load_state(&fs::read(&path)?)?;
+        // The state replaces the save RAM a finished movie left the game running on
+        if self.playback_save_ram.take().is_some() {
+            let mut memory = self.memory.write().unwrap();
+            memory.cartridge_mut().set_persistent(true);
+        }
+        info!("Loaded state from {:?}", path);
+
+        Ok(())
+    }
+
+    fn state_slot_path(&self, slot: u8) -> Result<PathBuf, anyhow::Error> {
+        self.slot_path("ss", slot)
     }
 
-    fn start(&mut self, receiver: &Receiver<EmulatorCommand>) -> Result<(), anyhow::Error> {
-        self.cpu.reboot();
+    fn movie_slot_path(&self, slot: u8) -> Result<PathBuf, anyhow::Error> {
+        self.slot_path("mv", slot)
+    }
+
+    fn slot_path(&self, extension: &str, slot: u8) -> Result<PathBuf, anyhow::Error> {
+        let save_directory = self
+            .save_directory
+            .as_ref()
+            .ok_or_else(|| anyhow!("No save directory configured for slots."))?;
+        let title = self.memory.read().unwrap().cartridge().title().to_owned();
 
+        Ok(save_directory.join(format!("{}.{}{}", title, extension, slot)))
+    }
+
+    /// Where the cheats for a ROM are kept, if there is a save directory.
+    fn cheats_path(save_directory: Option<&Path>, title: &str) -> Option<PathBuf> {
+        save_directory.map(|save_directory| save_directory.join(format!("{}.cheats", title)))
+    }
+
+    /// Begins recording a movie that is written to the numbered slot once stopped.
+    fn record_movie_to_slot(&mut self, slot: u8) -> Result<(), anyhow::Error> {
+        self.record_movie()?;
+        self.movie_slot = Some(slot);
+
+        Ok(())
+    }
+
+    /// Plays back the movie stored in the numbered slot for the loaded ROM.
+    fn play_movie_from_slot(&mut self, slot: u8) -> Result<(), anyhow::Error> {
+        let path = self.movie_slot_path(slot)?;
+        self.play_movie(Movie::from_bytes(&fs::read(&path)?)?)?;
+        info!("Loaded movie from {:?}", path);
+
+        Ok(())
+    }
+
+    /// Stops any movie, writing it to its slot if one was being recorded.
+    fn stop_movie_to_slot(&mut self) -> Result<(), anyhow::Error> {
+        let slot = self.movie_slot.take();
+        if let (Some(movie), Some(slot)) = (self.stop_movie(), slot) {
+            let path = self.movie_slot_path(slot)?;
+            fs::write(&path, movie.to_bytes()?)?;
+            info!("Saved movie of {} frames to {:?}", movie.len(), path);
+        }
+
+        Ok(())
+    }
+
+    /// Changes how fast emulation runs relative to real hardware. Audio is muted at any speed
+    /// other than 1x.
+    pub fn set_speed(&mut self, speed: EmulationSpeed) {
+        info!("Setting emulation speed to {:?}", speed);
+        self.pacer.set_speed(speed);
+        self.memory
+            .write()
+            .unwrap()
+            .set_audio_muted(!speed.is_real_time());
+    }
+
+    /// Called once per frame while running. Either records the current state into the rewind
+    /// history, or steps backwards through it while rewind is held.
+    fn update_rewind(&mut self) {
+        let Some(rewind) = self.rewind.as_mut() else {
+            return;
+        };
+
+        // Jumping backwards would desynchronize a movie from its recorded input
+        if self.movie.is_some() {
+            return;
+        }
+
+        if self.rewinding {
+            match rewind.pop() {
+                Ok(Some(state)) => {
+                    if let Err(e) = self.load_state(&state) {
+                        error!("Unable to restore rewind state. {:?}", e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Unable to rewind. {:?}", e);
+                    self.rewinding = false;
+                }
+            }
+        } else if rewind.frame() {
+            match self.save_state() {
+                Ok(state) => self.rewind.as_mut().unwrap().push(state),
+                Err(e) => error!("Unable to record rewind state. {:?}", e),
+            }
+        }
+    }
+
+    /// Blocks until emulation is resumed, handling any commands that are valid while paused. This
+    /// is at a frame boundary unless the debugger stopped execution. Key presses are recorded so
+    /// that input for the next frame can be set up before advancing. Returns false if the
+    /// emulator should stop.
+    fn wait_while_paused(&mut self, mut reason: StopReason) -> bool {
+        self.publish_debugger_state(Some(reason));
+
+        loop {
+            match self.input.wait() {
+                Some(EmulatorCommand::Start) => {
+                    self.resume_debugger();
+                    break;
+                }
+                Some(EmulatorCommand::Stop) | None => return false,
+                Some(EmulatorCommand::FrameAdvance) => {
+                    self.resume_debugger();
+                    self.run_frame();
+                    reason = self.take_stop_reason().unwrap_or(StopReason::Paused);
+                    self.publish_debugger_state(Some(reason));
+                }
+                Some(EmulatorCommand::Debug(request, command)) => {
+                    self.debug(command);
+                    if command.resumes() {
+                        self.debug_request = Some(request);
+                        break;
+                    }
+                    self.publish_debug_reply(request, command, Some(reason));
+                }
+                Some(EmulatorCommand::KeyDown(input)) => self.buttons.insert(input.into()),
+                Some(EmulatorCommand::KeyUp(input)) => self.buttons.remove(input.into()),
+                Some(
+                    command @ (EmulatorCommand::SaveState(_)
+                    | EmulatorCommand::LoadState(_)
+                    | EmulatorCommand::RecordMovie(_)
+                    | EmulatorCommand::PlayMovie(_)
+                    | EmulatorCommand::StopMovie),
+                ) => self.handle_state_command(command),
+                Some(EmulatorCommand::SetSpeed(speed)) => self.set_speed(speed),
+                Some(EmulatorCommand::Pause | EmulatorCommand::Rewind(_)) => {}
+            }
+        }
+
+        self.pacer.reset();
+        true
+    }
+
+    /// Handles commands that are valid whether or not the emulator is currently running.
+    fn handle_state_command(&mut self, command: EmulatorCommand) {
+        let result = match command {
+            EmulatorCommand::SaveState(slot) => self.save_state_to_slot(slot),
+            EmulatorCommand::LoadState(slot) => {
+                if self.movie.is_some() {
+                    warn!("Stopping movie as a save state was loaded.");
+                    self.stop_movie();
+                    self.movie_slot = None;
+                }
+                self.load_state_from_slot(slot)
+            }
+            EmulatorCommand::RecordMovie(slot) => self.record_movie_to_slot(slot),
+            EmulatorCommand::PlayMovie(slot) => self.play_movie_from_slot(slot),
+            EmulatorCommand::StopMovie => self.stop_movie_to_slot(),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            error!("Unable to process {:?}. {:?}", command, e);
+        }
+    }
+}
+
+impl Emulator for Gameboy {
+    fn start(&mut self) -> Result<(), anyhow::Error> {
         loop {
-            self.clock += GlobalConstants::CYCLE_RESOLUTION;
+            self.clock += self.tick_cycles;
 
             if self.clock >= GlobalConstants::INPUT_RESPONSIVENESS {
                 self.clock -= GlobalConstants::INPUT_RESPONSIVENESS;
 
-                match receiver.try_recv() {
-                    Ok(EmulatorCommand::Start) => {}
-                    Ok(EmulatorCommand::Pause) => loop {
-                        if let Ok(EmulatorCommand::Start) = receiver.recv() {
-                            break;
+                match self.input.poll() {
+                    Some(EmulatorCommand::Start) => {}
+                    // Pausing is deferred until the end of the current frame
+                    Some(EmulatorCommand::Pause | EmulatorCommand::FrameAdvance) => {
+                        self.pause_pending = true;
+                    }
+                    Some(EmulatorCommand::Stop) => break,
+                    // Input is applied at the start of the next frame
+                    Some(EmulatorCommand::KeyDown(input)) => self.buttons.insert(input.into()),
+                    Some(EmulatorCommand::KeyUp(input)) => self.buttons.remove(input.into()),
+                    Some(
+                        command @ (EmulatorCommand::SaveState(_)
+                        | EmulatorCommand::LoadState(_)
+                        | EmulatorCommand::RecordMovie(_)
+                        | EmulatorCommand::PlayMovie(_)
+                        | EmulatorCommand::StopMovie),
+                    ) => self.handle_state_command(command),
+                    Some(EmulatorCommand::Rewind(rewinding)) => {
+                        if let (false, Some(rewind)) = (rewinding, self.rewind.as_ref()) {
+                            debug!("Rewind history is using {} bytes.", rewind.size());
                         }
-                    },
-                    Ok(EmulatorCommand::Stop) => break,
-                    Ok(EmulatorCommand::KeyDown(input)) => {
-                        self.joypad.write().unwrap().keydown(input);
+                        self.rewinding = rewinding;
                     }
-                    Ok(EmulatorCommand::KeyUp(input)) => {
-                        self.joypad.write().unwrap().keyup(input);
+                    Some(EmulatorCommand::SetSpeed(speed)) => self.set_speed(speed),
+                    Some(EmulatorCommand::Debug(request, command)) => {
+                        self.debug(command);
+                        match command.resumes() {
+                            true => self.debug_request = Some(request),
+                            false => self.publish_debug_reply(request, command, None),
+                        }
                     }
-                    Err(_) => {}
+                    None => {}
                 };
+
+                self.update_rewind();
+                self.pacer.wait(GlobalConstants::INPUT_RESPONSIVENESS);
             }
 
-            self.cpu.tick(GlobalConstants::CYCLE_RESOLUTION);
-            self.memory
-                .write()
-                .unwrap()
-                .tick(GlobalConstants::CYCLE_RESOLUTION);
+            if self.tick() {
+                self.frame_boundary();
+                self.debugger.end_frame();
+
+                if self.pause_pending {
+                    self.pause_pending = false;
+                    if !self.wait_while_paused(StopReason::Paused) {
+                        return Ok(());
+                    }
+                }
+            }
+
+            if let Some(reason) = self.take_stop_reason() {
+                if !self.wait_while_paused(reason) {
+                    return Ok(());
+                }
+            }
         }
 
         Ok(())
     }
 }
 
+/// Platform-specific dependencies of a Gameboy. Running headless only requires a Display and an
+/// InputSource, such as NullDisplay and a channel receiver.
+pub struct GameboyConfig {
+    save_directory: Option<PathBuf>,
+    display: Box<dyn Display>,
+    audio: Box<dyn AudioSink>,
+    input: Box<dyn InputSource>,
+    rewind: Option<RewindBuffer>,
+    speed: EmulationSpeed,
+    rtc_clock: Box<dyn Clock>,
+    rumble: Box<dyn RumbleSink>,
+    serial: Box<dyn SerialEndpoint>,
+    boot: Boot,
+    model: Option<Model>,
+    debug_events: Option<Sender<DebugReply>>,
+    trace: Option<Box<dyn TraceSink>>,
+    stub_ly: bool,
+}
+
+impl GameboyConfig {
+    /// The default number of seconds of rewind history kept.
+    pub const DEFAULT_REWIND_SECONDS: u32 = 30;
+
+    /// The default number of frames between captured rewind states.
+    pub const DEFAULT_REWIND_INTERVAL: u32 = 4;
+
+    pub fn builder() -> GameboyConfigBuilder {
+        GameboyConfigBuilder {
+            save_directory: None,
+            display: None,
+            audio: None,
+            input: None,
+            rewind: Some((Self::DEFAULT_REWIND_SECONDS, Self::DEFAULT_REWIND_INTERVAL)),
+            speed: EmulationSpeed::default(),
+            rtc_clock: None,
+            rumble: None,
+            serial: None,
+            boot: Boot::default(),
+            model: None,
+            debug_events: None,
+            trace: None,
+            stub_ly: false,
+        }
+    }
+}
+
+pub struct GameboyConfigBuilder {
+    save_directory: Option<PathBuf>,
+    display: Option<Box<dyn Display>>,
+    audio: Option<Box<dyn AudioSink>>,
+    input: Option<Box<dyn InputSource>>,
+    rewind: Option<(u32, u32)>,
+    speed: EmulationSpeed,
+    rtc_clock: Option<Box<dyn Clock>>,
+    rumble: Option<Box<dyn RumbleSink>>,
+    serial: Option<Box<dyn SerialEndpoint>>,
+    boot: Boot,
+    model: Option<Model>,
+    debug_events: Option<Sender<DebugReply>>,
+    trace: Option<Box<dyn TraceSink>>,
+    stub_ly: bool,
+}
+
+impl GameboyConfigBuilder {
+    /// The directory battery-backed cartridge RAM and save state slots are persisted to. If None,
+    /// save data is kept in memory only and save state slots are unavailable.
+    pub fn save_directory(mut self, save_directory: Option<PathBuf>) -> Self {
+        self.save_directory = save_directory;
+        self
+    }
+
+    pub fn display(mut self, display: impl Display + 'static) -> Self {
+        self.display = Some(Box::new(display));
+        self
+    }
+
+    /// The sink mixed audio samples are sent to. Defaults to a NullAudioSink.
+    pub fn audio(mut self, audio: impl AudioSink + 'static) -> Self {
+        self.audio = Some(Box::new(audio));
+        self
+    }
+
+    pub fn input(mut self, input: impl InputSource + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Keeps `seconds` of rewind history, capturing a state every `interval` frames. Rewind is
+    /// enabled by default; passing zero seconds disables it.
+    pub fn rewind(mut self, seconds: u32, interval: u32) -> Self {
+        self.rewind = (seconds > 0).then_some((seconds, interval.max(1)));
+        self
+    }
+
+    /// The speed emulation starts at. Defaults to real-time.
+    pub fn speed(mut self, speed: EmulationSpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// The clock cartridge RTCs read the time from. Defaults to a WallClock. An EmulatedClock
+    /// keeps in-game time consistent with fast-forward, pausing and save states.
+    pub fn rtc_clock(mut self, rtc_clock: impl Clock + 'static) -> Self {
+        self.rtc_clock = Some(Box::new(rtc_clock));
+        self
+    }
+
+    /// The sink notified when the motor of a rumble cartridge starts or stops. Defaults to a
+    /// NullRumbleSink.
+    pub fn rumble(mut self, rumble: impl RumbleSink + 'static) -> Self {
+        self.rumble = Some(Box::new(rumble));
+        self
+    }
+
+    /// The device at the other end of the link cable. Defaults to a LogSerialEndpoint.
+    pub fn serial(mut self, serial: impl SerialEndpoint + 'static) -> Self {
+        self.serial = Some(Box::new(serial));
+        self
+    }
+
+    /// How the Gameboy starts up. Defaults to Boot::Embedded.
+    pub fn boot(mut self, boot: Boot) -> Self {
+        self.boot = boot;
+        self
+    }
+
+    /// The hardware to emulate, instead of detecting it from the cartridge header. Ignored when
+    /// running a boot ROM dump, which decides the model itself.
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Where the debugger state is sent whenever execution stops or a debugger command is handled.
+    pub fn debug_events(mut self, debug_events: Sender<DebugReply>) -> Self {
+        self.debug_events = Some(debug_events);
+        self
+    }
+
+    /// The sink the CPU state is sent to as each instruction starts. Tracing is off by default.
+    pub fn trace(mut self, trace: impl TraceSink + 'static) -> Self {
+        self.trace = Some(Box::new(trace));
+        self
+    }
+
+    /// Makes LY always read 0x90, for comparing traces against gameboy-doctor logs. Defaults to
+    /// false.
+    pub fn stub_ly(mut self, stub_ly: bool) -> Self {
+        self.stub_ly = stub_ly;
+        self
+    }
+
+    pub fn build(self) -> GameboyConfig {
+        debug_assert!(self.display.is_some(), "No Display specified on builder.");
+        debug_assert!(self.input.is_some(), "No InputSource specified on builder.");
+
+        GameboyConfig {
+            save_directory: self.save_directory,
+            display: self.display.unwrap(),
+            audio: self.audio.unwrap_or_else(|| Box::new(NullAudioSink)),
+            input: self.input.unwrap(),
+            rewind: self
+                .rewind
+                .map(|(seconds, interval)| RewindBuffer::new(seconds, interval)),
+            speed: self.speed,
+            rtc_clock: self.rtc_clock.unwrap_or_else(|| Box::new(WallClock)),
+            rumble: self.rumble.unwrap_or_else(|| Box::new(NullRumbleSink)),
+            serial: self
+                .serial
+                .unwrap_or_else(|| Box::new(LogSerialEndpoint::new())),
+            boot: self.boot,
+            model: self.model,
+            debug_events: self.debug_events,
+            trace: self.trace,
+            stub_ly: self.stub_ly,
+        }
+    }
+}
+
+/// The hardware a Gameboy emulates.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Model {
+    /// The earliest DMG revision, which has its own boot ROM.
+    DMG0,
+    DMG,
+    /// The Game Boy Pocket.
+    MGB,
+    /// The Super Game Boy, which runs DMG games on a SNES and lets them colorize the screen and
+    /// draw a border around it.
+    SGB,
+    CGB,
+}
+
 pub struct GlobalConstants;
 
 impl GlobalConstants {
@@ -109,12 +1142,25 @@ impl GlobalConstants {
     /// The number of t-cycles that pass before events will be polled. Lower values means
     /// more responsive controls, sacrificing performance
     pub const INPUT_RESPONSIVENESS: u32 = 70224;
+
+    /// The number of t-cycles in a single frame.
+    pub const FRAME_CYCLES: u32 = 70224;
+
+    /// The number of instructions from the program counter on that the debugger reports.
+    pub const DEBUGGER_INSTRUCTIONS: usize = 16;
+
+    /// The number of RAM search results listed. Any more are only counted.
+    pub const SEARCH_RESULTS: usize = 256;
 }
 
 pub struct GameboyBuilder {
     cpu: Option<CPU>,
-    memory: Option<Rc<RwLock<dyn MemoryController>>>,
+    memory: Option<Rc<RwLock<MemoryBus>>>,
     joypad: Option<Rc<RwLock<Joypad>>>,
+    input: Option<Box<dyn InputSource>>,
+    save_directory: Option<PathBuf>,
+    rewind: Option<RewindBuffer>,
+    debug_events: Option<Sender<DebugReply>>,
 }
 
 impl GameboyBuilder {
@@ -123,6 +1169,10 @@ impl GameboyBuilder {
             cpu: None,
             memory: None,
             joypad: None,
+            input: None,
+            save_directory: None,
+            rewind: None,
+            debug_events: None,
         }
     }
     fn cpu(mut self, cpu: CPU) -> Self {
@@ -130,7 +1180,7 @@ impl GameboyBuilder {
         self
     }
 
-    fn memory(mut self, memory: Rc<RwLock<dyn MemoryController>>) -> Self {
+    fn memory(mut self, memory: Rc<RwLock<MemoryBus>>) -> Self {
         self.memory = Some(memory);
         self
     }
@@ -140,16 +1190,54 @@ impl GameboyBuilder {
         self
     }
 
+    fn input(mut self, input: Box<dyn InputSource>) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    fn save_directory(mut self, save_directory: Option<PathBuf>) -> Self {
+        self.save_directory = save_directory;
+        self
+    }
+
+    fn rewind(mut self, rewind: Option<RewindBuffer>) -> Self {
+        self.rewind = rewind;
+        self
+    }
+
+    fn debug_events(mut self, debug_events: Option<Sender<DebugReply>>) -> Self {
+        self.debug_events = debug_events;
+        self
+    }
+
     fn build(self) -> Gameboy {
         debug_assert!(self.cpu.is_some(), "No CPU specified on builder.");
         debug_assert!(self.memory.is_some(), "No Memory specified on builder.");
         debug_assert!(self.joypad.is_some(), "No Joypad specified on builder.");
+        debug_assert!(self.input.is_some(), "No InputSource specified on builder.");
 
         Gameboy {
             cpu: self.cpu.unwrap(),
             memory: self.memory.unwrap(),
             joypad: self.joypad.unwrap(),
+            input: self.input.unwrap(),
+            save_directory: self.save_directory,
+            rewind: self.rewind,
+            rewinding: false,
+            pacer: FramePacer::new(EmulationSpeed::default()),
+            pause_pending: false,
+            buttons: Buttons::empty(),
+            movie: None,
+            movie_slot: None,
+            playback_save_ram: None,
+            power_on_state: Vec::new(),
             clock: 4560,
+            frame_clock: 0,
+            tick_cycles: GlobalConstants::CYCLE_RESOLUTION,
+            debugger: Debugger::new(),
+            debug_events: self.debug_events,
+            debug_request: None,
+            ram_search: RamSearch::default(),
         }
     }
 }
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use super::{joypad::Buttons, memory::Cheats, CheatCode};
+
+/// Identifies a file as an emyco input movie.
+const MOVIE_MAGIC: &[u8; 8] = b"EMYCO-MV";
+
+/// The current version of the movie format.
+pub const MOVIE_VERSION: u32 = 2;
+
+/// A movie that is currently being recorded or played back.
+pub enum MovieSession {
+    Recording(Movie),
+    Playing {
+        movie: Movie,
+        frame: usize,
+        /// The save RAM from before playback, restored once it stops.
+        save_ram: Vec<u8>,
+        /// The cheats from before playback, which give way to the movie's until it stops.
+        cheats: Cheats,
+    },
+}
+
+/// A recording of the buttons held on every frame of a run, starting from power on. Together with
+/// the initial save RAM, RTC seed and enabled cheats, replaying the inputs reproduces the run
+/// exactly.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Movie {
+    rom_hash: [u8; 20],
+    save_ram: Vec<u8>,
+    rtc_seed: i64,
+    cheats: Vec<CheatCode>,
+    inputs: Vec<Buttons>,
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], save_ram: Vec<u8>, rtc_seed: i64, cheats: Vec<CheatCode>) -> Self {
+        Movie {
+            rom_hash: Self::hash(rom),
+            save_ram,
+            rtc_seed,
+            cheats,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// The SHA-1 hash of a ROM, used to verify that a movie is replayed against the ROM it was
+    /// recorded with.
+    pub fn hash(rom: &[u8]) -> [u8; 20] {
+        Sha1::digest(rom).into()
+    }
+
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        self.rom_hash == Self::hash(rom)
+    }
+
+    pub fn save_ram(&self) -> &[u8] {
+        &self.save_ram
+    }
+
+    pub fn rtc_seed(&self) -> i64 {
+        self.rtc_seed
+    }
+
+    /// The cheats that were enabled throughout the recording.
+    pub fn cheats(&self) -> &[CheatCode] {
+        &self.cheats
+    }
+
+    /// The buttons held on the given frame, or None once the movie has ended.
+    pub fn input(&self, frame: usize) -> Option<Buttons> {
+        self.inputs.get(frame).copied()
+    }
+
+    pub fn push_input(&mut self, buttons: Buttons) {
+        self.inputs.push(buttons);
+    }
+
+    /// The number of frames of input recorded.
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Encodes this movie in the versioned movie format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut bytes = Vec::from(*MOVIE_MAGIC);
+        bytes.extend(MOVIE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+
+        Ok(bytes)
+    }
+
+    /// Decodes a movie previously encoded with to_bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let header_length = MOVIE_MAGIC.len() + 4;
+        if bytes.len() < header_length || &bytes[..MOVIE_MAGIC.len()] != MOVIE_MAGIC {
+            bail!("Data is not an emyco movie.");
+        }
+
+        let version = u32::from_le_bytes(
+            bytes[MOVIE_MAGIC.len()..header_length]
+                .try_into()
+                .map_err(|_| anyhow!("Movie header is truncated."))?,
+        );
+        if version != MOVIE_VERSION {
+            bail!(
+                "Movie version {} is not supported. Expected version {}.",
+                version,
+                MOVIE_VERSION
+            );
+        }
+
+        Ok(bincode::deserialize(&bytes[header_length..])?)
+    }
+}
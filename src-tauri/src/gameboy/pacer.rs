@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+use crate::emulator::EmulationSpeed;
+
+use super::GlobalConstants;
+
+/// The time a FramePacer paces emulation against.
+pub trait PacerClock {
+    fn now(&self) -> Instant;
+
+    /// Blocks for the given duration.
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// Wall-clock time, sleeping the emulator thread.
+#[derive(Debug, Default)]
+pub struct SystemPacerClock;
+
+impl PacerClock for SystemPacerClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Paces emulation against wall-clock time, so that a given number of emulated t-cycles takes as
+/// long as it would on real hardware, scaled by the current speed.
+pub struct FramePacer {
+    speed: EmulationSpeed,
+    deadline: Instant,
+    clock: Box<dyn PacerClock>,
+}
+
+impl FramePacer {
+    /// If emulation falls further behind than this, the deadline is reset rather than running
+    /// flat out to catch up. This happens after pauses, or when the host is too slow.
+    pub const MAX_LAG: Duration = Duration::from_millis(100);
+
+    pub fn new(speed: EmulationSpeed) -> Self {
+        Self::with_clock(speed, Box::new(SystemPacerClock))
+    }
+
+    /// Constructs a pacer that paces against the provided clock rather than wall-clock time.
+    pub fn with_clock(speed: EmulationSpeed, clock: Box<dyn PacerClock>) -> Self {
+        FramePacer {
+            speed: speed.clamped(),
+            deadline: clock.now(),
+            clock,
+        }
+    }
+
+    /// Changes the speed, clamping any multiplier to the supported range so that pacing never
+    /// divides by zero or a negative.
+    pub fn set_speed(&mut self, speed: EmulationSpeed) {
+        self.speed = speed.clamped();
+        self.reset();
+    }
+
+    /// Restarts pacing from the current instant, discarding any accumulated lag.
+    pub fn reset(&mut self) {
+        self.deadline = self.clock.now();
+    }
+
+    /// Called after `cycles` t-cycles have been emulated. Sleeps until the moment those cycles
+    /// would have finished on real hardware at the current speed.
+    pub fn wait(&mut self, cycles: u32) {
+        let EmulationSpeed::Multiplier(multiplier) = self.speed else {
+            return;
+        };
+
+        let seconds = cycles as f64 / GlobalConstants::SYSTEM_CLOCK_RATE as f64;
+        self.deadline += Duration::from_secs_f64(seconds / multiplier as f64);
+
+        let now = self.clock.now();
+        if self.deadline > now {
+            self.clock.sleep(self.deadline - now);
+        } else if now - self.deadline > Self::MAX_LAG {
+            self.deadline = now;
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use bitflags::bitflags;
 use log::trace;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 use crate::gameboy::display::Color;
@@ -8,8 +9,15 @@ use super::{
     cpu::OperationQueue,
     display::Display,
     memory::{Interrupt, Register},
+    sgb::{SGB, SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH, TRANSFER_SIZE},
+    state::Snapshot,
+    Model,
 };
 
+mod viewer;
+
+pub use viewer::{OamEntry, TilemapImage, ViewerImage, ViewerPalette};
+
 const SCANLINE_CYCLES: u32 = 456;
 
 const LCDC_ADDRESS: u16 = 0xFF40;
@@ -23,6 +31,17 @@ const OBP0_ADDRESS: u16 = 0xFF48;
 const OBP1_ADDRESS: u16 = 0xFF49;
 const WY_ADDRESS: u16 = 0xFF4A;
 const WX_ADDRESS: u16 = 0xFF4B;
+const VBK_ADDRESS: u16 = 0xFF4F;
+const BCPS_ADDRESS: u16 = 0xFF68;
+const BCPD_ADDRESS: u16 = 0xFF69;
+const OCPS_ADDRESS: u16 = 0xFF6A;
+const OCPD_ADDRESS: u16 = 0xFF6B;
+
+/// The RGB555 colors the four DMG shades are displayed as, from lightest to darkest.
+pub(super) const DMG_COLORS: [u16; 4] = [0x06F3, 0x06B1, 0x1986, 0x04E1];
+
+/// The size of a single VRAM bank. CGB mode has two.
+const VRAM_BANK_SIZE: usize = 0x2000;
 
 const OPERATION_QUEUE_CAPACITY: usize = 64;
 
@@ -30,7 +49,7 @@ const OPERATION_QUEUE_CAPACITY: usize = 64;
 pub struct PPU {
     lcd_state: LcdState,
     pending_interrupts: Option<Interrupt>,
-    vram: [u8; 8192],
+    vram: [u8; 2 * VRAM_BANK_SIZE],
     oam: [u8; 160],
     clock: u32,
     scanline_clock: u32,
@@ -45,17 +64,32 @@ pub struct PPU {
     window_mode: bool,
     active_interrupts: STAT,
     registers: InternalRegisters,
+    frame_complete: bool,
+    hblank_started: bool,
+    cgb: bool,
+    sgb: Option<SGB>,
 }
 
 impl PPU {
-    pub fn new(display: Box<dyn Display>) -> Self {
+    /// Constructs a PPU that renders to the provided display. In CGB mode, VRAM bank 1, BG map
+    /// attributes and color palettes are available. In SGB mode, frames are colorized by the SGB
+    /// and rendered within its border.
+    pub fn new(mut display: Box<dyn Display>, model: Model) -> Self {
         let mut operation_queue = OperationQueue::new();
         operation_queue.push_back(Operation::NewFrame);
 
+        let sgb = match model {
+            Model::SGB => {
+                display.resize(SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT);
+                Some(SGB::new())
+            }
+            _ => None,
+        };
+
         PPU {
             lcd_state: LcdState::Disabled,
             pending_interrupts: None,
-            vram: [0; 8192],
+            vram: [0; 2 * VRAM_BANK_SIZE],
             oam: [0; 160],
             clock: 0,
             scanline_clock: 0,
@@ -70,9 +104,59 @@ impl PPU {
             window_mode: false,
             active_interrupts: STAT::empty(),
             registers: InternalRegisters::new(),
+            frame_complete: false,
+            hblank_started: false,
+            cgb: model == Model::CGB,
+            sgb,
         }
     }
 
+    /// The Super Game Boy, which receives command packets through the joypad register. Only
+    /// present in SGB mode.
+    pub fn sgb(&self) -> Option<&SGB> {
+        self.sgb.as_ref()
+    }
+
+    pub fn sgb_mut(&mut self) -> Option<&mut SGB> {
+        self.sgb.as_mut()
+    }
+
+    /// The tile data of the first 256 background tiles on screen, in the order they are
+    /// displayed. SGB transfers send their data this way.
+    fn screen_tile_data(&self) -> Vec<u8> {
+        let tilemap_base_address: u16 = match self.registers.lcdc.contains(LCDC::BG_TILEMAP_SELECT)
+        {
+            true => 0x9C00,
+            false => 0x9800,
+        };
+
+        (0..(TRANSFER_SIZE / 16) as u16)
+            .flat_map(|tile| {
+                let tile_number =
+                    self.read_vram(0, tilemap_base_address + (tile / 20) * 32 + tile % 20);
+                let mut base_address = 0x8000;
+                if tile_number & 0x80 == 0
+                    && !self.registers.lcdc.contains(LCDC::TILE_ADDRESSING_MODE)
+                {
+                    base_address |= 0x1000;
+                }
+                let tile_address = base_address + ((tile_number as u16) << 4);
+
+                (0..16).map(move |offset| self.read_vram(0, tile_address + offset))
+            })
+            .collect()
+    }
+
+    /// Returns true if VBlank has been entered since this was last called.
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    /// Returns true if HBlank has been entered on a visible scanline since this was last called.
+    pub fn take_hblank_started(&mut self) -> bool {
+        std::mem::take(&mut self.hblank_started)
+    }
+
     #[inline]
     fn fetch_sprite(&self, address: u16) -> Sprite {
         debug_assert!(
@@ -96,9 +180,56 @@ impl PPU {
             x,
             tile_index,
             attributes,
+            index: ((address - 0xFE00) / 4) as u8,
+        }
+    }
+
+    /// Reads VRAM from the given bank, regardless of which bank is mapped for the CPU.
+    #[inline]
+    fn read_vram(&self, bank: u8, address: u16) -> u8 {
+        self.vram[bank as usize * VRAM_BANK_SIZE + (address - 0x8000) as usize]
+    }
+
+    /// A whole VRAM bank, regardless of which bank is mapped for the CPU. Only bank 0 exists
+    /// outside CGB mode.
+    pub fn vram_bank(&self, bank: u8) -> Option<&[u8]> {
+        match bank <= self.cgb as u8 {
+            true => Some(&self.vram[bank as usize * VRAM_BANK_SIZE..][..VRAM_BANK_SIZE]),
+            false => None,
+        }
+    }
+
+    pub fn vram_bank_mut(&mut self, bank: u8) -> Option<&mut [u8]> {
+        match bank <= self.cgb as u8 {
+            true => Some(&mut self.vram[bank as usize * VRAM_BANK_SIZE..][..VRAM_BANK_SIZE]),
+            false => None,
         }
     }
 
+    /// The attributes of the tile at the given tile map address, which are stored at the same
+    /// address in VRAM bank 1. DMG mode has no tile attributes.
+    #[inline]
+    fn tile_attributes(&self, tilemap_address: u16) -> TileAttributes {
+        match self.cgb {
+            true => TileAttributes::from_bits_truncate(self.read_vram(1, tilemap_address)),
+            false => TileAttributes::empty(),
+        }
+    }
+
+    /// Reads a row of a tile, applying the bank and horizontal flip from its attributes.
+    #[inline]
+    fn read_tile_row(&self, tile_address: u16, bank: u8, x_flip: bool) -> (u8, u8) {
+        let mut low = self.read_vram(bank, tile_address);
+        let mut high = self.read_vram(bank, tile_address.wrapping_add(1));
+
+        if x_flip {
+            low = low.reverse_bits();
+            high = high.reverse_bits();
+        }
+
+        (low, high)
+    }
+
     #[inline]
     fn enabled(&self) -> bool {
         self.registers.lcdc.contains(LCDC::LCD_DISPLAY_ENABLE)
@@ -113,7 +244,7 @@ impl PPU {
     }
 
     #[inline]
-    fn get_bg_tile_number(&self) -> u8 {
+    fn get_bg_tilemap_address(&self) -> u16 {
         let tilemap_base_address: u16 = match self.registers.lcdc.contains(LCDC::BG_TILEMAP_SELECT)
         {
             true => 0x9C00,
@@ -125,13 +256,11 @@ impl PPU {
             ((((self.registers.ly as u16) + (self.registers.scy as u16)) / 8) & 0x1F) << 5;
 
         // let tilemap_offset = (x_coordinate + 32 * (y_coordinate / 8)) & 0x3FF;
-        let tilemap_address = tilemap_base_address + x_coordinate + y_coordinate;
-
-        self.read(tilemap_address)
+        tilemap_base_address + x_coordinate + y_coordinate
     }
 
     #[inline]
-    fn fetch_bg_tile(&self, tile_number: u8) -> (u8, u8) {
+    fn fetch_bg_tile(&self, tile_number: u8, attributes: TileAttributes) -> (u8, u8) {
         let mut base_address = 0x8000;
 
         if tile_number & 0x80 == 0 && !self.registers.lcdc.contains(LCDC::TILE_ADDRESSING_MODE) {
@@ -139,8 +268,11 @@ impl PPU {
         }
 
         let tile_offset = (tile_number as u16) << 4;
-        let y_offset =
-            (((self.registers.ly as u16).wrapping_add(self.registers.scy as u16)) % 8) << 1;
+        let mut row = (self.registers.ly as u16).wrapping_add(self.registers.scy as u16) % 8;
+        if attributes.contains(TileAttributes::Y_FLIP) {
+            row = 7 - row;
+        }
+        let y_offset = row << 1;
 
         let tile_address = base_address + tile_offset + y_offset;
 
@@ -153,14 +285,15 @@ impl PPU {
             tile_address
         );
 
-        let low = self.read(tile_address);
-        let high = self.read(tile_address.wrapping_add(1));
-
-        (low, high)
+        self.read_tile_row(
+            tile_address,
+            attributes.contains(TileAttributes::VRAM_BANK) as u8,
+            attributes.contains(TileAttributes::X_FLIP),
+        )
     }
 
     #[inline]
-    fn get_window_tile_number(&self) -> u8 {
+    fn get_window_tilemap_address(&self) -> u16 {
         let tilemap_base_address: u16 =
             match self.registers.lcdc.contains(LCDC::WINDOW_TILEMAP_SELECT) {
                 true => 0x9C00,
@@ -172,13 +305,11 @@ impl PPU {
         let x_coordinate = (window_x / 8) & 0x1F;
         let y_coordinate = (((self.wlc as u16) / 8) & 0x1F) << 5;
 
-        let tilemap_address = x_coordinate | y_coordinate | tilemap_base_address;
-
-        self.read(tilemap_address)
+        x_coordinate | y_coordinate | tilemap_base_address
     }
 
     #[inline]
-    fn fetch_window_tile(&self, tile_number: u8) -> (u8, u8) {
+    fn fetch_window_tile(&self, tile_number: u8, attributes: TileAttributes) -> (u8, u8) {
         let mut base_address = 0x8000;
 
         if tile_number & 0x80 == 0 && !self.registers.lcdc.contains(LCDC::TILE_ADDRESSING_MODE) {
@@ -186,7 +317,11 @@ impl PPU {
         }
 
         let tile_offset = (tile_number as u16) << 4;
-        let y_offset = ((self.wlc as u16) % 8) << 1;
+        let mut row = (self.wlc as u16) % 8;
+        if attributes.contains(TileAttributes::Y_FLIP) {
+            row = 7 - row;
+        }
+        let y_offset = row << 1;
 
         let tile_address = base_address + tile_offset + y_offset;
 
@@ -199,10 +334,11 @@ impl PPU {
             tile_address
         );
 
-        let low = self.read(tile_address);
-        let high = self.read(tile_address.wrapping_add(1));
-
-        (low, high)
+        self.read_tile_row(
+            tile_address,
+            attributes.contains(TileAttributes::VRAM_BANK) as u8,
+            attributes.contains(TileAttributes::X_FLIP),
+        )
     }
 
     #[inline]
@@ -232,19 +368,20 @@ impl PPU {
 
         let tile_address = 0x8000 + (tile_index as u16 * 16) + mem_offset;
 
-        let mut low = self.read(tile_address);
-        let mut high = self.read(tile_address.wrapping_add(1));
-
-        if sprite.attributes.contains(SpriteAttributes::X_FLIP) {
-            low = low.reverse_bits();
-            high = high.reverse_bits();
-        }
+        let bank = match self.cgb {
+            true => sprite.attributes.contains(SpriteAttributes::VRAM_BANK) as u8,
+            false => 0,
+        };
 
-        (low, high)
+        self.read_tile_row(
+            tile_address,
+            bank,
+            sprite.attributes.contains(SpriteAttributes::X_FLIP),
+        )
     }
 
     #[inline]
-    fn generate_bg_pixels(&self, low: u8, high: u8) -> [Pixel; 8] {
+    fn generate_bg_pixels(&self, low: u8, high: u8, attributes: TileAttributes) -> [Pixel; 8] {
         let mut pixels = [Pixel::default(); 8];
         for (i, pixel) in pixels.iter_mut().enumerate() {
             let low_bit = (low >> (7 - i)) & 1;
@@ -253,7 +390,11 @@ impl PPU {
             let color = (high_bit << 1) | low_bit;
 
             pixel.color = color;
-            pixel.palette = Palette::Bgp;
+            pixel.priority = attributes.contains(TileAttributes::PRIORITY);
+            pixel.palette = match self.cgb {
+                true => Palette::Background((attributes & TileAttributes::PALETTE).bits()),
+                false => Palette::Bgp,
+            };
         }
 
         pixels
@@ -270,9 +411,16 @@ impl PPU {
 
             pixel.color = color;
             pixel.priority = sprite.attributes.contains(SpriteAttributes::PRIORITY);
-            pixel.palette = match sprite.attributes.contains(SpriteAttributes::DMG_PALETTE) {
-                false => Palette::Obp0,
-                true => Palette::Obp1,
+            pixel.index = sprite.index;
+            pixel.palette = match (
+                self.cgb,
+                sprite.attributes.contains(SpriteAttributes::DMG_PALETTE),
+            ) {
+                (true, _) => {
+                    Palette::Object((sprite.attributes & SpriteAttributes::CGB_PALETTE).bits())
+                }
+                (false, false) => Palette::Obp0,
+                (false, true) => Palette::Obp1,
             };
         }
 
@@ -340,7 +488,8 @@ impl Register for PPU {
     fn read(&self, address: u16) -> u8 {
         match address {
             0xFF40..=0xFF4B => self.registers.read(address),
-            0x8000..=0x9FFF => self.vram[(address - 0x8000) as usize],
+            VBK_ADDRESS | BCPS_ADDRESS..=OCPD_ADDRESS if self.cgb => self.registers.read(address),
+            0x8000..=0x9FFF => self.read_vram(self.registers.vbk, address),
             0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize],
             _ => 0xFF,
         }
@@ -370,7 +519,13 @@ impl Register for PPU {
                 }
             }
             0xFF40..=0xFF4B => self.registers.write(address, value),
-            0x8000..=0x9FFF => self.vram[(address - 0x8000) as usize] = value,
+            VBK_ADDRESS | BCPS_ADDRESS..=OCPD_ADDRESS if self.cgb => {
+                self.registers.write(address, value)
+            }
+            0x8000..=0x9FFF => {
+                let bank_offset = self.registers.vbk as usize * VRAM_BANK_SIZE;
+                self.vram[bank_offset + (address - 0x8000) as usize] = value;
+            }
             0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize] = value,
             _ => {}
         }
@@ -520,13 +675,22 @@ impl Register for PPU {
                             );
 
                             let remaining_cycles = SCANLINE_CYCLES - self.scanline_clock;
+                            self.hblank_started = true;
 
                             self.operation_queue.clear();
                             self.operation_queue.push_back(Sleep(remaining_cycles));
                             self.operation_queue.push_back(NewScanline);
                         }
                         VBlank => {
+                            if self.sgb.as_ref().is_some_and(|sgb| sgb.transfer_pending()) {
+                                let data = self.screen_tile_data();
+                                self.sgb.as_mut().unwrap().transfer(&data);
+                            }
+                            if let Some(sgb) = &self.sgb {
+                                sgb.render(self.display.as_mut());
+                            }
                             self.display.present();
+                            self.frame_complete = true;
 
                             self.pending_interrupts
                                 .get_or_insert(Interrupt::empty())
@@ -559,17 +723,21 @@ impl Register for PPU {
                     }
                 }
                 FetchBackgroundPixels => {
-                    let tile_number = self.get_bg_tile_number();
-                    let (tile_low, tile_high) = self.fetch_bg_tile(tile_number);
-                    let pixels = self.generate_bg_pixels(tile_low, tile_high);
+                    let tilemap_address = self.get_bg_tilemap_address();
+                    let tile_number = self.read_vram(0, tilemap_address);
+                    let attributes = self.tile_attributes(tilemap_address);
+                    let (tile_low, tile_high) = self.fetch_bg_tile(tile_number, attributes);
+                    let pixels = self.generate_bg_pixels(tile_low, tile_high, attributes);
 
                     self.bg_fifo.extend(pixels);
                     self.operation_queue.push_back(PopPixels);
                 }
                 FetchWindowPixels => {
-                    let tile_number = self.get_window_tile_number();
-                    let (tile_low, tile_high) = self.fetch_window_tile(tile_number);
-                    let pixels = self.generate_bg_pixels(tile_low, tile_high);
+                    let tilemap_address = self.get_window_tilemap_address();
+                    let tile_number = self.read_vram(0, tilemap_address);
+                    let attributes = self.tile_attributes(tilemap_address);
+                    let (tile_low, tile_high) = self.fetch_window_tile(tile_number, attributes);
+                    let pixels = self.generate_bg_pixels(tile_low, tile_high, attributes);
 
                     self.bg_fifo.extend(pixels);
                     self.operation_queue.push_back(PopPixels);
@@ -602,8 +770,13 @@ impl Register for PPU {
 
                     for (i, pixel) in pixels.iter().enumerate().skip(offscreen_pixels) {
                         // Replace any transparent pixels in the fifo with pixels from this sprite
-                        // Otherwise do nothing (this sprite is behind another)
-                        if self.sprite_fifo[i - offscreen_pixels].color == 0 {
+                        // Otherwise do nothing (this sprite is behind another). On CGB, priority
+                        // is decided by OAM position alone, so an opaque pixel from an earlier
+                        // object also replaces a later object's pixel.
+                        let existing = self.sprite_fifo[i - offscreen_pixels];
+                        if existing.color == 0
+                            || (self.cgb && pixel.color != 0 && pixel.index < existing.index)
+                        {
                             self.sprite_fifo[i - offscreen_pixels] = *pixel;
                         }
                     }
@@ -645,7 +818,25 @@ impl Register for PPU {
                         .pop_front()
                         .expect("Attempted to pop pixel from empty fifo.");
 
+                    let cgb = self.cgb;
                     let merged_pixel = match self.sprite_fifo.pop_front() {
+                        // On CGB, clearing LCDC bit 0 takes priority away from the background and
+                        // window rather than hiding them
+                        Some(sprite_pixel) if cgb && sprite_pixel.color == 0 => bg_pixel,
+                        Some(sprite_pixel)
+                            if cgb
+                                && (!self.registers.lcdc.contains(LCDC::BGW_ENABLE)
+                                    || bg_pixel.color == 0) =>
+                        {
+                            sprite_pixel
+                        }
+                        Some(sprite_pixel)
+                            if cgb && (bg_pixel.priority || sprite_pixel.priority) =>
+                        {
+                            bg_pixel
+                        }
+                        Some(sprite_pixel) if cgb => sprite_pixel,
+                        None if cgb => bg_pixel,
                         Some(sprite_pixel) if !self.registers.lcdc.contains(LCDC::BGW_ENABLE) => {
                             sprite_pixel
                         }
@@ -659,16 +850,40 @@ impl Register for PPU {
                         None => bg_pixel,
                     };
 
-                    let palette = match merged_pixel.palette {
-                        Palette::Bgp => self.registers.bgp,
-                        Palette::Obp0 => self.registers.obp0,
-                        Palette::Obp1 => self.registers.obp1,
-                    };
-
-                    let color_shift = merged_pixel.color * 2;
-                    let color = ((3 << color_shift) & palette) >> color_shift;
-
-                    self.display.push_pixel(self.lx, self.registers.ly, color);
+                    match merged_pixel.palette {
+                        Palette::Background(palette) => {
+                            let color = self
+                                .registers
+                                .bg_palettes
+                                .color(palette, merged_pixel.color);
+                            self.display.push_pixel(self.lx, self.registers.ly, color);
+                        }
+                        Palette::Object(palette) => {
+                            let color = self
+                                .registers
+                                .obj_palettes
+                                .color(palette, merged_pixel.color);
+                            self.display.push_pixel(self.lx, self.registers.ly, color);
+                        }
+                        dmg_palette => {
+                            let palette = match dmg_palette {
+                                Palette::Obp0 => self.registers.obp0,
+                                Palette::Obp1 => self.registers.obp1,
+                                _ => self.registers.bgp,
+                            };
+                            let shade = dmg_shade(palette, merged_pixel.color);
+
+                            match &mut self.sgb {
+                                // The SGB colorizes the whole screen when the frame is rendered
+                                Some(sgb) => sgb.push_shade(self.lx, self.registers.ly, shade),
+                                None => self.display.push_pixel(
+                                    self.lx,
+                                    self.registers.ly,
+                                    DMG_COLORS[shade as usize],
+                                ),
+                            }
+                        }
+                    }
 
                     // LX Increment
                     self.lx += 1;
@@ -735,7 +950,84 @@ impl Register for PPU {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// Looks up a color index in a DMG palette register, returning the shade it is displayed as.
+#[inline]
+fn dmg_shade(palette: u8, color: u8) -> u8 {
+    let color_shift = color * 2;
+    ((3 << color_shift) & palette) >> color_shift
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PPUSnapshot {
+    lcd_state: LcdState,
+    pending_interrupts: Option<Interrupt>,
+    vram: Vec<u8>,
+    oam: Vec<u8>,
+    clock: u32,
+    scanline_clock: u32,
+    operation_queue: Vec<Operation>,
+    mode: Mode,
+    sprite_buffer: Vec<Sprite>,
+    bg_fifo: VecDeque<Pixel>,
+    sprite_fifo: VecDeque<Pixel>,
+    lx: u8,
+    wlc: u8,
+    window_mode: bool,
+    active_interrupts: STAT,
+    registers: InternalRegisters,
+    sgb: Option<SGB>,
+}
+
+impl Snapshot for PPU {
+    type State = PPUSnapshot;
+
+    fn save_state(&self) -> PPUSnapshot {
+        PPUSnapshot {
+            lcd_state: self.lcd_state,
+            pending_interrupts: self.pending_interrupts,
+            vram: self.vram.to_vec(),
+            oam: self.oam.to_vec(),
+            clock: self.clock,
+            scanline_clock: self.scanline_clock,
+            operation_queue: self.operation_queue.iter().copied().collect(),
+            mode: self.mode,
+            sprite_buffer: self.sprite_buffer.clone(),
+            bg_fifo: self.bg_fifo.clone(),
+            sprite_fifo: self.sprite_fifo.clone(),
+            lx: self.lx,
+            wlc: self.wlc,
+            window_mode: self.window_mode,
+            active_interrupts: self.active_interrupts,
+            registers: self.registers.clone(),
+            sgb: self.sgb.clone(),
+        }
+    }
+
+    fn load_state(&mut self, state: PPUSnapshot) {
+        self.lcd_state = state.lcd_state;
+        self.pending_interrupts = state.pending_interrupts;
+        let vram_length = self.vram.len().min(state.vram.len());
+        self.vram[..vram_length].copy_from_slice(&state.vram[..vram_length]);
+        let oam_length = self.oam.len().min(state.oam.len());
+        self.oam[..oam_length].copy_from_slice(&state.oam[..oam_length]);
+        self.clock = state.clock;
+        self.scanline_clock = state.scanline_clock;
+        self.operation_queue.clear();
+        self.operation_queue.extend(state.operation_queue);
+        self.mode = state.mode;
+        self.sprite_buffer = state.sprite_buffer;
+        self.bg_fifo = state.bg_fifo;
+        self.sprite_fifo = state.sprite_fifo;
+        self.lx = state.lx;
+        self.wlc = state.wlc;
+        self.window_mode = state.window_mode;
+        self.active_interrupts = state.active_interrupts;
+        self.registers = state.registers;
+        self.sgb = state.sgb;
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum Mode {
     OAMScan,
     Drawing,
@@ -743,7 +1035,7 @@ enum Mode {
     VBlank,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum Operation {
     NewFrame,
     NewScanline,
@@ -774,19 +1066,22 @@ impl Operation {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 struct Sprite {
     y: u8,
     x: u8,
     tile_index: u8,
     attributes: SpriteAttributes,
+    /// The position of this sprite in OAM, which decides priority between sprites on CGB
+    index: u8,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 struct Pixel {
     color: u8,
     palette: Palette,
     priority: bool,
+    index: u8,
 }
 
 impl Default for Pixel {
@@ -795,18 +1090,23 @@ impl Default for Pixel {
             color: 0,
             palette: Palette::Obp0,
             priority: false,
+            index: 0,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum Palette {
     Bgp,
     Obp0,
     Obp1,
+    /// One of the eight CGB background palettes
+    Background(u8),
+    /// One of the eight CGB object palettes
+    Object(u8),
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum LcdState {
     Enabled,
     Disabled,
@@ -815,7 +1115,7 @@ enum LcdState {
 bitflags! {
 
     #[repr(transparent)]
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
     struct SpriteAttributes: u8 {
         /// If set, BG and window colors 1-3 are drawn over this object
         const PRIORITY = 0b1000_0000;
@@ -828,10 +1128,36 @@ bitflags! {
 
         /// Selects which OBP palette to use. 0 = OBP0, 1 = OBP1
         const DMG_PALETTE = 0b0001_0000;
+
+        /// CGB only. Selects which VRAM bank the sprite's tile is read from
+        const VRAM_BANK = 0b0000_1000;
+
+        /// CGB only. Selects which of the eight object palettes to use
+        const CGB_PALETTE = 0b0000_0111;
     }
 
+    /// The attributes of a background or window tile, stored in VRAM bank 1 in CGB mode.
     #[repr(transparent)]
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+    struct TileAttributes: u8 {
+        /// If set, BG and window colors 1-3 are drawn over all objects
+        const PRIORITY = 0b1000_0000;
+
+        /// If set, this tile is flipped vertically
+        const Y_FLIP = 0b0100_0000;
+
+        /// If set, this tile is flipped horizontally
+        const X_FLIP = 0b0010_0000;
+
+        /// Selects which VRAM bank the tile is read from
+        const VRAM_BANK = 0b0000_1000;
+
+        /// Selects which of the eight background palettes to use
+        const PALETTE = 0b0000_0111;
+    }
+
+    #[repr(transparent)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
     struct LCDC: u8 {
         /// Controls whether the PPU should be active at all. 1 = Enabled, 0 = Disabled
         const LCD_DISPLAY_ENABLE = 0b1000_0000;
@@ -864,7 +1190,7 @@ bitflags! {
     }
 
     #[repr(transparent)]
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
     struct STAT: u8 {
         /// Determines whether the LY=LYC triggers an LCD interrupt
         const LYC_INTERRUPT_ENABLE = 0b0100_0000;
@@ -886,6 +1212,7 @@ bitflags! {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InternalRegisters {
     ly: u8,
     lyc: u8,
@@ -898,6 +1225,9 @@ pub struct InternalRegisters {
     obp0: u8,
     obp1: u8,
     bgp: u8,
+    vbk: u8,
+    bg_palettes: ColorPalettes,
+    obj_palettes: ColorPalettes,
 }
 
 impl InternalRegisters {
@@ -914,6 +1244,10 @@ impl InternalRegisters {
             obp0: 0,
             obp1: 0,
             bgp: 0,
+            vbk: 0,
+            // The CGB boot ROM initializes every background color to white
+            bg_palettes: ColorPalettes::new(0xFF),
+            obj_palettes: ColorPalettes::new(0x00),
         }
     }
 }
@@ -932,6 +1266,11 @@ impl Register for InternalRegisters {
             OBP0_ADDRESS => self.obp0,
             OBP1_ADDRESS => self.obp1,
             BGP_ADDRESS => self.bgp,
+            VBK_ADDRESS => 0xFE | self.vbk,
+            BCPS_ADDRESS => self.bg_palettes.read_specification(),
+            BCPD_ADDRESS => self.bg_palettes.read_data(),
+            OCPS_ADDRESS => self.obj_palettes.read_specification(),
+            OCPD_ADDRESS => self.obj_palettes.read_data(),
             _ => 0xFF,
         }
     }
@@ -952,7 +1291,58 @@ impl Register for InternalRegisters {
             OBP0_ADDRESS => self.obp0 = value,
             OBP1_ADDRESS => self.obp1 = value,
             BGP_ADDRESS => self.bgp = value,
+            VBK_ADDRESS => self.vbk = value & 0x01,
+            BCPS_ADDRESS => self.bg_palettes.write_specification(value),
+            BCPD_ADDRESS => self.bg_palettes.write_data(value),
+            OCPS_ADDRESS => self.obj_palettes.write_specification(value),
+            OCPD_ADDRESS => self.obj_palettes.write_data(value),
             _ => {}
         }
     }
 }
+
+/// CGB color palette RAM, holding eight palettes of four RGB555 colors. It is accessed through a
+/// specification register, which selects a byte and can increment after each data write, and a
+/// data register.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorPalettes {
+    data: Vec<u8>,
+    index: u8,
+    auto_increment: bool,
+}
+
+impl ColorPalettes {
+    pub fn new(fill: u8) -> Self {
+        ColorPalettes {
+            data: vec![fill; 64],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    /// The RGB555 color at the given index of a palette.
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let offset = (palette as usize * 8) + (color as usize * 2);
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]]) & 0x7FFF
+    }
+
+    fn read_specification(&self) -> u8 {
+        ((self.auto_increment as u8) << 7) | 0b0100_0000 | self.index
+    }
+
+    fn write_specification(&mut self, value: u8) {
+        self.index = value & 0x3F;
+        self.auto_increment = value & 0x80 != 0;
+    }
+
+    fn read_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    fn write_data(&mut self, value: u8) {
+        self.data[self.index as usize] = value;
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+
+use super::{dmg_shade, SpriteAttributes, TileAttributes, DMG_COLORS, LCDC, PPU};
+
+/// Each VRAM bank holds 384 tiles, drawn 16 to a row as they are laid out in memory.
+const TILE_COUNT: u16 = 384;
+const TILES_PER_ROW: u16 = 16;
+
+/// Tile maps are 32x32 tiles.
+const TILEMAP_SIZE: u16 = 32;
+
+const SCREEN_WIDTH: u16 = 160;
+const SCREEN_HEIGHT: u16 = 144;
+
+/// The viewport is outlined in red.
+const VIEWPORT_COLOR: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+
+/// An image rendered by a graphics viewer, as RGBA8 pixels row by row. The pixels are left out
+/// when serialized, as they reach the webview as raw bytes instead.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ViewerImage {
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip)]
+    pub pixels: Vec<u8>,
+}
+
+impl ViewerImage {
+    fn new(width: u32, height: u32) -> Self {
+        ViewerImage {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) {
+        let offset = ((y * self.width + x) * 4) as usize;
+        self.pixels[offset..offset + 4].copy_from_slice(&color);
+    }
+
+    /// The RGBA8 color of a pixel.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * self.width + x) * 4) as usize;
+        self.pixels[offset..offset + 4].try_into().unwrap()
+    }
+}
+
+/// The palette the tile viewer draws tiles with.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum ViewerPalette {
+    /// The four DMG shades in order, ignoring the palette registers.
+    Grayscale,
+    Bgp,
+    Obp0,
+    Obp1,
+    /// One of the eight CGB background palettes.
+    Background(u8),
+    /// One of the eight CGB object palettes.
+    Object(u8),
+}
+
+/// A rendering of one of the two 32x32 tile maps.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TilemapImage {
+    /// The tile map at 256x256. If the background is drawn from it, the area visible on screen is
+    /// outlined, wrapping around the edges as it does on hardware.
+    pub image: ViewerImage,
+    /// 0x9800 or 0x9C00.
+    pub address: u16,
+    /// Whether LCDC selects this tile map for the background.
+    pub background: bool,
+    /// Whether LCDC selects this tile map for the window.
+    pub window: bool,
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+}
+
+/// An OAM entry, with its attributes decoded.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OamEntry {
+    pub index: u8,
+    /// The position as stored, which is offset by (8, 16) from the position on screen.
+    pub x: u8,
+    pub y: u8,
+    pub tile_index: u8,
+    /// If set, BG and window colors 1-3 are drawn over this object.
+    pub priority: bool,
+    pub y_flip: bool,
+    pub x_flip: bool,
+    /// The DMG object palette, 0 for OBP0 or 1 for OBP1.
+    pub dmg_palette: u8,
+    /// CGB only.
+    pub vram_bank: u8,
+    /// CGB only.
+    pub cgb_palette: u8,
+    /// The object as drawn, 8x8 or 8x16 depending on LCDC, with color 0 transparent.
+    pub image: ViewerImage,
+}
+
+impl PPU {
+    /// Renders all 384 tiles in a VRAM bank with a palette, 16 tiles across. Only bank 0 exists
+    /// outside CGB mode.
+    pub fn render_tiles(&self, bank: u8, palette: ViewerPalette) -> ViewerImage {
+        let bank = bank & self.cgb as u8;
+        let mut image = ViewerImage::new(
+            (TILES_PER_ROW * 8) as u32,
+            (TILE_COUNT / TILES_PER_ROW * 8) as u32,
+        );
+
+        for tile in 0..TILE_COUNT {
+            let tile_address = 0x8000 + tile * 16;
+            let tile_x = (tile % TILES_PER_ROW * 8) as u32;
+            let tile_y = (tile / TILES_PER_ROW * 8) as u32;
+
+            for y in 0..8 {
+                for x in 0..8 {
+                    let color = self.tile_color(bank, tile_address + y as u16 * 2, x);
+                    image.set_pixel(
+                        tile_x + x as u32,
+                        tile_y + y as u32,
+                        self.viewer_color(palette, color),
+                    );
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Renders the tile map at 0x9800 (0) or 0x9C00 (1) as the background would draw it, with
+    /// the current tile addressing mode and, in CGB mode, tile attributes.
+    pub fn render_tilemap(&self, tilemap: u8) -> TilemapImage {
+        let address: u16 = match tilemap & 0x01 {
+            0 => 0x9800,
+            _ => 0x9C00,
+        };
+        let mut image = ViewerImage::new((TILEMAP_SIZE * 8) as u32, (TILEMAP_SIZE * 8) as u32);
+
+        for row in 0..TILEMAP_SIZE {
+            for column in 0..TILEMAP_SIZE {
+                let tilemap_address = address + row * TILEMAP_SIZE + column;
+                let tile_number = self.read_vram(0, tilemap_address);
+                let attributes = self.tile_attributes(tilemap_address);
+                let tile_address = self.tile_data_address(tile_number);
+                let bank = attributes.contains(TileAttributes::VRAM_BANK) as u8;
+                let palette = match self.cgb {
+                    true => {
+                        ViewerPalette::Background((attributes & TileAttributes::PALETTE).bits())
+                    }
+                    false => ViewerPalette::Bgp,
+                };
+
+                for y in 0..8 {
+                    let tile_y = match attributes.contains(TileAttributes::Y_FLIP) {
+                        true => 7 - y,
+                        false => y,
+                    };
+                    for x in 0..8 {
+                        let tile_x = match attributes.contains(TileAttributes::X_FLIP) {
+                            true => 7 - x,
+                            false => x,
+                        };
+                        let color = self.tile_color(bank, tile_address + tile_y as u16 * 2, tile_x);
+                        image.set_pixel(
+                            (column * 8) as u32 + x as u32,
+                            (row * 8) as u32 + y as u32,
+                            self.viewer_color(palette, color),
+                        );
+                    }
+                }
+            }
+        }
+
+        let lcdc = self.registers.lcdc;
+        let background = lcdc.contains(LCDC::BG_TILEMAP_SELECT) == (address == 0x9C00);
+        let window = lcdc.contains(LCDC::WINDOW_TILEMAP_SELECT) == (address == 0x9C00);
+        let (scx, scy) = (self.registers.scx, self.registers.scy);
+        if background {
+            outline_viewport(&mut image, scx, scy);
+        }
+
+        TilemapImage {
+            image,
+            address,
+            background,
+            window,
+            scx,
+            scy,
+            wx: self.registers.wx,
+            wy: self.registers.wy,
+        }
+    }
+
+    /// Decodes all 40 OAM entries and renders the object each one draws.
+    pub fn oam_entries(&self) -> Vec<OamEntry> {
+        let height = self.sprite_height();
+
+        (0..40)
+            .map(|index| {
+                let sprite = self.fetch_sprite(0xFE00 + index * 4);
+                let attributes = sprite.attributes;
+                let bank = match self.cgb {
+                    true => attributes.contains(SpriteAttributes::VRAM_BANK) as u8,
+                    false => 0,
+                };
+                let dmg_palette = attributes.contains(SpriteAttributes::DMG_PALETTE) as u8;
+                let cgb_palette = (attributes & SpriteAttributes::CGB_PALETTE).bits();
+                let palette = match (self.cgb, dmg_palette) {
+                    (true, _) => ViewerPalette::Object(cgb_palette),
+                    (false, 0) => ViewerPalette::Obp0,
+                    (false, _) => ViewerPalette::Obp1,
+                };
+                // Tall objects ignore the lowest bit of the tile index
+                let tile_index = match height {
+                    16 => sprite.tile_index & 0xFE,
+                    _ => sprite.tile_index,
+                };
+                let tile_address = 0x8000 + tile_index as u16 * 16;
+
+                let mut image = ViewerImage::new(8, height as u32);
+                for y in 0..height {
+                    let tile_y = match attributes.contains(SpriteAttributes::Y_FLIP) {
+                        true => height - 1 - y,
+                        false => y,
+                    };
+                    for x in 0..8 {
+                        let tile_x = match attributes.contains(SpriteAttributes::X_FLIP) {
+                            true => 7 - x,
+                            false => x,
+                        };
+                        let color = self.tile_color(bank, tile_address + tile_y as u16 * 2, tile_x);
+                        if color != 0 {
+                            image.set_pixel(x as u32, y as u32, self.viewer_color(palette, color));
+                        }
+                    }
+                }
+
+                OamEntry {
+                    index: sprite.index,
+                    x: sprite.x,
+                    y: sprite.y,
+                    tile_index: sprite.tile_index,
+                    priority: attributes.contains(SpriteAttributes::PRIORITY),
+                    y_flip: attributes.contains(SpriteAttributes::Y_FLIP),
+                    x_flip: attributes.contains(SpriteAttributes::X_FLIP),
+                    dmg_palette,
+                    vram_bank: attributes.contains(SpriteAttributes::VRAM_BANK) as u8,
+                    cgb_palette,
+                    image,
+                }
+            })
+            .collect()
+    }
+
+    /// The address of the data for a background or window tile, following the LCDC addressing
+    /// mode.
+    fn tile_data_address(&self, tile_number: u8) -> u16 {
+        let mut address = 0x8000 + tile_number as u16 * 16;
+        if tile_number & 0x80 == 0 && !self.registers.lcdc.contains(LCDC::TILE_ADDRESSING_MODE) {
+            address |= 0x1000;
+        }
+        address
+    }
+
+    /// The color index of pixel `x` in the tile row at the given address.
+    fn tile_color(&self, bank: u8, row_address: u16, x: u8) -> u8 {
+        let (low, high) = self.read_tile_row(row_address, bank, false);
+        let bit = 7 - x;
+        ((low >> bit) & 0x01) | (((high >> bit) & 0x01) << 1)
+    }
+
+    fn viewer_color(&self, palette: ViewerPalette, color: u8) -> [u8; 4] {
+        let color = match palette {
+            ViewerPalette::Grayscale => DMG_COLORS[color as usize],
+            ViewerPalette::Bgp => DMG_COLORS[dmg_shade(self.registers.bgp, color) as usize],
+            ViewerPalette::Obp0 => DMG_COLORS[dmg_shade(self.registers.obp0, color) as usize],
+            ViewerPalette::Obp1 => DMG_COLORS[dmg_shade(self.registers.obp1, color) as usize],
+            ViewerPalette::Background(palette) => {
+                self.registers.bg_palettes.color(palette & 0x07, color)
+            }
+            ViewerPalette::Object(palette) => {
+                self.registers.obj_palettes.color(palette & 0x07, color)
+            }
+        };
+
+        rgba(color)
+    }
+}
+
+/// Expands an RGB555 color, with red in the low bits, to opaque RGBA8.
+fn rgba(color: u16) -> [u8; 4] {
+    let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+    [
+        expand(color & 0x1F),
+        expand((color >> 5) & 0x1F),
+        expand((color >> 10) & 0x1F),
+        0xFF,
+    ]
+}
+
+/// Outlines the 160x144 area of a 256x256 tile map that is on screen when scrolled to (scx, scy).
+fn outline_viewport(image: &mut ViewerImage, scx: u8, scy: u8) {
+    let (left, top) = (scx as u16, scy as u16);
+    let right = (left + SCREEN_WIDTH - 1) % 256;
+    let bottom = (top + SCREEN_HEIGHT - 1) % 256;
+
+    for x in 0..SCREEN_WIDTH {
+        let x = ((left + x) % 256) as u32;
+        image.set_pixel(x, top as u32, VIEWPORT_COLOR);
+        image.set_pixel(x, bottom as u32, VIEWPORT_COLOR);
+    }
+    for y in 0..SCREEN_HEIGHT {
+        let y = ((top + y) % 256) as u32;
+        image.set_pixel(left as u32, y, VIEWPORT_COLOR);
+        image.set_pixel(right as u32, y, VIEWPORT_COLOR);
+    }
+}
@@ -0,0 +1,357 @@
+use std::{fmt::Debug, fs::File, io::BufWriter, path::Path};
+
+use bitflags::bitflags;
+use log::{debug, info, warn};
+
+use super::serial::SerialEndpoint;
+
+/// Every packet starts with these two bytes.
+const MAGIC: [u8; 2] = [0x88, 0x33];
+
+/// The reply to the first of the two bytes that end a packet, identifying the printer.
+const DEVICE_ID: u8 = 0x81;
+
+/// The printer holds up to 8KiB of tile data between prints.
+const MEMORY_SIZE: usize = 0x2000;
+
+/// Prints are always 20 tiles across.
+const TILES_PER_ROW: usize = 20;
+const TILE_SIZE: usize = 16;
+
+/// The width of a print in pixels.
+pub const PRINT_WIDTH: u32 = TILES_PER_ROW as u32 * 8;
+
+/// The palette used when a print command specifies none, as games commonly do.
+const DEFAULT_PALETTE: u8 = 0xE4;
+
+/// The gray level each shade prints as.
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// The number of status requests a print stays busy for. Games wait for printing to finish by
+/// polling the status, so this stands in for the time the paper takes to feed.
+const PRINT_STATUS_POLLS: u8 = 4;
+
+bitflags! {
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct PrinterStatus: u8 {
+        const CHECKSUM_ERROR = 0b0000_0001;
+        const PRINTING = 0b0000_0010;
+        const IMAGE_DATA_FULL = 0b0000_0100;
+        const UNPROCESSED_DATA = 0b0000_1000;
+        const PACKET_ERROR = 0b0001_0000;
+        const PAPER_JAM = 0b0010_0000;
+        const OTHER_ERROR = 0b0100_0000;
+        const LOW_BATTERY = 0b1000_0000;
+    }
+}
+
+/// A PrintSink receives each print job once the paper has been fed out after it.
+pub trait PrintSink: Debug {
+    fn print(&mut self, image: PrintedImage);
+}
+
+/// A finished print job, as 8-bit grayscale pixels.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PrintedImage {
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl PrintedImage {
+    pub fn width(&self) -> u32 {
+        PRINT_WIDTH
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The gray level of every pixel, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn write_png(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            PRINT_WIDTH,
+            self.height,
+        );
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+
+        Ok(())
+    }
+}
+
+/// How far through a packet the printer is.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    DeviceId,
+    Status,
+}
+
+/// The Game Boy Printer, connected to the serial port. Games send it packets of tile data, 160
+/// pixels across, and then a command to print what was sent with a given palette. Consecutive
+/// prints without a margin after them form one job, which is handed to the PrintSink once the
+/// paper is fed out.
+///
+/// A packet is made up of the magic bytes 0x88 0x33, a command, a compression flag, the length
+/// of the data as a little endian u16, the data, and a checksum of everything from the command
+/// on. The printer replies to the two bytes that follow with its device ID and its status.
+#[derive(Debug)]
+pub struct Printer {
+    sink: Box<dyn PrintSink>,
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: PrinterStatus,
+    busy_polls: u8,
+    /// Tile data received since the last print.
+    memory: Vec<u8>,
+    /// The pixels printed so far in the current job.
+    job: Vec<u8>,
+}
+
+impl Printer {
+    pub fn new(sink: Box<dyn PrintSink>) -> Self {
+        Printer {
+            sink,
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: PrinterStatus::empty(),
+            busy_polls: 0,
+            memory: Vec::new(),
+            job: Vec::new(),
+        }
+    }
+
+    /// Advances through the packet with the next byte sent, returning the byte sent back.
+    fn receive_byte(&mut self, byte: u8) -> u8 {
+        if matches!(
+            self.state,
+            PacketState::Command
+                | PacketState::Compression
+                | PacketState::LengthLow
+                | PacketState::LengthHigh
+                | PacketState::Data
+        ) {
+            self.checksum = self.checksum.wrapping_add(byte as u16);
+        }
+
+        match self.state {
+            PacketState::Magic(index) => {
+                self.state = match (byte == MAGIC[index], index) {
+                    (true, 0) => PacketState::Magic(1),
+                    (true, _) => {
+                        self.checksum = 0;
+                        PacketState::Command
+                    }
+                    // The first magic byte may be resent after a mismatched second one
+                    (false, _) if byte == MAGIC[0] => PacketState::Magic(1),
+                    (false, _) => PacketState::Magic(0),
+                };
+            }
+            PacketState::Command => {
+                self.command = byte;
+                self.state = PacketState::Compression;
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.state = PacketState::LengthLow;
+            }
+            PacketState::LengthLow => {
+                self.length = byte as u16;
+                self.state = PacketState::LengthHigh;
+            }
+            PacketState::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.data.clear();
+                self.state = match self.length {
+                    0 => PacketState::ChecksumLow,
+                    _ => PacketState::Data,
+                };
+            }
+            PacketState::Data => {
+                self.data.push(byte);
+                if self.data.len() == self.length as usize {
+                    self.state = PacketState::ChecksumLow;
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                self.state = PacketState::ChecksumHigh;
+            }
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                self.state = PacketState::DeviceId;
+            }
+            PacketState::DeviceId => {
+                self.state = PacketState::Status;
+                return DEVICE_ID;
+            }
+            PacketState::Status => {
+                self.state = PacketState::Magic(0);
+                return self.complete_packet();
+            }
+        }
+
+        0x00
+    }
+
+    /// Runs the command of a received packet, returning the status reported for it.
+    fn complete_packet(&mut self) -> u8 {
+        if self.checksum != self.received_checksum {
+            warn!(
+                "Printer packet checksum {:#06x} doesn't match {:#06x}.",
+                self.received_checksum, self.checksum
+            );
+            self.status.insert(PrinterStatus::CHECKSUM_ERROR);
+            return self.status.bits();
+        }
+        self.status.remove(PrinterStatus::CHECKSUM_ERROR);
+
+        let data = match self.compressed {
+            true => decompress(&self.data),
+            false => std::mem::take(&mut self.data),
+        };
+
+        match self.command {
+            // Initialize
+            0x01 => {
+                self.memory.clear();
+                self.status = PrinterStatus::empty();
+                self.busy_polls = 0;
+            }
+            // Print
+            0x02 if data.len() >= 4 => self.print(data[1], data[2]),
+            // Data, where an empty packet marks the end of the image
+            0x04 => match data.is_empty() {
+                true => self.status.insert(PrinterStatus::IMAGE_DATA_FULL),
+                false => {
+                    let length = data.len().min(MEMORY_SIZE - self.memory.len());
+                    self.memory.extend_from_slice(&data[..length]);
+                    self.status.insert(PrinterStatus::UNPROCESSED_DATA);
+                }
+            },
+            // Break
+            0x08 => {
+                self.memory.clear();
+                self.job.clear();
+                self.status = PrinterStatus::empty();
+                self.busy_polls = 0;
+            }
+            // Status
+            0x0F => {
+                let status = self.status.bits();
+                if self.busy_polls > 0 {
+                    self.busy_polls -= 1;
+                    if self.busy_polls == 0 {
+                        self.status
+                            .remove(PrinterStatus::PRINTING | PrinterStatus::IMAGE_DATA_FULL);
+                    }
+                }
+                return status;
+            }
+            command => {
+                debug!("Ignoring unknown printer command {:#04x}.", command);
+                self.status.insert(PrinterStatus::PACKET_ERROR);
+            }
+        }
+
+        self.status.bits()
+    }
+
+    /// Prints the received tile data with the palette. The job ends once a margin is fed out
+    /// after it.
+    fn print(&mut self, margins: u8, palette: u8) {
+        let palette = match palette {
+            0x00 => DEFAULT_PALETTE,
+            palette => palette,
+        };
+        let tiles = self.memory.len() / TILE_SIZE;
+        let height = tiles.div_ceil(TILES_PER_ROW) * 8;
+        debug!("Printing {} tiles with palette {:#04x}.", tiles, palette);
+
+        for y in 0..height {
+            for x in 0..PRINT_WIDTH as usize {
+                let tile = (y / 8) * TILES_PER_ROW + x / 8;
+                let shade = match tile < tiles {
+                    true => {
+                        let address = tile * TILE_SIZE + (y % 8) * 2;
+                        let bit = 7 - x % 8;
+                        let color = ((self.memory[address] >> bit) & 0x01)
+                            | (((self.memory[address + 1] >> bit) & 0x01) << 1);
+                        (palette >> (color * 2)) & 0x03
+                    }
+                    false => 0,
+                };
+                self.job.push(SHADES[shade as usize]);
+            }
+        }
+
+        self.memory.clear();
+        self.status.remove(PrinterStatus::UNPROCESSED_DATA);
+        self.status.insert(PrinterStatus::PRINTING);
+        self.busy_polls = PRINT_STATUS_POLLS;
+
+        if margins & 0x0F != 0 && !self.job.is_empty() {
+            let pixels = std::mem::take(&mut self.job);
+            let height = (pixels.len() / PRINT_WIDTH as usize) as u32;
+            info!("Printed a {}x{} image.", PRINT_WIDTH, height);
+            self.sink.print(PrintedImage { height, pixels });
+        }
+    }
+}
+
+impl SerialEndpoint for Printer {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.receive_byte(sent)
+    }
+}
+
+/// Expands run-length encoded packet data. A control byte with bit 7 set repeats the next byte
+/// (control & 0x7F) + 2 times, and otherwise is followed by (control + 1) literal bytes.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut index = 0;
+
+    while index < data.len() {
+        let control = data[index] as usize;
+        index += 1;
+
+        match control & 0x80 != 0 {
+            true => {
+                if let Some(&byte) = data.get(index) {
+                    output.extend(std::iter::repeat_n(byte, (control & 0x7F) + 2));
+                }
+                index += 1;
+            }
+            false => {
+                let end = (index + control + 1).min(data.len());
+                output.extend_from_slice(&data[index..end]);
+                index = end;
+            }
+        }
+    }
+
+    output
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+
+use anyhow::bail;
+
+/// A rolling history of save states, captured every few frames while the emulator runs.
+///
+/// Only the most recent state is kept in full. Every older state is stored as a delta against the
+/// state captured after it, by LZ4 compressing it with its newer neighbour as the dictionary.
+/// Consecutive states differ in very few bytes, so almost all of a state is encoded as references
+/// into the dictionary. Because each delta only depends on its newer neighbour, the oldest history
+/// can be discarded without touching anything else, and stepping backwards only ever decodes a
+/// single delta.
+pub struct RewindBuffer {
+    interval: u32,
+    capacity: usize,
+    frames: u32,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// The number of frames the Gameboy presents every second.
+    const FRAME_RATE: f64 = 59.7;
+
+    /// Constructs a buffer holding roughly the given number of seconds of history, capturing a
+    /// state every `interval` frames.
+    pub fn new(seconds: u32, interval: u32) -> Self {
+        debug_assert!(interval > 0, "Rewind interval must be at least one frame.");
+
+        let capacity = (seconds as f64 * Self::FRAME_RATE / interval as f64).ceil() as usize;
+
+        RewindBuffer {
+            interval,
+            capacity: capacity.max(1),
+            frames: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Called once per frame. Returns true if a state should be captured and pushed this frame.
+    pub fn frame(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            return true;
+        }
+
+        false
+    }
+
+    /// Records a newly captured state, discarding the oldest history if the buffer is full.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.replace(state) {
+            let delta = encode_delta(&previous, self.latest.as_ref().unwrap());
+            self.deltas.push_back(delta);
+        }
+
+        while self.deltas.len() + 1 > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Steps one capture backwards in time, returning the state to restore. Once the oldest
+    /// recorded state is reached it is returned repeatedly rather than being removed, so holding
+    /// rewind comes to rest at the start of the history.
+    pub fn pop(&mut self) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let Some(latest) = self.latest.as_ref() else {
+            return Ok(None);
+        };
+
+        self.frames = 0;
+        match self.deltas.pop_back() {
+            Some(delta) => {
+                let previous = decode_delta(&delta, latest)?;
+                Ok(self.latest.replace(previous))
+            }
+            None => Ok(Some(latest.clone())),
+        }
+    }
+
+    /// The approximate number of bytes of memory used by recorded states.
+    pub fn size(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+/// States are delta encoded in chunks, as LZ4 can only reference data up to 64KiB behind the
+/// current position, and cartridge RAM alone can exceed that.
+const DELTA_CHUNK_SIZE: usize = 32 * 1024;
+
+/// How far either side of a chunk the dictionary extends. Queues and FIFOs serialize to a variable
+/// number of bytes, so data in consecutive states can be shifted slightly relative to each other.
+const DELTA_DICTIONARY_MARGIN: usize = 16 * 1024;
+
+/// The region of the newer state used as the dictionary for the chunk starting at `start`.
+fn dictionary(newer: &[u8], start: usize) -> &[u8] {
+    let dictionary_start = start
+        .saturating_sub(DELTA_DICTIONARY_MARGIN)
+        .min(newer.len());
+    let dictionary_end = (start + DELTA_CHUNK_SIZE + DELTA_DICTIONARY_MARGIN).min(newer.len());
+
+    &newer[dictionary_start..dictionary_end]
+}
+
+/// Encodes `older` relative to `newer` as a sequence of length-prefixed compressed chunks.
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+
+    for (i, chunk) in older.chunks(DELTA_CHUNK_SIZE).enumerate() {
+        let compressed = lz4_flex::block::compress_prepend_size_with_dict(
+            chunk,
+            dictionary(newer, i * DELTA_CHUNK_SIZE),
+        );
+
+        delta.extend((compressed.len() as u32).to_le_bytes());
+        delta.extend(compressed);
+    }
+
+    delta
+}
+
+fn decode_delta(mut delta: &[u8], newer: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut older = Vec::with_capacity(newer.len());
+
+    while !delta.is_empty() {
+        if delta.len() < 4 {
+            bail!("Rewind history is corrupt. Truncated chunk header.");
+        }
+
+        let length = u32::from_le_bytes([delta[0], delta[1], delta[2], delta[3]]) as usize;
+        let Some(compressed) = delta.get(4..4 + length) else {
+            bail!("Rewind history is corrupt. Truncated chunk.");
+        };
+
+        match lz4_flex::block::decompress_size_prepended_with_dict(
+            compressed,
+            dictionary(newer, older.len()),
+        ) {
+            Ok(chunk) => older.extend(chunk),
+            Err(e) => bail!("Rewind history is corrupt. {:?}", e),
+        }
+
+        delta = &delta[4 + length..];
+    }
+
+    Ok(older)
+}
@@ -0,0 +1,15 @@
+use std::fmt::Debug;
+
+/// A RumbleSink is notified when the motor of a rumble cartridge starts or stops.
+pub trait RumbleSink: Debug {
+    /// Called whenever the motor changes state.
+    fn set_rumble(&mut self, active: bool);
+}
+
+/// A RumbleSink that ignores the motor, for hosts that can't vibrate.
+#[derive(Debug, Default)]
+pub struct NullRumbleSink;
+
+impl RumbleSink for NullRumbleSink {
+    fn set_rumble(&mut self, _active: bool) {}
+}
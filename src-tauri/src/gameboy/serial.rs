@@ -1,8 +1,113 @@
+mod link;
+
+use std::{cell::RefCell, collections::VecDeque, fmt::Debug, rc::Rc};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
 use super::{
     memory::{Interrupt, Register},
+    state::Snapshot,
     GlobalConstants,
 };
 
+pub use link::{Link, LinkAddress, LinkSerialEndpoint};
+
+/// The byte shifted in when nothing is connected to the link port, as the data line is pulled
+/// high.
+const DISCONNECTED: u8 = 0xFF;
+
+/// The longest a LogSerialEndpoint buffers a line before logging it anyway.
+const MAX_LINE_LENGTH: usize = 80;
+
+/// A SerialEndpoint is the device at the other end of the link cable. It is sent each byte the
+/// Gameboy transfers and provides the byte transferred back in return.
+pub trait SerialEndpoint: Debug {
+    /// Called when the Gameboy starts a transfer using its internal clock. Returns the byte
+    /// shifted in from the other side while `sent` is shifted out.
+    fn exchange(&mut self, sent: u8) -> u8;
+
+    /// Polled while the Gameboy waits for a transfer driven by the other side's clock. Returns
+    /// the byte received once the other side has sent one, in which case `outgoing` is shifted
+    /// out to it in return.
+    fn receive(&mut self, _outgoing: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// A SerialEndpoint that logs sent bytes a line at a time, as test ROMs print their results over
+/// the serial port. Nothing is ever received.
+#[derive(Debug, Default)]
+pub struct LogSerialEndpoint {
+    line: Vec<u8>,
+}
+
+impl LogSerialEndpoint {
+    pub fn new() -> Self {
+        LogSerialEndpoint { line: Vec::new() }
+    }
+}
+
+impl SerialEndpoint for LogSerialEndpoint {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        if sent != b'\n' {
+            self.line.push(sent);
+        }
+
+        if sent == b'\n' || self.line.len() >= MAX_LINE_LENGTH {
+            info!("Serial: {}", String::from_utf8_lossy(&self.line));
+            self.line.clear();
+        }
+
+        DISCONNECTED
+    }
+}
+
+/// A SerialEndpoint that records sent bytes in memory and replies with queued bytes, so that
+/// serial traffic can be inspected and scripted. Clones share the same buffers, so a clone kept
+/// outside the Gameboy sees everything sent through it.
+#[derive(Debug, Clone, Default)]
+pub struct BufferSerialEndpoint {
+    sent: Rc<RefCell<Vec<u8>>>,
+    incoming: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl BufferSerialEndpoint {
+    pub fn new() -> Self {
+        BufferSerialEndpoint {
+            sent: Rc::new(RefCell::new(Vec::new())),
+            incoming: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    /// Takes the bytes sent since this was last called.
+    pub fn take_sent(&self) -> Vec<u8> {
+        std::mem::take(&mut self.sent.borrow_mut())
+    }
+
+    /// Queues bytes to be received, one per transfer, whichever side drives the clock.
+    pub fn push_incoming(&self, bytes: &[u8]) {
+        self.incoming.borrow_mut().extend(bytes);
+    }
+}
+
+impl SerialEndpoint for BufferSerialEndpoint {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.sent.borrow_mut().push(sent);
+        self.incoming
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(DISCONNECTED)
+    }
+
+    fn receive(&mut self, outgoing: u8) -> Option<u8> {
+        let incoming = self.incoming.borrow_mut().pop_front()?;
+        self.sent.borrow_mut().push(outgoing);
+
+        Some(incoming)
+    }
+}
+
 #[derive(Debug)]
 pub struct Serial {
     transfer_state: TransferState,
@@ -11,10 +116,12 @@ pub struct Serial {
     master: bool,
     remaining_cycles: i32,
     pending_interrupts: Option<Interrupt>,
+    endpoint: Box<dyn SerialEndpoint>,
 }
 
 impl Serial {
-    pub fn new() -> Self {
+    /// Constructs the serial port, connected to the provided endpoint.
+    pub fn new(endpoint: Box<dyn SerialEndpoint>) -> Self {
         Serial {
             transfer_state: TransferState::Waiting,
             clock_speed: ClockSpeed::Hz8192,
@@ -22,18 +129,69 @@ impl Serial {
             master: false,
             remaining_cycles: 0,
             pending_interrupts: None,
+            endpoint,
         }
     }
+
+    fn complete_transfer(&mut self, byte: u8) {
+        self.sb_register = byte;
+        self.transfer_state = TransferState::Waiting;
+        self.pending_interrupts
+            .get_or_insert(Interrupt::empty())
+            .insert(Interrupt::SERIAL);
+    }
 }
 
-impl Default for Serial {
-    fn default() -> Self {
-        Self::new()
+#[derive(Serialize, Deserialize)]
+pub struct SerialSnapshot {
+    transfer_state: TransferState,
+    clock_speed: ClockSpeed,
+    sb_register: u8,
+    master: bool,
+    remaining_cycles: i32,
+    pending_interrupts: Option<Interrupt>,
+}
+
+impl Snapshot for Serial {
+    type State = SerialSnapshot;
+
+    fn save_state(&self) -> SerialSnapshot {
+        SerialSnapshot {
+            transfer_state: self.transfer_state.clone(),
+            clock_speed: self.clock_speed.clone(),
+            sb_register: self.sb_register,
+            master: self.master,
+            remaining_cycles: self.remaining_cycles,
+            pending_interrupts: self.pending_interrupts,
+        }
+    }
+
+    fn load_state(&mut self, state: SerialSnapshot) {
+        self.transfer_state = state.transfer_state;
+        self.clock_speed = state.clock_speed;
+        self.sb_register = state.sb_register;
+        self.master = state.master;
+        self.remaining_cycles = state.remaining_cycles;
+        self.pending_interrupts = state.pending_interrupts;
     }
 }
 
 impl Register for Serial {
     fn tick(&mut self, cycles: u32) {
+        // Transfers on the external clock complete whenever the other side sends a byte
+        if !self.master {
+            if self.transfer_state != TransferState::Waiting {
+                if let Some(incoming) = self.endpoint.receive(self.sb_register) {
+                    debug!(
+                        "Serial transfer sent {:#04x} and received {:#04x}.",
+                        self.sb_register, incoming
+                    );
+                    self.complete_transfer(incoming);
+                }
+            }
+            return;
+        }
+
         self.remaining_cycles -= cycles as i32;
         if self.remaining_cycles > 0 {
             return;
@@ -47,17 +205,27 @@ impl Register for Serial {
         };
 
         match self.transfer_state {
-            TransferState::InProgress { byte, shifts } if shifts >= 8 => {
-                print!("{}", char::from(byte));
-                self.transfer_state = TransferState::Waiting;
-                self.pending_interrupts
-                    .get_or_insert(Interrupt::empty())
-                    .insert(Interrupt::SERIAL);
+            TransferState::InProgress {
+                byte,
+                incoming,
+                shifts,
+            } if shifts >= 8 => {
+                debug!(
+                    "Serial transfer sent {:#04x} and received {:#04x}.",
+                    byte, incoming
+                );
+                self.complete_transfer(incoming);
             }
-            TransferState::InProgress { byte, shifts } => {
-                self.sb_register <<= 1;
+            TransferState::InProgress {
+                byte,
+                incoming,
+                shifts,
+            } => {
+                // The received byte is shifted in as the sent one is shifted out
+                self.sb_register = (self.sb_register << 1) | ((incoming >> (7 - shifts)) & 0x01);
                 self.transfer_state = TransferState::InProgress {
                     byte,
+                    incoming,
                     shifts: shifts + 1,
                 };
             }
@@ -100,33 +268,39 @@ impl Register for Serial {
         match address {
             0xFF01 => self.sb_register = value,
             0xFF02 => {
-                if self.transfer_state == TransferState::Waiting && value & 0b1000_0000 != 0 {
-                    self.transfer_state = TransferState::InProgress {
-                        byte: self.sb_register,
-                        shifts: 0,
-                    };
-                }
-
                 match value & 0b0000_0010 != 0 {
                     true => self.clock_speed = ClockSpeed::Hz262144,
                     false => self.clock_speed = ClockSpeed::Hz8192,
                 }
 
                 self.master = value & 0b0000_0001 != 0;
+
+                if self.transfer_state == TransferState::Waiting && value & 0b1000_0000 != 0 {
+                    // With the internal clock, the other side has to reply as the transfer starts
+                    let incoming = match self.master {
+                        true => self.endpoint.exchange(self.sb_register),
+                        false => DISCONNECTED,
+                    };
+                    self.transfer_state = TransferState::InProgress {
+                        byte: self.sb_register,
+                        incoming,
+                        shifts: 0,
+                    };
+                }
             }
             _ => panic!("{:#05x} is not a Serial address.", address),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 enum TransferState {
     Waiting,
-    InProgress { byte: u8, shifts: u8 },
+    InProgress { byte: u8, incoming: u8, shifts: u8 },
 }
 
 #[allow(dead_code)]
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 enum ClockSpeed {
     Hz8192,
     Hz16384, // CGB only
@@ -0,0 +1,389 @@
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use crossbeam::{
+    channel::{Receiver, Sender},
+    select,
+};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{SerialEndpoint, DISCONNECTED};
+
+/// The size of an encoded LinkMessage: its kind, sequence number and data byte.
+const MESSAGE_SIZE: usize = 6;
+
+/// A message exchanged between the two ends of a link cable.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum LinkMessage {
+    /// A byte sent by the side driving the clock.
+    Transfer { sequence: u32, byte: u8 },
+    /// The byte shifted back in return for the transfer with the same sequence number.
+    Reply { sequence: u32, byte: u8 },
+}
+
+impl LinkMessage {
+    fn encode(&self) -> [u8; MESSAGE_SIZE] {
+        let (kind, sequence, byte) = match *self {
+            LinkMessage::Transfer { sequence, byte } => (0, sequence, byte),
+            LinkMessage::Reply { sequence, byte } => (1, sequence, byte),
+        };
+
+        let mut bytes = [kind, 0, 0, 0, 0, byte];
+        bytes[1..5].copy_from_slice(&sequence.to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: [u8; MESSAGE_SIZE]) -> Option<Self> {
+        let sequence = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+        let byte = bytes[5];
+
+        match bytes[0] {
+            0 => Some(LinkMessage::Transfer { sequence, byte }),
+            1 => Some(LinkMessage::Reply { sequence, byte }),
+            _ => None,
+        }
+    }
+}
+
+/// A LinkMessage along with when it was sent, or when it arrived if it came over a socket.
+type Envelope = (Instant, LinkMessage);
+
+/// The side of a transfer driven by the other Gameboy's clock, shared with the thread that answers
+/// its transfers.
+#[derive(Debug, Default)]
+struct ExternalClock {
+    /// The byte to shift out, while the Gameboy waits for the other side to drive a transfer.
+    outgoing: Option<u8>,
+    /// The byte shifted in by a transfer that the Gameboy has yet to receive.
+    incoming: Option<u8>,
+}
+
+/// A SerialEndpoint connected to another emulated Gameboy, either through channels within the same
+/// process or over a socket.
+///
+/// The side driving the clock sends its byte and waits for the reply. Each endpoint answers
+/// transfers from the other side on a background thread, with the byte its Gameboy last offered
+/// while waiting on the external clock, so replies never wait on the pacing of the other
+/// emulator's frames. A transfer that arrives while the Gameboy isn't waiting is answered as if
+/// the cable were disconnected. Transfers are numbered so that replies arriving after the sender
+/// gave up are never mistaken for the reply to a later transfer, and a transfer is only answered
+/// while the sender can still be waiting for its reply.
+#[derive(Debug)]
+pub struct LinkSerialEndpoint {
+    sender: Sender<Envelope>,
+    replies: Receiver<(u32, u8)>,
+    external_clock: Arc<Mutex<ExternalClock>>,
+    /// Never sent on. Dropping it stops the thread answering transfers.
+    _closed: Sender<()>,
+    sequence: u32,
+}
+
+impl LinkSerialEndpoint {
+    /// How long a transfer driven by the internal clock waits for the other side to reply. Replies
+    /// are sent as soon as a transfer arrives, so this only runs out when the other side can't be
+    /// reached, and is about a frame so that emulation stalls only briefly when it does.
+    pub const TIMEOUT: Duration = Duration::from_millis(16);
+
+    fn new(sender: Sender<Envelope>, receiver: Receiver<Envelope>) -> Self {
+        let external_clock = Arc::new(Mutex::new(ExternalClock::default()));
+        let (reply_sender, replies) = crossbeam::channel::unbounded();
+        let (closed, closed_receiver) = crossbeam::channel::bounded(0);
+
+        thread::spawn({
+            let sender = sender.clone();
+            let external_clock = external_clock.clone();
+            move || loop {
+                let envelope = select! {
+                    recv(receiver) -> envelope => envelope.ok(),
+                    recv(closed_receiver) -> _ => None,
+                };
+                let Some((sent, message)) = envelope else {
+                    break;
+                };
+
+                match message {
+                    // Transfers the sender has already given up on are left unanswered
+                    LinkMessage::Transfer { sequence, byte } if sent.elapsed() <= Self::TIMEOUT => {
+                        let mut external_clock = external_clock.lock().unwrap();
+                        let outgoing = match external_clock.outgoing.take() {
+                            Some(outgoing) => {
+                                external_clock.incoming = Some(byte);
+                                outgoing
+                            }
+                            None => DISCONNECTED,
+                        };
+                        let reply = LinkMessage::Reply {
+                            sequence,
+                            byte: outgoing,
+                        };
+                        // The other side may have disconnected since sending
+                        let _ = sender.send((Instant::now(), reply));
+                    }
+                    LinkMessage::Reply { sequence, byte } => {
+                        let _ = reply_sender.send((sequence, byte));
+                    }
+                    _ => debug!("Dropping out of sync link message {:?}.", message),
+                }
+            }
+        });
+
+        LinkSerialEndpoint {
+            sender,
+            replies,
+            external_clock,
+            _closed: closed,
+            sequence: 0,
+        }
+    }
+
+    /// Constructs both ends of a link cable between two Gameboys in the same process.
+    pub fn pair() -> (Self, Self) {
+        let (first_sender, first_receiver) = crossbeam::channel::unbounded();
+        let (second_sender, second_receiver) = crossbeam::channel::unbounded();
+
+        (
+            LinkSerialEndpoint::new(first_sender, second_receiver),
+            LinkSerialEndpoint::new(second_sender, first_receiver),
+        )
+    }
+
+    /// Connects to another emulator listening on a TCP socket.
+    pub fn connect_tcp(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        info!("Link cable connected to {:?}.", stream.peer_addr()?);
+
+        Ok(Self::bridge(|| Ok(stream)))
+    }
+
+    /// Waits in the background for another emulator to connect to the TCP listener. Until then,
+    /// the cable is disconnected.
+    pub fn accept_tcp(listener: TcpListener) -> Self {
+        Self::bridge(move || {
+            let (stream, address) = listener.accept()?;
+            info!("Link cable connected to {:?}.", address);
+            Ok(stream)
+        })
+    }
+
+    /// Connects to another emulator listening on a Unix domain socket.
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<Path>) -> io::Result<Self> {
+        let stream = UnixStream::connect(&path)?;
+        info!("Link cable connected to {:?}.", path.as_ref());
+
+        Ok(Self::bridge(|| Ok(stream)))
+    }
+
+    /// Waits in the background for another emulator to connect to the Unix domain socket
+    /// listener. Until then, the cable is disconnected.
+    #[cfg(unix)]
+    pub fn accept_unix(listener: UnixListener) -> Self {
+        Self::bridge(move || {
+            let (stream, address) = listener.accept()?;
+            info!("Link cable connected to {:?}.", address);
+            Ok(stream)
+        })
+    }
+
+    /// Carries messages between the channels of an endpoint and the socket returned by
+    /// `connect`, which is called on a background thread.
+    fn bridge<S: LinkStream>(connect: impl FnOnce() -> io::Result<S> + Send + 'static) -> Self {
+        let (outgoing_sender, outgoing_receiver) = crossbeam::channel::unbounded::<Envelope>();
+        let (incoming_sender, incoming_receiver) = crossbeam::channel::unbounded();
+
+        thread::spawn(move || {
+            let (mut reader, mut writer) = match connect().and_then(|stream| {
+                let reader = stream.try_clone()?;
+                Ok((reader, stream))
+            }) {
+                Ok(streams) => streams,
+                Err(e) => {
+                    warn!("Unable to connect link cable. {:?}", e);
+                    return;
+                }
+            };
+
+            thread::spawn(move || {
+                let mut bytes = [0; MESSAGE_SIZE];
+                while reader.read_exact(&mut bytes).is_ok() {
+                    let Some(message) = LinkMessage::decode(bytes) else {
+                        warn!("Closing link cable after an invalid message {:?}.", bytes);
+                        break;
+                    };
+                    if incoming_sender.send((Instant::now(), message)).is_err() {
+                        break;
+                    }
+                }
+                info!("Link cable disconnected.");
+            });
+
+            for (sent, message) in outgoing_receiver {
+                // Transfers queued while connecting are no longer awaited by the emulator
+                if sent.elapsed() > Self::TIMEOUT {
+                    continue;
+                }
+                if writer.write_all(&message.encode()).is_err() {
+                    break;
+                }
+            }
+
+            // Unblocks the reader once the endpoint is dropped
+            writer.shutdown();
+        });
+
+        Self::new(outgoing_sender, incoming_receiver)
+    }
+}
+
+impl SerialEndpoint for LinkSerialEndpoint {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        // Driving the clock takes this side out of any transfer it was waiting on
+        self.external_clock.lock().unwrap().outgoing = None;
+
+        self.sequence = self.sequence.wrapping_add(1);
+        let sequence = self.sequence;
+        let transfer = LinkMessage::Transfer {
+            sequence,
+            byte: sent,
+        };
+        if self.sender.send((Instant::now(), transfer)).is_err() {
+            return DISCONNECTED;
+        }
+
+        let deadline = Instant::now() + Self::TIMEOUT;
+        loop {
+            match self.replies.recv_deadline(deadline) {
+                Ok((reply, byte)) if reply == sequence => return byte,
+                // Replies to transfers this side already gave up on
+                Ok((reply, byte)) => debug!("Dropping late link reply {} of {:#04x}.", reply, byte),
+                Err(_) => return DISCONNECTED,
+            }
+        }
+    }
+
+    fn receive(&mut self, outgoing: u8) -> Option<u8> {
+        let mut external_clock = self.external_clock.lock().unwrap();
+        let incoming = external_clock.incoming.take();
+        if incoming.is_none() {
+            external_clock.outgoing = Some(outgoing);
+        }
+
+        incoming
+    }
+}
+
+/// A connected socket that can carry link cable messages.
+trait LinkStream: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    fn shutdown(&self);
+}
+
+impl LinkStream for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        // Transfers are single bytes that the other side is waiting on
+        self.set_nodelay(true)?;
+        TcpStream::try_clone(self)
+    }
+
+    fn shutdown(&self) {
+        let _ = TcpStream::shutdown(self, Shutdown::Both);
+    }
+}
+
+#[cfg(unix)]
+impl LinkStream for UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self) {
+        let _ = UnixStream::shutdown(self, Shutdown::Both);
+    }
+}
+
+/// Where the other end of a link cable can be reached.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkAddress {
+    /// A host and port, such as 127.0.0.1:5734.
+    Tcp(String),
+    /// The path of a Unix domain socket. Only available on Unix platforms.
+    Unix(PathBuf),
+}
+
+/// How a link cable is connected to another emulator. Either side can drive the clock, whichever
+/// of them listens.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Link {
+    /// Waits for the other emulator to connect to this address.
+    Listen(LinkAddress),
+    /// Connects to the other emulator listening on this address.
+    Connect(LinkAddress),
+}
+
+impl Link {
+    /// Binds or connects to the address. Listening returns straight away, and the cable stays
+    /// disconnected until the other emulator connects.
+    pub fn open(&self) -> Result<LinkSerialEndpoint, anyhow::Error> {
+        match self {
+            Link::Listen(LinkAddress::Tcp(address)) => {
+                let listener = TcpListener::bind(address)
+                    .with_context(|| format!("Failed to listen on {}.", address))?;
+                info!("Waiting for link cable connection on {}.", address);
+                Ok(LinkSerialEndpoint::accept_tcp(listener))
+            }
+            Link::Connect(LinkAddress::Tcp(address)) => {
+                LinkSerialEndpoint::connect_tcp(address.as_str())
+                    .with_context(|| format!("Failed to connect to {}.", address))
+            }
+            #[cfg(unix)]
+            Link::Listen(LinkAddress::Unix(path)) => {
+                remove_stale_socket(path)?;
+                let listener = UnixListener::bind(path)
+                    .with_context(|| format!("Failed to listen on {:?}.", path))?;
+                info!("Waiting for link cable connection on {:?}.", path);
+                Ok(LinkSerialEndpoint::accept_unix(listener))
+            }
+            #[cfg(unix)]
+            Link::Connect(LinkAddress::Unix(path)) => LinkSerialEndpoint::connect_unix(path)
+                .with_context(|| format!("Failed to connect to {:?}.", path)),
+            #[cfg(not(unix))]
+            Link::Listen(LinkAddress::Unix(_)) | Link::Connect(LinkAddress::Unix(_)) => {
+                anyhow::bail!("Unix domain sockets aren't supported on this platform.")
+            }
+        }
+    }
+}
+
+/// Removes a socket left behind by an earlier session, which would otherwise prevent binding to
+/// the same path. Anything other than a socket is left alone.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<(), anyhow::Error> {
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {:?}.", path))?;
+        }
+    }
+
+    Ok(())
+}
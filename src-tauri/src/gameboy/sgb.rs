@@ -0,0 +1,513 @@
+use std::cmp::Ordering;
+
+use log::{debug, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use super::display::Display;
+
+/// The width of the SGB picture, including the border.
+pub const SGB_SCREEN_WIDTH: u16 = 256;
+
+/// The height of the SGB picture, including the border.
+pub const SGB_SCREEN_HEIGHT: u16 = 224;
+
+/// Where the Gameboy screen is placed within the border.
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+
+/// The screen is colorized in blocks of 8x8 pixels, 20 across and 18 down.
+const ATTRIBUTE_WIDTH: usize = 20;
+const ATTRIBUTE_HEIGHT: usize = 18;
+const ATTRIBUTE_FILE_SIZE: usize = ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT / 4;
+const ATTRIBUTE_FILES: usize = 45;
+
+const PACKET_SIZE: usize = 16;
+const MAX_PACKETS: usize = 7;
+
+/// The number of bytes a *_TRN command transfers from the screen.
+pub const TRANSFER_SIZE: usize = 0x1000;
+
+const SYSTEM_PALETTES: usize = 512;
+const BORDER_TILES: usize = 256;
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_MAP_SIZE: usize = 32 * 28;
+
+/// The colors used until a game sets its own, matching the DMG shades.
+const DEFAULT_PALETTE: [u16; 4] = [0x06F3, 0x06B1, 0x1986, 0x04E1];
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+enum Mask {
+    Cancel,
+    /// Keeps showing the last frame while the game prepares a transfer.
+    Freeze,
+    Black,
+    Color0,
+}
+
+/// The data a *_TRN command is waiting to capture from the next frame.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+enum Transfer {
+    Palettes,
+    /// Border tiles 0x00-0x7F, or 0x80-0xFF if set.
+    BorderTiles(bool),
+    Border,
+    AttributeFiles,
+}
+
+/// The Super Game Boy. Games send it command packets through the joypad register, which it uses to
+/// colorize the screen and draw a border around it.
+///
+/// A packet starts with a reset pulse, where both P14 and P15 are pulled low. It is followed by 128
+/// bits, least significant first, each sent by pulling P14 low for a zero or P15 low for a one,
+/// with both lines released in between. A final zero bit ends the packet. Commands that span
+/// several packets start each one with another reset pulse.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SGB {
+    command: Vec<u8>,
+    /// The number of command bits received so far.
+    bit_index: usize,
+    ready_for_pulse: bool,
+    ready_for_write: bool,
+    ready_for_stop: bool,
+    joypad: u8,
+    players: u8,
+    current_player: u8,
+    /// Palettes 0-3 used to colorize the screen. Color 0 is shared by all of them.
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<u16>,
+    /// The palette used for each 8x8 block of the screen.
+    attributes: Vec<u8>,
+    attribute_files: Vec<u8>,
+    border_tiles: Vec<u8>,
+    border_map: Vec<u16>,
+    /// Border palettes 4-7, 16 colors each.
+    border_palettes: Vec<u16>,
+    mask: Mask,
+    pending_transfer: Option<Transfer>,
+    /// The shade of every pixel of the Gameboy screen.
+    screen: Vec<u8>,
+}
+
+impl SGB {
+    pub fn new() -> Self {
+        SGB {
+            command: vec![0; PACKET_SIZE * MAX_PACKETS],
+            bit_index: 0,
+            ready_for_pulse: false,
+            ready_for_write: false,
+            ready_for_stop: false,
+            joypad: 0x30,
+            players: 1,
+            current_player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![0; SYSTEM_PALETTES * 4],
+            attributes: vec![0; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+            attribute_files: vec![0; ATTRIBUTE_FILE_SIZE * ATTRIBUTE_FILES],
+            border_tiles: vec![0; BORDER_TILES * BORDER_TILE_SIZE],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: vec![0; 4 * 16],
+            mask: Mask::Cancel,
+            pending_transfer: None,
+            screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    /// Receives a write to the joypad register, decoding the command packets sent through P14
+    /// and P15.
+    pub fn write_joypad(&mut self, value: u8) {
+        let previous = std::mem::replace(&mut self.joypad, value & 0x30);
+
+        // In multiplayer mode, releasing P15 switches to the next controller
+        if self.players > 1 && previous & 0x20 == 0 && value & 0x20 != 0 {
+            self.current_player = (self.current_player + 1) & (self.players - 1);
+        }
+
+        let packet_bits = PACKET_SIZE * 8;
+        match value & 0x30 {
+            0x30 => self.ready_for_pulse = true,
+            // Reset pulse
+            0x00 => {
+                if !self.ready_for_pulse {
+                    return;
+                }
+                self.ready_for_write = true;
+                self.ready_for_pulse = false;
+                // A reset pulse continues a multi-packet command only between packets
+                if !self.bit_index.is_multiple_of(packet_bits)
+                    || self.bit_index == 0
+                    || self.ready_for_stop
+                {
+                    self.bit_index = 0;
+                    self.command.fill(0);
+                    self.ready_for_stop = false;
+                }
+            }
+            line => {
+                if !self.ready_for_pulse || !self.ready_for_write {
+                    return;
+                }
+                self.ready_for_pulse = false;
+                let bit = line == 0x10;
+
+                if self.ready_for_stop {
+                    self.ready_for_stop = false;
+                    self.ready_for_write = false;
+                    if bit {
+                        warn!("Discarding SGB packet without a stop bit.");
+                        self.bit_index = 0;
+                        return;
+                    }
+                    if self.bit_index == self.command_length() * packet_bits {
+                        self.execute_command();
+                        self.bit_index = 0;
+                        self.command.fill(0);
+                    }
+                    return;
+                }
+
+                if bit {
+                    self.command[self.bit_index / 8] |= 1 << (self.bit_index % 8);
+                }
+                self.bit_index += 1;
+                if self.bit_index.is_multiple_of(packet_bits) {
+                    self.ready_for_stop = true;
+                }
+            }
+        }
+    }
+
+    /// The number of packets the current command spans.
+    fn command_length(&self) -> usize {
+        (self.command[0] as usize & 0x07).max(1)
+    }
+
+    /// The ID of the selected controller, reported in the lower nibble of the joypad register
+    /// while neither P14 nor P15 is selected. Only available in multiplayer mode.
+    pub fn joypad_id(&self) -> Option<u8> {
+        match self.players {
+            1 => None,
+            _ => Some(0x0F - self.current_player),
+        }
+    }
+
+    fn execute_command(&mut self) {
+        let data = std::mem::take(&mut self.command);
+        let command = data[0] >> 3;
+        debug!("Received SGB command {:#04x}.", command);
+
+        match command {
+            0x00 => self.set_palette_pair(0, 1, &data),
+            0x01 => self.set_palette_pair(2, 3, &data),
+            0x02 => self.set_palette_pair(0, 3, &data),
+            0x03 => self.set_palette_pair(1, 2, &data),
+            // ATTR_BLK
+            0x04 => {
+                for block in data[2..].chunks_exact(6).take(data[1] as usize) {
+                    self.apply_attribute_block(block);
+                }
+            }
+            // ATTR_LIN
+            0x05 => {
+                for &line in data[2..].iter().take(data[1] as usize) {
+                    let index = (line & 0x1F) as usize;
+                    let palette = (line >> 5) & 0x03;
+                    match line & 0x80 != 0 {
+                        true if index < ATTRIBUTE_HEIGHT => {
+                            (0..ATTRIBUTE_WIDTH).for_each(|x| self.set_attribute(x, index, palette))
+                        }
+                        false if index < ATTRIBUTE_WIDTH => (0..ATTRIBUTE_HEIGHT)
+                            .for_each(|y| self.set_attribute(index, y, palette)),
+                        _ => {}
+                    }
+                }
+            }
+            // ATTR_DIV
+            0x06 => {
+                let horizontal = data[1] & 0x40 != 0;
+                let line = data[2] as usize;
+                for y in 0..ATTRIBUTE_HEIGHT {
+                    for x in 0..ATTRIBUTE_WIDTH {
+                        let position = if horizontal { y } else { x };
+                        let palette = match position.cmp(&line) {
+                            Ordering::Less => (data[1] >> 2) & 0x03,
+                            Ordering::Equal => (data[1] >> 4) & 0x03,
+                            Ordering::Greater => data[1] & 0x03,
+                        };
+                        self.set_attribute(x, y, palette);
+                    }
+                }
+            }
+            // ATTR_CHR
+            0x07 => {
+                let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+                let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+                let vertical = data[5] & 0x01 != 0;
+                for index in 0..count.min((data.len() - 6) * 4) {
+                    if x >= ATTRIBUTE_WIDTH || y >= ATTRIBUTE_HEIGHT {
+                        break;
+                    }
+                    let palette = (data[6 + index / 4] >> (6 - 2 * (index % 4))) & 0x03;
+                    self.set_attribute(x, y, palette);
+
+                    match vertical {
+                        false => {
+                            x += 1;
+                            if x == ATTRIBUTE_WIDTH {
+                                x = 0;
+                                y += 1;
+                            }
+                        }
+                        true => {
+                            y += 1;
+                            if y == ATTRIBUTE_HEIGHT {
+                                y = 0;
+                                x += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            // PAL_SET
+            0x0A => {
+                let color_0 = self.system_palettes
+                    [(u16::from_le_bytes([data[1], data[2]]) & 0x1FF) as usize * 4];
+                for (palette, number) in data[1..9].chunks_exact(2).enumerate() {
+                    let number = (u16::from_le_bytes([number[0], number[1]]) & 0x1FF) as usize;
+                    self.palettes[palette]
+                        .copy_from_slice(&self.system_palettes[number * 4..number * 4 + 4]);
+                    self.palettes[palette][0] = color_0;
+                }
+                if data[9] & 0x80 != 0 {
+                    self.apply_attribute_file(data[9] & 0x3F);
+                }
+                if data[9] & 0x40 != 0 {
+                    self.mask = Mask::Cancel;
+                }
+            }
+            0x0B => self.pending_transfer = Some(Transfer::Palettes),
+            // MLT_REQ
+            0x11 => {
+                self.players = match data[1] & 0x03 {
+                    0x01 => 2,
+                    0x03 => 4,
+                    _ => 1,
+                };
+                self.current_player = 0;
+            }
+            0x13 => self.pending_transfer = Some(Transfer::BorderTiles(data[1] & 0x01 != 0)),
+            0x14 => self.pending_transfer = Some(Transfer::Border),
+            0x15 => self.pending_transfer = Some(Transfer::AttributeFiles),
+            // ATTR_SET
+            0x16 => {
+                self.apply_attribute_file(data[1] & 0x3F);
+                if data[1] & 0x40 != 0 {
+                    self.mask = Mask::Cancel;
+                }
+            }
+            // MASK_EN
+            0x17 => {
+                self.mask = match data[1] & 0x03 {
+                    0x00 => Mask::Cancel,
+                    0x01 => Mask::Freeze,
+                    0x02 => Mask::Black,
+                    _ => Mask::Color0,
+                }
+            }
+            _ => trace!("Ignoring unsupported SGB command {:#04x}.", command),
+        }
+
+        self.command = data;
+    }
+
+    /// Sets two palettes from a PALxy command: the shared color 0, then colors 1-3 of each.
+    fn set_palette_pair(&mut self, first: usize, second: usize, data: &[u8]) {
+        let colors: Vec<u16> = data[1..15]
+            .chunks_exact(2)
+            .map(|color| u16::from_le_bytes([color[0], color[1]]) & 0x7FFF)
+            .collect();
+
+        for palette in self.palettes.iter_mut() {
+            palette[0] = colors[0];
+        }
+        self.palettes[first][1..].copy_from_slice(&colors[1..4]);
+        self.palettes[second][1..].copy_from_slice(&colors[4..7]);
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        self.attributes[y * ATTRIBUTE_WIDTH + x] = palette;
+    }
+
+    /// Applies a single data set of an ATTR_BLK command, which colors the inside, border and
+    /// outside of a rectangle.
+    fn apply_attribute_block(&mut self, block: &[u8]) {
+        let control = block[0] & 0x07;
+        let inside = block[1] & 0x03;
+        let outside = (block[1] >> 4) & 0x03;
+        // Changing only the inside or only the outside colors the border along with it
+        let (change_border, border) = match control {
+            0x01 => (true, inside),
+            0x04 => (true, outside),
+            _ => (control & 0x02 != 0, (block[1] >> 2) & 0x03),
+        };
+        let (x1, y1, x2, y2) = (
+            block[2] as usize,
+            block[3] as usize,
+            block[4] as usize,
+            block[5] as usize,
+        );
+
+        for y in 0..ATTRIBUTE_HEIGHT {
+            for x in 0..ATTRIBUTE_WIDTH {
+                let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                let on_edge = x == x1 || x == x2 || y == y1 || y == y2;
+                match (within, on_edge) {
+                    (true, false) if control & 0x01 != 0 => self.set_attribute(x, y, inside),
+                    (true, true) if change_border => self.set_attribute(x, y, border),
+                    (false, _) if control & 0x04 != 0 => self.set_attribute(x, y, outside),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Colors the screen from an attribute file previously sent with ATTR_TRN.
+    fn apply_attribute_file(&mut self, file: u8) {
+        if file as usize >= ATTRIBUTE_FILES {
+            warn!("Ignoring invalid SGB attribute file {}.", file);
+            return;
+        }
+
+        let start = file as usize * ATTRIBUTE_FILE_SIZE;
+        for (index, byte) in self.attribute_files[start..start + ATTRIBUTE_FILE_SIZE]
+            .iter()
+            .enumerate()
+        {
+            for cell in 0..4 {
+                self.attributes[index * 4 + cell] = (byte >> (6 - 2 * cell)) & 0x03;
+            }
+        }
+    }
+
+    /// Whether a *_TRN command is waiting for the next frame to capture its data.
+    pub fn transfer_pending(&self) -> bool {
+        self.pending_transfer.is_some()
+    }
+
+    /// Completes a pending *_TRN command with the tile data shown on screen.
+    pub fn transfer(&mut self, data: &[u8]) {
+        debug_assert!(
+            data.len() == TRANSFER_SIZE,
+            "SGB transfers are {} bytes, but {} were provided.",
+            TRANSFER_SIZE,
+            data.len()
+        );
+        let words = || {
+            data.chunks_exact(2)
+                .map(|word| u16::from_le_bytes([word[0], word[1]]))
+        };
+
+        match self.pending_transfer.take() {
+            Some(Transfer::Palettes) => {
+                for (entry, color) in self.system_palettes.iter_mut().zip(words()) {
+                    *entry = color & 0x7FFF;
+                }
+            }
+            Some(Transfer::BorderTiles(upper)) => {
+                let start = upper as usize * TRANSFER_SIZE;
+                self.border_tiles[start..start + TRANSFER_SIZE].copy_from_slice(data);
+            }
+            Some(Transfer::Border) => {
+                for (entry, word) in self.border_map.iter_mut().zip(words()) {
+                    *entry = word;
+                }
+                for (entry, color) in self.border_palettes.iter_mut().zip(words().skip(0x800 / 2)) {
+                    *entry = color & 0x7FFF;
+                }
+            }
+            Some(Transfer::AttributeFiles) => {
+                let length = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..length]);
+            }
+            None => {}
+        }
+    }
+
+    /// Records the shade of a pixel of the Gameboy screen. The screen is colorized when the frame
+    /// is rendered.
+    pub fn push_shade(&mut self, x: u8, y: u8, shade: u8) {
+        if self.mask != Mask::Freeze {
+            self.screen[y as usize * SCREEN_WIDTH + x as usize] = shade;
+        }
+    }
+
+    /// The color of a border pixel, or None where the border is transparent.
+    fn border_color(&self, x: usize, y: usize) -> Option<u16> {
+        let entry = self.border_map[(y / 8) * 32 + x / 8];
+        let tile = (entry & 0xFF) as usize;
+        let palette = ((entry >> 10) & 0x03) as usize;
+        let column = match entry & 0x4000 != 0 {
+            true => 7 - x % 8,
+            false => x % 8,
+        };
+        let row = match entry & 0x8000 != 0 {
+            true => 7 - y % 8,
+            false => y % 8,
+        };
+
+        // SNES tiles are stored as four bitplanes, interleaved in pairs
+        let address = tile * BORDER_TILE_SIZE + row * 2;
+        let color = [
+            self.border_tiles[address],
+            self.border_tiles[address + 1],
+            self.border_tiles[address + 16],
+            self.border_tiles[address + 17],
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |color, (plane, bits)| {
+            color | (((bits >> (7 - column)) & 0x01) << plane)
+        });
+
+        match color {
+            0 => None,
+            _ => Some(self.border_palettes[palette * 16 + color as usize]),
+        }
+    }
+
+    /// Renders the colorized screen within its border.
+    pub fn render(&self, display: &mut dyn Display) {
+        for y in 0..SGB_SCREEN_HEIGHT as usize {
+            for x in 0..SGB_SCREEN_WIDTH as usize {
+                let screen_x = x.wrapping_sub(SCREEN_X);
+                let screen_y = y.wrapping_sub(SCREEN_Y);
+                let on_screen = screen_x < SCREEN_WIDTH && screen_y < SCREEN_HEIGHT;
+
+                let color = match self.border_color(x, y) {
+                    Some(color) => color,
+                    None if on_screen => match self.mask {
+                        Mask::Black => 0x0000,
+                        Mask::Color0 => self.palettes[0][0],
+                        Mask::Cancel | Mask::Freeze => {
+                            let palette =
+                                self.attributes[(screen_y / 8) * ATTRIBUTE_WIDTH + screen_x / 8];
+                            let shade = self.screen[screen_y * SCREEN_WIDTH + screen_x];
+                            self.palettes[palette as usize][shade as usize]
+                        }
+                    },
+                    None => self.palettes[0][0],
+                };
+
+                display.push_pixel(x as u8, y as u8, color);
+            }
+        }
+    }
+}
+
+impl Default for SGB {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{anyhow, bail};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{cpu::CPUSnapshot, memory::MemoryBusSnapshot};
+
+/// Identifies a file as an emyco save state.
+const SAVE_STATE_MAGIC: &[u8; 8] = b"EMYCO-SS";
+
+/// The current version of the save state format. This must be incremented whenever the layout of
+/// any component state changes, as older states can no longer be decoded.
+pub const SAVE_STATE_VERSION: u32 = 7;
+
+/// Components that hold emulated machine state implement Snapshot so that their state can be
+/// captured and later restored exactly, including any work in progress partway through a frame.
+pub trait Snapshot {
+    type State: Serialize + DeserializeOwned;
+
+    /// Captures the current state of this component.
+    fn save_state(&self) -> Self::State;
+
+    /// Restores this component to a previously captured state. Host resources such as displays
+    /// and audio sinks are kept as they are.
+    fn load_state(&mut self, state: Self::State);
+}
+
+/// The complete state of a Gameboy at a single point in time.
+#[derive(Serialize, Deserialize)]
+pub struct SaveState {
+    pub title: String,
+    pub global_checksum: u16,
+    pub clock: u32,
+    pub cpu: CPUSnapshot,
+    pub memory: MemoryBusSnapshot,
+}
+
+impl SaveState {
+    /// Encodes this state in the versioned save state format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut bytes = Vec::from(*SAVE_STATE_MAGIC);
+        bytes.extend(SAVE_STATE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+
+        Ok(bytes)
+    }
+
+    /// Decodes a state previously encoded with to_bytes. Fails if the data is not a save state or
+    /// was written by an incompatible version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let header_length = SAVE_STATE_MAGIC.len() + 4;
+        if bytes.len() < header_length || &bytes[..SAVE_STATE_MAGIC.len()] != SAVE_STATE_MAGIC {
+            bail!("Data is not an emyco save state.");
+        }
+
+        let version = u32::from_le_bytes(
+            bytes[SAVE_STATE_MAGIC.len()..header_length]
+                .try_into()
+                .map_err(|_| anyhow!("Save state header is truncated."))?,
+        );
+        if version != SAVE_STATE_VERSION {
+            bail!(
+                "Save state version {} is not supported. Expected version {}.",
+                version,
+                SAVE_STATE_VERSION
+            );
+        }
+
+        Ok(bincode::deserialize(&bytes[header_length..])?)
+    }
+}
@@ -0,0 +1,817 @@
+mod cheats;
+mod debugger;
+mod disassembler;
+mod link;
+mod memory;
+mod pacer;
+mod printer;
+mod roms;
+mod screenshots;
+mod search;
+mod trace;
+mod viewer;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    rc::Rc,
+};
+
+use crate::emulator::{Emulator, EmulatorCommand, EmulatorHandle, InputSource};
+
+use chrono::{DateTime, Duration};
+
+use super::{
+    joypad::Buttons,
+    memory::{cartridge::Cartridge, MemoryBus, MemoryController, Register},
+    rewind::RewindBuffer,
+    Boot, BootRom, BufferSerialEndpoint, Clock, DebugCommand, Display, EmulatedClock, Gameboy,
+    GameboyConfig, GlobalConstants, ManualClock, Model, Movie, NullDisplay, NullRumbleSink,
+    RumbleSink, WallClock,
+};
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+struct FrameCounter(Rc<Cell<u32>>);
+
+impl Display for FrameCounter {
+    fn push_pixel(&mut self, _x: u8, _y: u8, _color: u16) {}
+
+    fn present(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+/// Delivers one scripted command to the running emulator, then the rest one at a time while it is
+/// paused, recording how many frames had been presented each time the emulator waited.
+struct ScriptedInput {
+    running: Option<EmulatorCommand>,
+    paused: VecDeque<EmulatorCommand>,
+    frames: Rc<Cell<u32>>,
+    waits: Rc<RefCell<Vec<u32>>>,
+}
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self) -> Option<EmulatorCommand> {
+        self.running.take()
+    }
+
+    fn wait(&mut self) -> Option<EmulatorCommand> {
+        self.waits.borrow_mut().push(self.frames.get());
+        self.paused.pop_front()
+    }
+}
+
+/// Builds a minimal ROM-only cartridge with a valid header that loops forever at 0x0150.
+pub fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+
+    // JP 0x0150
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+    rom[0x0134..0x0138].copy_from_slice(b"TEST");
+
+    let checksum = rom[0x0134..0x014D]
+        .iter()
+        .fold(0u8, |acc, byte| acc.wrapping_sub(*byte).wrapping_sub(1));
+    rom[0x014D] = checksum;
+
+    // JR -2
+    rom[0x0150..0x0152].copy_from_slice(&[0x18, 0xFE]);
+
+    rom
+}
+
+fn headless_gameboy(rom: Vec<u8>, frames: Rc<Cell<u32>>) -> Gameboy {
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+
+    let config = GameboyConfig::builder()
+        .display(FrameCounter(frames))
+        .input(receiver)
+        .build();
+
+    Gameboy::new(rom, config).unwrap()
+}
+
+fn run_frames(gameboy: &mut Gameboy, frames: u32) {
+    for _ in 0..(GlobalConstants::FRAME_CYCLES * frames / GlobalConstants::CYCLE_RESOLUTION) {
+        gameboy.tick();
+    }
+}
+
+#[test]
+fn gameboy_runs_headless() {
+    let frames = Rc::new(Cell::new(0));
+    let mut gameboy = headless_gameboy(test_rom(), frames.clone());
+
+    run_frames(&mut gameboy, 60);
+
+    assert!(frames.get() > 0, "No frames were presented.");
+}
+
+#[test]
+fn save_state_resumes_deterministically() {
+    let mut gameboy = headless_gameboy(test_rom(), Rc::new(Cell::new(0)));
+
+    // Stop partway through a frame so that in-flight PPU and CPU state is captured.
+    run_frames(&mut gameboy, 10);
+    for _ in 0..1000 {
+        gameboy.tick();
+    }
+    let snapshot = gameboy.save_state().unwrap();
+
+    run_frames(&mut gameboy, 10);
+    let expected = gameboy.save_state().unwrap();
+
+    gameboy.load_state(&snapshot).unwrap();
+    run_frames(&mut gameboy, 10);
+
+    assert_eq!(gameboy.save_state().unwrap(), expected);
+}
+
+#[test]
+fn save_state_rejects_other_roms() {
+    let gameboy = headless_gameboy(test_rom(), Rc::new(Cell::new(0)));
+    let snapshot = gameboy.save_state().unwrap();
+
+    let mut other_rom = test_rom();
+    other_rom[0x0134..0x0138].copy_from_slice(b"OTHR");
+    let mut other = headless_gameboy(other_rom, Rc::new(Cell::new(0)));
+
+    assert!(other.load_state(&snapshot).is_err());
+    assert!(other.load_state(b"not a save state").is_err());
+}
+
+#[test]
+fn save_state_rejects_truncated_memory() {
+    let mut gameboy = headless_gameboy(test_rom(), Rc::new(Cell::new(0)));
+    let mut snapshot = gameboy.save_state().unwrap();
+
+    // Drop the last byte of internal memory, which bincode prefixes with its length
+    let prefix = (16384u64).to_le_bytes();
+    let start = snapshot
+        .windows(prefix.len())
+        .position(|window| window == prefix)
+        .unwrap();
+    snapshot[start..start + prefix.len()].copy_from_slice(&(16383u64).to_le_bytes());
+    snapshot.remove(start + prefix.len() + 16383);
+
+    assert!(gameboy.load_state(&snapshot).is_err());
+}
+
+#[test]
+fn rewind_restores_states_newest_first() {
+    let mut gameboy = headless_gameboy(test_rom(), Rc::new(Cell::new(0)));
+    // Two seconds of history captured every frame, more than will fit.
+    let mut rewind = RewindBuffer::new(2, 1);
+
+    let mut states = Vec::new();
+    for _ in 0..180 {
+        run_frames(&mut gameboy, 1);
+        let state = gameboy.save_state().unwrap();
+        assert!(rewind.frame());
+        rewind.push(state.clone());
+        states.push(state);
+    }
+
+    let raw_size: usize = states.iter().rev().take(120).map(Vec::len).sum();
+    assert!(
+        rewind.size() < raw_size / 4,
+        "Rewind history is not compressed."
+    );
+
+    for expected in states.iter().rev().take(120) {
+        assert_eq!(&rewind.pop().unwrap().unwrap(), expected);
+    }
+
+    // Once history is exhausted the oldest state is held rather than discarded.
+    let oldest = rewind.pop().unwrap().unwrap();
+    assert_eq!(&oldest, states.iter().rev().nth(119).unwrap());
+
+    gameboy.load_state(&oldest).unwrap();
+}
+
+#[test]
+fn frame_advance_runs_single_frames() {
+    let frames = Rc::new(Cell::new(0));
+    let waits = Rc::new(RefCell::new(Vec::new()));
+
+    let config = GameboyConfig::builder()
+        .display(FrameCounter(frames.clone()))
+        .input(ScriptedInput {
+            running: Some(EmulatorCommand::Pause),
+            paused: VecDeque::from([EmulatorCommand::FrameAdvance, EmulatorCommand::FrameAdvance]),
+            frames: frames.clone(),
+            waits: waits.clone(),
+        })
+        .speed(crate::emulator::EmulationSpeed::Unthrottled)
+        .build();
+
+    let mut gameboy = Gameboy::new(test_rom(), config).unwrap();
+
+    // Run past the boot ROM, which keeps the LCD off while clearing VRAM.
+    run_frames(&mut gameboy, 60);
+    gameboy.start().unwrap();
+
+    let waits = waits.borrow();
+    assert_eq!(waits.len(), 3);
+    assert!(waits[0] > 0, "Paused before any frame was presented.");
+    assert_eq!(waits[1], waits[0] + 1);
+    assert_eq!(waits[2], waits[1] + 1);
+}
+
+/// Stands in for the emulator on its thread, reporting that it started and then waiting for Stop.
+struct StartedEmulator {
+    input: crossbeam::channel::Receiver<EmulatorCommand>,
+    started: crossbeam::channel::Sender<()>,
+}
+
+impl Emulator for StartedEmulator {
+    fn start(&mut self) -> Result<(), anyhow::Error> {
+        self.started.send(())?;
+        while !matches!(self.input.wait(), Some(EmulatorCommand::Stop) | None) {}
+
+        Ok(())
+    }
+}
+
+#[test]
+fn emulator_handle_ignores_commands_before_start() {
+    let (started, started_receiver) = crossbeam::channel::unbounded();
+    let mut handle = EmulatorHandle::new(move |input| Ok(StartedEmulator { input, started }));
+
+    handle.pause();
+    handle.debug(0, DebugCommand::Inspect);
+    handle.start();
+
+    started_receiver
+        .recv_timeout(std::time::Duration::from_secs(1))
+        .expect("The emulator never started.");
+    handle.stop();
+}
+
+#[test]
+fn movie_playback_reproduces_recording() {
+    let mut gameboy = headless_gameboy(test_rom(), Rc::new(Cell::new(0)));
+    run_frames(&mut gameboy, 5);
+
+    gameboy.record_movie().unwrap();
+    for frame in 0..120 {
+        gameboy.buttons = match frame % 30 {
+            0..10 => Buttons::A | Buttons::RIGHT,
+            10..15 => Buttons::START,
+            _ => Buttons::empty(),
+        };
+        gameboy.run_frame();
+    }
+    // Stopping puts the configured RTC clock back, so the state is captured while recording
+    let expected = gameboy.save_state().unwrap();
+    let movie = gameboy.stop_movie().unwrap();
+
+    let movie = Movie::from_bytes(&movie.to_bytes().unwrap()).unwrap();
+    assert_eq!(movie.len(), 121);
+
+    gameboy.buttons = Buttons::B;
+    gameboy.play_movie(movie).unwrap();
+    for _ in 0..120 {
+        gameboy.run_frame();
+    }
+
+    assert_eq!(gameboy.save_state().unwrap(), expected);
+}
+
+#[test]
+fn movie_playback_restores_save_ram_and_clock() {
+    let mut rom = test_rom();
+    // MBC3+TIMER+RAM+BATTERY with 32KiB of RAM
+    rom[0x0147] = 0x10;
+    rom[0x0149] = 0x03;
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .boot(Boot::Skip)
+        .rtc_clock(ManualClock::new(
+            DateTime::from_timestamp(30, 0).unwrap().naive_utc(),
+        ))
+        .build();
+    let mut gameboy = Gameboy::new(rom, config).unwrap();
+
+    let write_save = |gameboy: &Gameboy, value| {
+        let mut memory = gameboy.memory.write().unwrap();
+        memory.cartridge_mut().write_ram(0x10, value);
+    };
+    let read_save = |gameboy: &Gameboy| gameboy.memory.read().unwrap().cartridge().read_ram(0x10);
+    let rtc_seconds = |gameboy: &Gameboy| {
+        let mut memory = gameboy.memory.write().unwrap();
+        let cartridge = memory.cartridge_mut();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x08);
+        read_rtc_seconds(cartridge)
+    };
+
+    write_save(&gameboy, 0x11);
+    gameboy.record_movie().unwrap();
+    gameboy.run_frame();
+    let movie = gameboy.stop_movie().unwrap();
+    assert_eq!(rtc_seconds(&gameboy), 30);
+
+    write_save(&gameboy, 0x22);
+    let rtc_seed = movie.rtc_seed();
+    gameboy.play_movie(movie).unwrap();
+    assert_eq!(read_save(&gameboy), 0x11);
+    assert_eq!(rtc_seconds(&gameboy) as i64, rtc_seed % 60);
+
+    // The game keeps running on the movie's save RAM until it is reset
+    gameboy.stop_movie();
+    assert_eq!(read_save(&gameboy), 0x11);
+    assert_eq!(rtc_seconds(&gameboy), 30);
+    gameboy.reset().unwrap();
+    assert_eq!(read_save(&gameboy), 0x22);
+}
+
+/// Latches the MBC3 RTC and reads its seconds register.
+fn read_rtc_seconds(cartridge: &mut Cartridge) -> u8 {
+    cartridge.write(0x6000, 0x00);
+    cartridge.write(0x6000, 0x01);
+    cartridge.read(0xA000)
+}
+
+fn mbc3_cartridge(clock: impl Clock + 'static, save_directory: Option<PathBuf>) -> Cartridge {
+    let mut rom = test_rom();
+    // MBC3+TIMER+RAM+BATTERY with 32KiB of RAM
+    rom[0x0147] = 0x10;
+    rom[0x0149] = 0x03;
+
+    let mut cartridge = Cartridge::new(
+        rom,
+        save_directory,
+        Box::new(clock),
+        Box::new(NullRumbleSink),
+    )
+    .unwrap();
+    // Enable RAM and RTC access, and map the seconds register
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x4000, 0x08);
+    cartridge
+}
+
+#[test]
+fn rtc_reads_injected_manual_clock() {
+    let clock = ManualClock::new(DateTime::from_timestamp(30, 0).unwrap().naive_utc());
+    let mut cartridge = mbc3_cartridge(clock.clone(), None);
+
+    assert_eq!(read_rtc_seconds(&mut cartridge), 30);
+
+    clock.advance(Duration::seconds(5));
+    assert_eq!(read_rtc_seconds(&mut cartridge), 35);
+}
+
+#[test]
+fn rtc_follows_emulated_cycles() {
+    let mut cartridge = mbc3_cartridge(EmulatedClock::new(0), None);
+
+    assert_eq!(read_rtc_seconds(&mut cartridge), 0);
+
+    for _ in 0..3 {
+        cartridge.tick(GlobalConstants::SYSTEM_CLOCK_RATE);
+    }
+    assert_eq!(read_rtc_seconds(&mut cartridge), 3);
+}
+
+#[test]
+fn rtc_persists_in_save_footer() {
+    let save_directory = std::env::temp_dir().join(format!("emyco-rtc-{}", std::process::id()));
+    fs::create_dir_all(&save_directory).unwrap();
+
+    let clock = ManualClock::new(DateTime::from_timestamp(30, 0).unwrap().naive_utc());
+    let mut cartridge = mbc3_cartridge(clock.clone(), Some(save_directory.clone()));
+    // Set the hours register to 5
+    cartridge.write(0x4000, 0x0A);
+    cartridge.write(0xA000, 5);
+    drop(cartridge);
+
+    let save = fs::read(save_directory.join("TEST.sav")).unwrap();
+    assert_eq!(save.len(), 32 * 1024 + 48);
+
+    // An hour and ten seconds pass while the game is off
+    clock.advance(Duration::seconds(3610));
+    let mut cartridge = mbc3_cartridge(clock, Some(save_directory.clone()));
+    assert_eq!(read_rtc_seconds(&mut cartridge), 40);
+    cartridge.write(0x4000, 0x0A);
+    assert_eq!(cartridge.read(0xA000), 6);
+
+    fs::remove_dir_all(save_directory).unwrap();
+}
+
+#[derive(Debug, Default)]
+struct RecordingRumble(Rc<RefCell<Vec<bool>>>);
+
+impl RumbleSink for RecordingRumble {
+    fn set_rumble(&mut self, active: bool) {
+        self.0.borrow_mut().push(active);
+    }
+}
+
+#[test]
+fn mbc5_banks_rom_and_reports_rumble() {
+    // 512 banks of 16KiB, each starting with its own 9-bit bank number
+    let mut rom = test_rom();
+    rom.resize(512 * 0x4000, 0);
+    rom[0x0000] = 0xAA;
+    for bank in 1..512 {
+        rom[bank * 0x4000] = bank as u8;
+        rom[bank * 0x4000 + 1] = (bank >> 8) as u8;
+    }
+    // MBC5+RUMBLE+RAM+BATTERY with 128KiB of RAM
+    rom[0x0147] = 0x1E;
+    rom[0x0149] = 0x04;
+
+    let rumble = RecordingRumble::default();
+    let events = rumble.0.clone();
+    let mut cartridge = Cartridge::new(rom, None, Box::new(WallClock), Box::new(rumble)).unwrap();
+
+    cartridge.write(0x2000, 0x23);
+    cartridge.write(0x3000, 0x01);
+    assert_eq!(
+        (cartridge.read(0x4000), cartridge.read(0x4001)),
+        (0x23, 0x01)
+    );
+
+    // Bank 0 can be mapped to the switchable region
+    cartridge.write(0x2000, 0x00);
+    cartridge.write(0x3000, 0x00);
+    assert_eq!(cartridge.read(0x4000), 0xAA);
+
+    // RAM banks are selected by the lower bits, and bit 3 drives the motor
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x4000, 0x0B);
+    cartridge.write(0xA000, 0x42);
+    cartridge.write(0x4000, 0x03);
+    assert_eq!(cartridge.read(0xA000), 0x42);
+    cartridge.write(0x4000, 0x00);
+    assert_eq!(cartridge.read(0xA000), 0x00);
+
+    assert_eq!(*events.borrow(), vec![true, false]);
+}
+
+/// Records the color of the top left pixel of every frame.
+struct TopLeftPixel(Rc<Cell<u16>>);
+
+impl Display for TopLeftPixel {
+    fn push_pixel(&mut self, x: u8, y: u8, color: u16) {
+        if (x, y) == (0, 0) {
+            self.0.set(color);
+        }
+    }
+
+    fn present(&mut self) {}
+}
+
+#[test]
+fn cgb_mode_banks_memory_and_renders_color_palettes() {
+    let mut rom = test_rom();
+    rom[0x0143] = 0x80;
+
+    let pixel = Rc::new(Cell::new(0));
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(TopLeftPixel(pixel.clone()))
+        .input(receiver)
+        .build();
+    let mut gameboy = Gameboy::new(rom, config).unwrap();
+
+    {
+        let mut memory = gameboy.memory.write().unwrap();
+
+        // WRAM banks 2 and 3 are independent
+        memory.write_byte(0xFF70, 0x02);
+        memory.write_byte(0xD000, 0x12);
+        memory.write_byte(0xFF70, 0x03);
+        assert_eq!(memory.read_byte(0xD000), 0x00);
+        memory.write_byte(0xFF70, 0x02);
+        assert_eq!(memory.read_byte(0xD000), 0x12);
+
+        // VRAM bank 1 holds the tile attributes, leaving bank 0 untouched
+        memory.write_byte(0xFF4F, 0x01);
+        memory.write_byte(0x9800, 0x08);
+        memory.write_byte(0xFF4F, 0x00);
+        assert_eq!(memory.read_byte(0xFF4F), 0xFE);
+        assert_eq!(memory.read_byte(0x9800), 0x00);
+        memory.write_byte(0xFF4F, 0x01);
+        assert_eq!(memory.read_byte(0x9800), 0x08);
+        memory.write_byte(0x9800, 0x00);
+        memory.write_byte(0xFF4F, 0x00);
+
+        // Set color 0 of background palette 0 to pure red, auto-incrementing the index
+        memory.write_byte(0xFF68, 0x80);
+        memory.write_byte(0xFF69, 0x1F);
+        memory.write_byte(0xFF69, 0x00);
+        assert_eq!(memory.read_byte(0xFF68), 0xC2);
+
+        // Arm and perform a speed switch
+        memory.write_byte(0xFF4D, 0x01);
+        assert!(memory.switch_speed());
+        assert_eq!(memory.read_byte(0xFF4D), 0xFE);
+    }
+
+    run_frames(&mut gameboy, 2);
+    assert_eq!(pixel.get(), 0x001F);
+}
+
+#[test]
+fn stop_performs_an_armed_speed_switch() {
+    let mut rom = test_rom();
+    rom[0x0143] = 0xC0;
+    // STOP, then LD A,0x42; LD (0xC000),A; JR -2
+    rom[0x0150..0x0159].copy_from_slice(&[0x10, 0x00, 0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+    let mut gameboy = booted_gameboy(rom, Boot::Skip, Some(Model::CGB));
+    gameboy.memory.write().unwrap().write_byte(0xFF4D, 0x01);
+
+    run_frames(&mut gameboy, 1);
+
+    // The CPU switched to double speed and carried on past STOP
+    let memory = gameboy.memory.read().unwrap();
+    assert_eq!(memory.read_byte(0xFF4D), 0xFE);
+    assert_eq!(memory.read_byte(0xC000), 0x42);
+}
+
+fn cgb_gameboy() -> Gameboy {
+    let mut rom = test_rom();
+    rom[0x0143] = 0xC0;
+    headless_gameboy(rom, Rc::new(Cell::new(0)))
+}
+
+/// Fills WRAM from 0xC000 with incrementing bytes and points the VRAM DMA source there and its
+/// destination at 0x8000.
+fn prepare_vram_dma(memory: &mut MemoryBus) {
+    for offset in 0..0x80u16 {
+        memory.write_byte(0xC000 + offset, offset as u8);
+    }
+    memory.write_byte(0xFF51, 0xC0);
+    memory.write_byte(0xFF52, 0x00);
+    memory.write_byte(0xFF53, 0x00);
+    memory.write_byte(0xFF54, 0x00);
+}
+
+#[test]
+fn general_purpose_dma_copies_immediately_and_stalls_cpu() {
+    let gameboy = cgb_gameboy();
+    let mut memory = gameboy.memory.write().unwrap();
+    prepare_vram_dma(&mut memory);
+
+    // Two blocks of 16 bytes
+    memory.write_byte(0xFF55, 0x01);
+
+    assert_eq!(memory.read_byte(0xFF55), 0xFF);
+    assert_eq!(memory.read_byte(0x801F), 0x1F);
+    assert_eq!(memory.read_byte(0x8020), 0x00);
+    assert!(memory.cpu_stalled());
+
+    memory.tick(64);
+    assert!(!memory.cpu_stalled());
+}
+
+#[test]
+fn hblank_dma_copies_a_block_per_hblank_until_cancelled() {
+    let mut gameboy = cgb_gameboy();
+    run_frames(&mut gameboy, 1);
+
+    {
+        let mut memory = gameboy.memory.write().unwrap();
+        prepare_vram_dma(&mut memory);
+        // Four blocks, one per HBlank
+        memory.write_byte(0xFF55, 0x83);
+        assert_eq!(memory.read_byte(0xFF55), 0x03);
+    }
+
+    // Each scanline has exactly one HBlank
+    for _ in 0..(2 * 456 / GlobalConstants::CYCLE_RESOLUTION) {
+        gameboy.tick();
+    }
+
+    let mut memory = gameboy.memory.write().unwrap();
+    assert_eq!(memory.read_byte(0xFF55), 0x01);
+    assert_eq!(memory.read_byte(0x801F), 0x1F);
+    assert_eq!(memory.read_byte(0x8020), 0x00);
+
+    // Cancelling leaves bit 7 set along with the blocks that were left
+    memory.write_byte(0xFF55, 0x00);
+    assert_eq!(memory.read_byte(0xFF55), 0x81);
+}
+
+/// Records every pixel of the latest frame, at whatever size the display is resized to.
+struct FrameBuffer {
+    width: usize,
+    pixels: Rc<RefCell<Vec<u16>>>,
+}
+
+impl Display for FrameBuffer {
+    fn push_pixel(&mut self, x: u8, y: u8, color: u16) {
+        self.pixels.borrow_mut()[y as usize * self.width + x as usize] = color;
+    }
+
+    fn present(&mut self) {}
+
+    fn resize(&mut self, width: u16, height: u16) {
+        self.width = width as usize;
+        *self.pixels.borrow_mut() = vec![0; width as usize * height as usize];
+    }
+}
+
+/// Sends a single SGB command packet through the joypad register, followed by the stop bit.
+fn send_sgb_packet(memory: &mut MemoryBus, packet: [u8; 16]) {
+    for value in [0x30, 0x00, 0x30] {
+        memory.write_byte(0xFF00, value);
+    }
+    for bit in (0..128).map(|index| packet[index / 8] >> (index % 8) & 0x01 != 0) {
+        memory.write_byte(0xFF00, if bit { 0x10 } else { 0x20 });
+        memory.write_byte(0xFF00, 0x30);
+    }
+    memory.write_byte(0xFF00, 0x20);
+    memory.write_byte(0xFF00, 0x30);
+}
+
+#[test]
+fn sgb_mode_decodes_packets_and_renders_border() {
+    let mut rom = test_rom();
+    rom[0x0146] = 0x03;
+    rom[0x014B] = 0x33;
+
+    let pixels = Rc::new(RefCell::new(Vec::new()));
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(FrameBuffer {
+            width: 160,
+            pixels: pixels.clone(),
+        })
+        .input(receiver)
+        .build();
+    let mut gameboy = Gameboy::new(rom, config).unwrap();
+    assert_eq!(pixels.borrow().len(), 256 * 224);
+
+    {
+        let mut memory = gameboy.memory.write().unwrap();
+
+        // MLT_REQ for two players, after which the controller ID is readable and advances when
+        // P15 is released
+        let mut packet = [0; 16];
+        packet[0] = (0x11 << 3) | 0x01;
+        packet[1] = 0x01;
+        send_sgb_packet(&mut memory, packet);
+        assert_eq!(memory.read_byte(0xFF00) & 0x0F, 0x0F);
+        memory.write_byte(0xFF00, 0x10);
+        memory.write_byte(0xFF00, 0x30);
+        assert_eq!(memory.read_byte(0xFF00) & 0x0F, 0x0E);
+
+        // PAL01 with red as the shared color 0
+        let mut packet = [0; 16];
+        packet[0] = 0x01;
+        packet[1..3].copy_from_slice(&0x001Fu16.to_le_bytes());
+        send_sgb_packet(&mut memory, packet);
+
+        // MASK_EN blacks out the screen
+        let mut packet = [0; 16];
+        packet[0] = (0x17 << 3) | 0x01;
+        packet[1] = 0x02;
+        send_sgb_packet(&mut memory, packet);
+    }
+
+    run_frames(&mut gameboy, 2);
+
+    // Without a border, the backdrop shows color 0 around the screen at (48, 40)
+    let pixels = pixels.borrow();
+    assert_eq!(pixels[0], 0x001F);
+    assert_eq!(pixels[40 * 256 + 47], 0x001F);
+    assert_eq!(pixels[40 * 256 + 48], 0x0000);
+    assert_eq!(pixels[183 * 256 + 207], 0x0000);
+    assert_eq!(pixels[184 * 256 + 207], 0x001F);
+}
+
+fn booted_gameboy(rom: Vec<u8>, boot: Boot, model: Option<Model>) -> Gameboy {
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let mut config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .boot(boot);
+    if let Some(model) = model {
+        config = config.model(model);
+    }
+
+    Gameboy::new(rom, config.build()).unwrap()
+}
+
+/// An MBC5 cartridge with 32KiB of RAM and the given number of ROM banks, each after the first
+/// filled with its bank number, booted straight into the cartridge.
+fn banked_gameboy(rom_banks: usize) -> Gameboy {
+    let mut rom = test_rom();
+    rom.resize(rom_banks * 0x4000, 0);
+    for bank in 1..rom_banks {
+        rom[bank * 0x4000..(bank + 1) * 0x4000].fill(bank as u8);
+    }
+    // MBC5+RAM with 32KiB of RAM
+    rom[0x0147] = 0x1A;
+    rom[0x0149] = 0x03;
+
+    booted_gameboy(rom, Boot::Skip, None)
+}
+
+fn read_byte(gameboy: &Gameboy, address: u16) -> u8 {
+    gameboy.memory.read().unwrap().read_byte(address)
+}
+
+#[test]
+fn skipped_boot_leaves_model_specific_state() {
+    // LD (0xC000),A followed by JR -2, to observe the register A left by the boot ROM
+    let mut rom = test_rom();
+    rom[0x0150..0x0155].copy_from_slice(&[0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+
+    for (model, register_a, div, logo) in [
+        (Model::DMG, 0x01, 0xAB, true),
+        (Model::MGB, 0xFF, 0xAB, true),
+        (Model::SGB, 0x01, 0x00, false),
+        (Model::CGB, 0x11, 0x00, false),
+    ] {
+        let mut gameboy = booted_gameboy(rom.clone(), Boot::Skip, Some(model));
+        {
+            let memory = gameboy.memory.read().unwrap();
+            assert_eq!(memory.read_byte(0xFF04), div, "{:?}", model);
+            assert_eq!(memory.read_byte(0xFF40), 0x91, "{:?}", model);
+            assert_eq!(memory.read_byte(0x9910) == 0x19, logo, "{:?}", model);
+            if logo {
+                // The top half of the first logo byte, 0xCE, doubled horizontally
+                assert_eq!(memory.read_byte(0x8010), 0xF0);
+                assert_eq!(memory.read_byte(0x8012), 0xF0);
+            }
+        }
+
+        run_frames(&mut gameboy, 1);
+        assert_eq!(read_byte(&gameboy, 0xC000), register_a, "{:?}", model);
+    }
+}
+
+#[test]
+fn custom_boot_rom_runs_before_cartridge() {
+    assert!(BootRom::new(Model::CGB, vec![0; 0x100]).is_err());
+
+    // LD A,0x42; LD (0xC000),A, then unmap the boot ROM right before the entry point
+    let mut data = vec![0; 0x100];
+    data[0x00..0x05].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xC0]);
+    data[0xFC..0x100].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    let boot_rom = BootRom::new(Model::MGB, data).unwrap();
+
+    let mut gameboy = booted_gameboy(test_rom(), Boot::Rom(boot_rom), None);
+    assert_eq!(read_byte(&gameboy, 0x0000), 0x3E);
+
+    run_frames(&mut gameboy, 1);
+    let memory = gameboy.memory.read().unwrap();
+    assert_eq!(memory.read_byte(0xC000), 0x42);
+    assert_eq!(memory.read_byte(0x0000), 0x00);
+}
+
+#[test]
+fn serial_transfers_exchange_bytes_with_endpoint() {
+    // Sends 0x42 on the internal clock, then 0x24 on the external clock, storing each received
+    // byte in 0xC000 and 0xC001
+    let mut rom = test_rom();
+    rom[0x0150..0x0178].copy_from_slice(&[
+        // LD A,0x42; LDH (SB),A; LD A,0x81; LDH (SC),A
+        0x3E, 0x42, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02,
+        // LDH A,(SC); BIT 7,A; JR NZ,-6; LDH A,(SB); LD (0xC000),A
+        0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA, 0xF0, 0x01, 0xEA, 0x00, 0xC0,
+        // LD A,0x24; LDH (SB),A; LD A,0x80; LDH (SC),A
+        0x3E, 0x24, 0xE0, 0x01, 0x3E, 0x80, 0xE0, 0x02,
+        // LDH A,(SC); BIT 7,A; JR NZ,-6; LDH A,(SB); LD (0xC001),A; JR -2
+        0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA, 0xF0, 0x01, 0xEA, 0x01, 0xC0, 0x18, 0xFE,
+    ]);
+
+    let serial = BufferSerialEndpoint::new();
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .serial(serial.clone())
+        .boot(Boot::Skip)
+        .build();
+    let mut gameboy = Gameboy::new(rom, config).unwrap();
+
+    serial.push_incoming(&[0x99]);
+    run_frames(&mut gameboy, 1);
+    assert_eq!(read_byte(&gameboy, 0xC000), 0x99);
+    assert_eq!(serial.take_sent(), vec![0x42]);
+
+    // Without a clock from the other side, the second transfer never completes
+    run_frames(&mut gameboy, 1);
+    assert_eq!(read_byte(&gameboy, 0xC001), 0x00);
+
+    serial.push_incoming(&[0x66]);
+    run_frames(&mut gameboy, 1);
+    assert_eq!(read_byte(&gameboy, 0xC001), 0x66);
+    assert_eq!(serial.take_sent(), vec![0x24]);
+}
@@ -0,0 +1,189 @@
+use std::{env, fs};
+
+use super::{banked_gameboy, read_byte, test_rom};
+use crate::{
+    emulator::EmulatorCommand,
+    gameboy::{
+        memory::MemoryController, Boot, Cheat, CheatCode, DebugCommand, DebugEvent, Gameboy,
+        GameboyConfig, MemoryRegion, NullDisplay,
+    },
+};
+
+#[test]
+fn codes_are_decoded_and_written_back_as_entered() {
+    let code: CheatCode = "00A-17B-C49".parse().unwrap();
+    assert_eq!(
+        code,
+        CheatCode::GameGenie {
+            bank: None,
+            address: 0x4A17,
+            value: 0x00,
+            compare: Some(0xC8),
+            checksum: 0x04,
+        }
+    );
+    assert_eq!(code.to_string(), "00A-17B-C49");
+
+    let code: CheatCode = "01ff12c0".parse().unwrap();
+    assert_eq!(
+        code,
+        CheatCode::GameShark {
+            bank: 0x01,
+            value: 0xFF,
+            address: 0xC012,
+        }
+    );
+    assert_eq!(code.to_string(), "01FF12C0");
+
+    for code in ["02:C30-50E", "3E1-23F"] {
+        assert_eq!(code.parse::<CheatCode>().unwrap().to_string(), code);
+    }
+
+    let cheat = Cheat {
+        code: "00A-17B-C49".parse().unwrap(),
+        enabled: true,
+    };
+    let json = serde_json::to_string(&cheat).unwrap();
+    assert_eq!(json, r#"{"code":"00A-17B-C49","enabled":true}"#);
+    assert_eq!(serde_json::from_str::<Cheat>(&json).unwrap(), cheat);
+}
+
+#[test]
+fn invalid_codes_are_rejected() {
+    for code in [
+        "",
+        "00A-17B-C",
+        "00A-17G",
+        // Game Genie codes only patch ROM, and GameShark codes only write RAM
+        "00A-177",
+        "01FF0040",
+        "01:01FF00C0",
+    ] {
+        assert!(
+            code.parse::<CheatCode>().is_err(),
+            "{:?} was accepted",
+            code
+        );
+    }
+}
+
+#[test]
+fn game_genie_codes_patch_matching_rom_reads() {
+    let mut gameboy = banked_gameboy(4);
+    gameboy.memory.write().unwrap().write_byte(0x2000, 0x02);
+
+    // Replaces 0x02 at 0x4100 with 0x99, so only bank 2
+    let code = CheatCode::GameGenie {
+        bank: None,
+        address: 0x4100,
+        value: 0x99,
+        compare: Some(0x02),
+        checksum: 0,
+    };
+    gameboy.debug(DebugCommand::AddCheat(code));
+    assert_eq!(read_byte(&gameboy, 0x4100), 0x99);
+    assert_eq!(read_byte(&gameboy, 0x4101), 0x02);
+
+    gameboy.memory.write().unwrap().write_byte(0x2000, 0x03);
+    assert_eq!(read_byte(&gameboy, 0x4100), 0x03);
+
+    // A bank limits the patch to when that bank is mapped
+    gameboy.debug(DebugCommand::RemoveCheat(code));
+    gameboy.debug(DebugCommand::AddCheat("01:770-01B".parse().unwrap()));
+    assert_eq!(read_byte(&gameboy, 0x4001), 0x03);
+    gameboy.memory.write().unwrap().write_byte(0x2000, 0x01);
+    assert_eq!(read_byte(&gameboy, 0x4001), 0x77);
+
+    // The ROM itself is left unchanged
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Rom(1), 1, 1).unwrap(),
+        vec![0x01]
+    );
+}
+
+#[test]
+fn game_shark_codes_write_ram_every_frame() {
+    let mut gameboy = banked_gameboy(4);
+    let code = "01FF00C0".parse().unwrap();
+    gameboy.debug(DebugCommand::AddCheat(code));
+
+    gameboy.run_frame();
+    assert_eq!(read_byte(&gameboy, 0xC000), 0xFF);
+
+    gameboy.memory.write().unwrap().write_byte(0xC000, 0x00);
+    gameboy.run_frame();
+    assert_eq!(read_byte(&gameboy, 0xC000), 0xFF);
+
+    gameboy.debug(DebugCommand::SetCheatEnabled(code, false));
+    gameboy.memory.write().unwrap().write_byte(0xC000, 0x00);
+    gameboy.run_frame();
+    assert_eq!(read_byte(&gameboy, 0xC000), 0x00);
+}
+
+#[test]
+fn cheats_are_saved_with_the_rom() {
+    let save_directory = env::temp_dir().join(format!("emyco-cheats-{}", std::process::id()));
+    fs::create_dir_all(&save_directory).unwrap();
+    let gameboy = || {
+        let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+        let config = GameboyConfig::builder()
+            .display(NullDisplay)
+            .input(receiver)
+            .boot(Boot::Skip)
+            .save_directory(Some(save_directory.clone()))
+            .build();
+        Gameboy::new(test_rom(), config).unwrap()
+    };
+
+    let mut first = gameboy();
+    let genie = "00A-17B-C49".parse().unwrap();
+    let shark = "01FF00C0".parse().unwrap();
+    first.debug(DebugCommand::AddCheat(genie));
+    first.debug(DebugCommand::AddCheat(shark));
+    first.debug(DebugCommand::SetCheatEnabled(genie, false));
+    let cheats = vec![
+        Cheat {
+            code: genie,
+            enabled: false,
+        },
+        Cheat {
+            code: shark,
+            enabled: true,
+        },
+    ];
+    assert_eq!(
+        first.debug_reply(DebugCommand::ListCheats, None),
+        DebugEvent::Cheats(cheats.clone())
+    );
+
+    let second = gameboy();
+    fs::remove_dir_all(&save_directory).unwrap();
+    assert_eq!(second.cheats(), cheats);
+}
+
+#[test]
+fn movies_replay_with_the_cheats_they_were_recorded_with() {
+    let mut gameboy = banked_gameboy(4);
+    let recorded = "014200C0".parse().unwrap();
+    let other = "01FF01C0".parse().unwrap();
+    gameboy.debug(DebugCommand::AddCheat(recorded));
+
+    gameboy.record_movie().unwrap();
+    // Cheats can't change partway through a movie
+    gameboy.debug(DebugCommand::AddCheat(other));
+    gameboy.run_frame();
+    let movie = gameboy.stop_movie().unwrap();
+    assert_eq!(movie.cheats(), [recorded]);
+
+    gameboy.debug(DebugCommand::RemoveCheat(recorded));
+    gameboy.debug(DebugCommand::AddCheat(other));
+    let cheats = gameboy.cheats();
+
+    gameboy.play_movie(movie).unwrap();
+    gameboy.run_frame();
+    assert_eq!(read_byte(&gameboy, 0xC000), 0x42);
+    assert_ne!(read_byte(&gameboy, 0xC001), 0xFF);
+
+    gameboy.stop_movie();
+    assert_eq!(gameboy.cheats(), cheats);
+}
@@ -0,0 +1,252 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+use super::{read_byte, test_rom, ScriptedInput};
+use crate::{
+    emulator::{Emulator, EmulatorCommand},
+    gameboy::{
+        Access, Boot, Breakpoint, Comparison, Condition, DebugCommand, DebugEvent, Flags, Gameboy,
+        GameboyConfig, GlobalConstants, NullDisplay, RegisterName, StopReason, Watchpoint,
+    },
+};
+
+/// Calls a function that increments A and stores it, then stores A again and loops forever.
+fn debugger_rom() -> Vec<u8> {
+    let mut rom = test_rom();
+    // LD A,0x05; CALL 0x0200; LD (0xC000),A; JR -2
+    rom[0x0150..0x015A]
+        .copy_from_slice(&[0x3E, 0x05, 0xCD, 0x00, 0x02, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+    // INC A; LD (0xC001),A; RET
+    rom[0x0200..0x0205].copy_from_slice(&[0x3C, 0xEA, 0x01, 0xC0, 0xC9]);
+    rom
+}
+
+fn debugger_gameboy() -> Gameboy {
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .boot(Boot::Skip)
+        .build();
+
+    Gameboy::new(debugger_rom(), config).unwrap()
+}
+
+/// Ticks for up to a frame, returning why the debugger stopped execution if it did.
+fn run_until_stop(gameboy: &mut Gameboy) -> Option<StopReason> {
+    for _ in 0..(GlobalConstants::FRAME_CYCLES / GlobalConstants::CYCLE_RESOLUTION) {
+        gameboy.tick();
+        if let Some(reason) = gameboy.take_stop_reason() {
+            return Some(reason);
+        }
+    }
+
+    None
+}
+
+fn breakpoint(address: u16) -> DebugCommand {
+    DebugCommand::AddBreakpoint(Breakpoint {
+        address,
+        condition: None,
+    })
+}
+
+#[test]
+fn breakpoints_stop_before_the_instruction_runs() {
+    let mut gameboy = debugger_gameboy();
+
+    // The condition doesn't hold, so execution continues through the function
+    gameboy.debug(DebugCommand::AddBreakpoint(Breakpoint {
+        address: 0x0201,
+        condition: Some(Condition {
+            register: RegisterName::A,
+            comparison: Comparison::Equal,
+            value: 0x07,
+        }),
+    }));
+    gameboy.debug(breakpoint(0x0155));
+
+    assert_eq!(
+        run_until_stop(&mut gameboy),
+        Some(StopReason::Breakpoint(0x0155))
+    );
+    assert_eq!(gameboy.registers().pc, 0x0155);
+    assert_eq!(gameboy.registers().a, 0x06);
+    assert_eq!(read_byte(&gameboy, 0xC000), 0x00);
+
+    // Execution stays stopped until resumed
+    assert_eq!(
+        run_until_stop(&mut gameboy),
+        Some(StopReason::Breakpoint(0x0155))
+    );
+    gameboy.debug(DebugCommand::RemoveBreakpoint(0x0155));
+    gameboy.resume_debugger();
+    assert_eq!(run_until_stop(&mut gameboy), None);
+    assert_eq!(read_byte(&gameboy, 0xC000), 0x06);
+}
+
+#[test]
+fn watchpoints_stop_on_matching_accesses() {
+    let mut gameboy = debugger_gameboy();
+    let execute = Watchpoint {
+        start: 0x0200,
+        end: 0x02FF,
+        read: false,
+        write: false,
+        execute: true,
+    };
+    let write = Watchpoint {
+        start: 0xC000,
+        end: 0xC001,
+        read: false,
+        write: true,
+        execute: false,
+    };
+    gameboy.debug(DebugCommand::AddWatchpoint(execute));
+    gameboy.debug(DebugCommand::AddWatchpoint(write));
+
+    assert_eq!(
+        run_until_stop(&mut gameboy),
+        Some(StopReason::Watchpoint {
+            address: 0x0200,
+            access: Access::Execute,
+            value: 0x3C,
+        })
+    );
+
+    gameboy.debug(DebugCommand::RemoveWatchpoint(execute));
+    gameboy.resume_debugger();
+    assert_eq!(
+        run_until_stop(&mut gameboy),
+        Some(StopReason::Watchpoint {
+            address: 0xC001,
+            access: Access::Write,
+            value: 0x06,
+        })
+    );
+
+    // Returning reads the stack, but instruction fetches from the watched range don't count
+    gameboy.debug(DebugCommand::RemoveWatchpoint(write));
+    gameboy.debug(DebugCommand::AddWatchpoint(Watchpoint {
+        start: 0x0000,
+        end: 0xFFFF,
+        read: true,
+        write: false,
+        execute: false,
+    }));
+    gameboy.resume_debugger();
+    assert_eq!(
+        run_until_stop(&mut gameboy),
+        Some(StopReason::Watchpoint {
+            address: 0xFFFC,
+            access: Access::Read,
+            value: 0x55,
+        })
+    );
+}
+
+#[test]
+fn stepping_follows_calls_and_returns() {
+    let mut gameboy = debugger_gameboy();
+    gameboy.debug(breakpoint(0x0152));
+    assert_eq!(
+        run_until_stop(&mut gameboy),
+        Some(StopReason::Breakpoint(0x0152))
+    );
+
+    gameboy.debug(DebugCommand::StepInstruction);
+    assert_eq!(run_until_stop(&mut gameboy), Some(StopReason::Step));
+    assert_eq!(gameboy.registers().pc, 0x0200);
+    assert_eq!(gameboy.registers().sp, 0xFFFC);
+
+    gameboy.debug(DebugCommand::StepInstruction);
+    assert_eq!(run_until_stop(&mut gameboy), Some(StopReason::Step));
+    assert_eq!(gameboy.registers().pc, 0x0201);
+
+    gameboy.debug(DebugCommand::StepOut);
+    assert_eq!(run_until_stop(&mut gameboy), Some(StopReason::Step));
+    assert_eq!(gameboy.registers().pc, 0x0155);
+    assert_eq!(gameboy.registers().sp, 0xFFFE);
+    assert_eq!(read_byte(&gameboy, 0xC001), 0x06);
+
+    // Stepping over the call runs the whole function
+    let mut gameboy = debugger_gameboy();
+    gameboy.debug(breakpoint(0x0152));
+    assert_eq!(
+        run_until_stop(&mut gameboy),
+        Some(StopReason::Breakpoint(0x0152))
+    );
+    gameboy.debug(DebugCommand::StepOver);
+    assert_eq!(run_until_stop(&mut gameboy), Some(StopReason::Step));
+    assert_eq!(gameboy.registers().pc, 0x0155);
+    assert_eq!(gameboy.registers().a, 0x06);
+
+    gameboy.debug(DebugCommand::RemoveBreakpoint(0x0152));
+    gameboy.debug(DebugCommand::RunToFrame(2));
+    gameboy.run_frame();
+    assert_eq!(gameboy.take_stop_reason(), None);
+    gameboy.run_frame();
+    assert_eq!(gameboy.take_stop_reason(), Some(StopReason::Frame));
+}
+
+#[test]
+fn debugger_commands_report_state_while_running() {
+    let (debug_sender, debug_receiver) = crossbeam::channel::unbounded();
+    let frames = Rc::new(Cell::new(0));
+    let input = ScriptedInput {
+        running: Some(EmulatorCommand::Debug(1, breakpoint(0x0158))),
+        paused: VecDeque::from([
+            EmulatorCommand::Debug(2, DebugCommand::Inspect),
+            EmulatorCommand::Debug(3, DebugCommand::StepInstruction),
+            EmulatorCommand::Stop,
+        ]),
+        frames: frames.clone(),
+        waits: Rc::new(RefCell::new(Vec::new())),
+    };
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(input)
+        .boot(Boot::Skip)
+        .debug_events(debug_sender)
+        .build();
+    let mut gameboy = Gameboy::new(debugger_rom(), config).unwrap();
+    gameboy.start().unwrap();
+
+    let (requests, states): (Vec<_>, Vec<_>) = debug_receiver
+        .try_iter()
+        .map(|reply| match reply.event {
+            DebugEvent::State(state) => (reply.request, state),
+            event => panic!("Unexpected debug event {:?}", event),
+        })
+        .unzip();
+    let stop_reasons: Vec<_> = states.iter().map(|state| state.stop_reason).collect();
+    assert_eq!(
+        stop_reasons,
+        vec![
+            None,
+            Some(StopReason::Breakpoint(0x0158)),
+            Some(StopReason::Breakpoint(0x0158)),
+            Some(StopReason::Step),
+        ]
+    );
+    // Hitting the breakpoint answers nothing, while the step is answered once it completes
+    assert_eq!(requests, vec![Some(1), None, Some(2), Some(3)]);
+
+    let state = &states[3];
+    assert_eq!(state.registers.pc, 0x0158);
+    assert_eq!(state.registers.a, 0x06);
+    assert_eq!(state.breakpoints.len(), 1);
+    // INC A clears the zero and half carry flags the boot ROM leaves set, but not carry
+    assert_eq!(
+        state.flags,
+        Flags {
+            zero: false,
+            subtract: false,
+            half_carry: false,
+            carry: true,
+        }
+    );
+}
@@ -0,0 +1,88 @@
+use super::test_rom;
+use crate::gameboy::{disassemble, disassemble_rom, instruction_length, Location};
+
+fn disassemble_bytes(bytes: &[u8], address: u16) -> String {
+    let instruction = disassemble(Location::new(None, address), |offset| {
+        bytes[(offset - address) as usize]
+    });
+    assert_eq!(instruction.bytes, bytes);
+    instruction.text
+}
+
+#[test]
+fn instructions_are_decoded_with_operands() {
+    let cases: [(&[u8], &str); 20] = [
+        (&[0x00], "NOP"),
+        (&[0x01, 0x34, 0x12], "LD BC,0x1234"),
+        (&[0x08, 0x00, 0xC0], "LD (0xc000),SP"),
+        (&[0x18, 0xFE], "JR 0x0150"),
+        (&[0x20, 0x05], "JR NZ,0x0157"),
+        (&[0x22], "LD (HL+),A"),
+        (&[0x36, 0x42], "LD (HL),0x42"),
+        (&[0x41], "LD B,C"),
+        (&[0x76], "HALT"),
+        (&[0x9E], "SBC A,(HL)"),
+        (&[0xC9], "RET"),
+        (&[0xCD, 0x00, 0x02], "CALL 0x0200"),
+        (&[0xD3], "DB 0xd3"),
+        (&[0xE0, 0x44], "LDH (0xff44),A"),
+        (&[0xE2], "LDH (C),A"),
+        (&[0xE8, 0xFE], "ADD SP,-2"),
+        (&[0xF8, 0x05], "LD HL,SP+5"),
+        (&[0xFE, 0x90], "CP 0x90"),
+        (&[0xFF], "RST 0x38"),
+        (&[0xF5], "PUSH AF"),
+    ];
+
+    for (bytes, text) in cases {
+        assert_eq!(disassemble_bytes(bytes, 0x0150), text);
+        assert_eq!(instruction_length(bytes[0]) as usize, bytes.len());
+    }
+}
+
+#[test]
+fn prefixed_instructions_are_decoded() {
+    assert_eq!(disassemble_bytes(&[0xCB, 0x37], 0x0000), "SWAP A");
+    assert_eq!(disassemble_bytes(&[0xCB, 0x7E], 0x0000), "BIT 7,(HL)");
+    assert_eq!(disassemble_bytes(&[0xCB, 0x80], 0x0000), "RES 0,B");
+    assert_eq!(disassemble_bytes(&[0xCB, 0xFD], 0x0000), "SET 7,L");
+}
+
+#[test]
+fn roms_are_disassembled_from_bank_locations() {
+    let mut rom = test_rom();
+    rom.resize(0x10000, 0x00);
+    // Bank 2 at 0x4000: LD A,0x01; JP 0x4000
+    rom[0x8000..0x8005].copy_from_slice(&[0x3E, 0x01, 0xC3, 0x00, 0x40]);
+    // The operand of the last instruction in the ROM is past its end
+    rom[0xFFFF] = 0x3E;
+
+    assert_eq!(
+        "02:4000".parse::<Location>().unwrap(),
+        Location::new(Some(2), 0x4000)
+    );
+    assert_eq!(
+        "0x0150".parse::<Location>().unwrap(),
+        Location::new(None, 0x0150)
+    );
+    assert!("02:zz".parse::<Location>().is_err());
+
+    let instructions = disassemble_rom(&rom, "02:4000".parse().unwrap(), 2);
+    let lines: Vec<_> = instructions.iter().map(|i| i.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "02:4000  3E 01     LD A,0x01",
+            "02:4002  C3 00 40  JP 0x4000"
+        ]
+    );
+
+    // Without a bank, the fixed bank is bank 0
+    let instructions = disassemble_rom(&rom, Location::new(None, 0x0150), 1);
+    assert_eq!(instructions[0].location, Location::new(Some(0), 0x0150));
+    assert_eq!(instructions[0].text, "JR 0x0150");
+
+    let instructions = disassemble_rom(&rom, Location::new(Some(3), 0x7FFF), 4);
+    assert_eq!(instructions.len(), 1);
+    assert_eq!(instructions[0].text, "LD A,0xff");
+}
@@ -0,0 +1,174 @@
+use std::{
+    net::TcpListener,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{read_byte, test_rom};
+use crate::{
+    emulator::EmulatorCommand,
+    gameboy::{
+        Boot, Gameboy, GameboyConfig, Link, LinkAddress, LinkSerialEndpoint, NullDisplay,
+        SerialEndpoint,
+    },
+};
+
+/// How long either side keeps trying before the test gives up.
+const DEADLINE: Duration = Duration::from_secs(5);
+
+#[test]
+fn link_cable_exchanges_bytes_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let mut host = LinkSerialEndpoint::accept_tcp(listener);
+    let mut guest = Link::Connect(LinkAddress::Tcp(address)).open().unwrap();
+
+    // The host waits on the external clock, replying with 0x24 to whatever it receives
+    let slave = thread::spawn(move || {
+        let start = Instant::now();
+        let mut received = Vec::new();
+        while start.elapsed() < DEADLINE && received.last() != Some(&0x42) {
+            received.extend(host.receive(0x24));
+            thread::sleep(Duration::from_millis(1));
+        }
+        received
+    });
+
+    // The guest drives the clock, retrying until the host is ready
+    let start = Instant::now();
+    let mut reply = guest.exchange(0x42);
+    while reply == 0xFF && start.elapsed() < DEADLINE {
+        reply = guest.exchange(0x42);
+    }
+
+    assert_eq!(reply, 0x24);
+    assert_eq!(slave.join().unwrap().last(), Some(&0x42));
+}
+
+#[test]
+fn link_cable_only_delivers_transfers_while_waiting() {
+    let (mut master, mut slave) = LinkSerialEndpoint::pair();
+
+    // The slave isn't waiting on the external clock yet, so nothing is shifted in either way
+    assert_eq!(master.exchange(0x42), 0xFF);
+    assert_eq!(slave.receive(0x24), None);
+
+    // Once it is, the transfer is answered without the slave polling again
+    assert_eq!(master.exchange(0x43), 0x24);
+    assert_eq!(slave.receive(0x24), Some(0x43));
+    assert_eq!(master.exchange(0x44), 0xFF);
+}
+
+/// A program that sends 0x42 over the link cable on the internal clock, storing the byte it
+/// receives at 0xC000.
+const MASTER_PROGRAM: [u8; 21] = [
+    0x3E, 0x42, // LD A, 0x42
+    0xE0, 0x01, // LDH (SB), A
+    0x3E, 0x81, // LD A, 0x81
+    0xE0, 0x02, // LDH (SC), A
+    0xF0, 0x02, // LDH A, (SC)
+    0xCB, 0x7F, // BIT 7, A
+    0x20, 0xFA, // JR NZ, -6
+    0xF0, 0x01, // LDH A, (SB)
+    0xEA, 0x00, 0xC0, // LD (0xC000), A
+    0x18, 0xFE, // JR -2
+];
+
+/// A program that waits on the external clock to send 0x24, storing the byte it receives at
+/// 0xC000.
+const SLAVE_PROGRAM: [u8; 21] = [
+    0x3E, 0x24, // LD A, 0x24
+    0xE0, 0x01, // LDH (SB), A
+    0x3E, 0x80, // LD A, 0x80
+    0xE0, 0x02, // LDH (SC), A
+    0xF0, 0x02, // LDH A, (SC)
+    0xCB, 0x7F, // BIT 7, A
+    0x20, 0xFA, // JR NZ, -6
+    0xF0, 0x01, // LDH A, (SB)
+    0xEA, 0x00, 0xC0, // LD (0xC000), A
+    0x18, 0xFE, // JR -2
+];
+
+fn link_gameboy(program: &[u8], endpoint: LinkSerialEndpoint) -> Gameboy {
+    let mut rom = test_rom();
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .boot(Boot::Skip)
+        .serial(endpoint)
+        .build();
+
+    Gameboy::new(rom, config).unwrap()
+}
+
+/// Runs frames, sleeping out the rest of each as the frame pacer would, until the byte at the
+/// address passes the check.
+fn run_paced(gameboy: &mut Gameboy, address: u16, done: impl Fn(u8) -> bool) {
+    let start = Instant::now();
+    while !done(read_byte(gameboy, address)) && start.elapsed() < DEADLINE {
+        gameboy.run_frame();
+        thread::sleep(Duration::from_millis(16));
+    }
+}
+
+#[test]
+fn paced_gameboys_exchange_bytes_over_link_cable() {
+    let (master, slave) = LinkSerialEndpoint::pair();
+    let (ready, slave_ready) = crossbeam::channel::bounded(1);
+
+    let slave = thread::spawn(move || {
+        let mut gameboy = link_gameboy(&SLAVE_PROGRAM, slave);
+        run_paced(&mut gameboy, 0xFF02, |sc| sc & 0x80 != 0);
+        ready.send(()).unwrap();
+
+        // The master's transfer arrives while this side is between frames
+        thread::sleep(LinkSerialEndpoint::TIMEOUT * 4);
+        run_paced(&mut gameboy, 0xC000, |received| received != 0x00);
+        read_byte(&gameboy, 0xC000)
+    });
+
+    let master = thread::spawn(move || {
+        let mut gameboy = link_gameboy(&MASTER_PROGRAM, master);
+        slave_ready.recv().unwrap();
+        run_paced(&mut gameboy, 0xC000, |received| received != 0x00);
+        read_byte(&gameboy, 0xC000)
+    });
+
+    assert_eq!(master.join().unwrap(), 0x24);
+    assert_eq!(slave.join().unwrap(), 0x42);
+}
+
+#[cfg(unix)]
+#[test]
+fn link_cable_connects_over_unix_socket() {
+    let path = std::env::temp_dir().join(format!("emyco-link-{}.sock", std::process::id()));
+    let mut host = Link::Listen(LinkAddress::Unix(path.clone()))
+        .open()
+        .unwrap();
+    let mut guest = Link::Connect(LinkAddress::Unix(path.clone()))
+        .open()
+        .unwrap();
+
+    let slave = thread::spawn(move || {
+        let start = Instant::now();
+        while start.elapsed() < DEADLINE {
+            if let Some(byte) = host.receive(0x99) {
+                return Some(byte);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        None
+    });
+
+    let start = Instant::now();
+    let mut reply = guest.exchange(0x66);
+    while reply == 0xFF && start.elapsed() < DEADLINE {
+        reply = guest.exchange(0x66);
+    }
+
+    assert_eq!(reply, 0x99);
+    assert_eq!(slave.join().unwrap(), Some(0x66));
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,166 @@
+use super::{banked_gameboy, cgb_gameboy, read_byte};
+use crate::gameboy::{memory::MemoryController, DebugCommand, DebugEvent, MemoryRegion};
+
+#[test]
+fn reads_are_not_hidden_by_oam_dma() {
+    let gameboy = banked_gameboy(8);
+    let mut memory = gameboy.memory.write().unwrap();
+    memory.write_byte(0xC000, 0x42);
+    memory.write_byte(0xFF46, 0xC0);
+
+    assert_eq!(memory.read_byte(0xC000), 0x00);
+    assert_eq!(
+        memory.read_memory(MemoryRegion::Cpu, 0xC000, 1).unwrap(),
+        vec![0x42]
+    );
+}
+
+#[test]
+fn rom_banks_are_read_and_patched_without_switching_banks() {
+    let mut gameboy = banked_gameboy(8);
+    gameboy.memory.write().unwrap().write_byte(0x2000, 0x03);
+
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Rom(5), 0, 2).unwrap(),
+        vec![0x05, 0x05]
+    );
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Cpu, 0x4000, 1).unwrap(),
+        vec![0x03]
+    );
+
+    // Writing to 0x2000 through the CPU's view patches bank 0 instead of selecting a bank
+    gameboy
+        .write_memory(MemoryRegion::Cpu, 0x2000, &[0x07])
+        .unwrap();
+    gameboy
+        .write_memory(MemoryRegion::Cpu, 0x4001, &[0xAB])
+        .unwrap();
+    assert_eq!(
+        gameboy
+            .read_memory(MemoryRegion::Rom(0), 0x2000, 1)
+            .unwrap(),
+        vec![0x07]
+    );
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Rom(3), 0, 2).unwrap(),
+        vec![0x03, 0xAB]
+    );
+    assert_eq!(read_byte(&gameboy, 0x4000), 0x03);
+
+    assert!(gameboy.read_memory(MemoryRegion::Rom(8), 0, 1).is_err());
+    assert!(gameboy
+        .read_memory(MemoryRegion::Rom(1), usize::MAX, 2)
+        .is_err());
+    assert!(gameboy
+        .read_memory(MemoryRegion::Rom(7), 0x3FFF, 2)
+        .is_err());
+}
+
+#[test]
+fn physical_banks_map_to_the_cpu_view() {
+    let mut gameboy = banked_gameboy(8);
+
+    // Cartridge RAM bank 2, even though RAM is disabled for the CPU
+    gameboy
+        .write_memory(MemoryRegion::CartridgeRam(2), 0x10, &[0x12, 0x34])
+        .unwrap();
+    {
+        let mut memory = gameboy.memory.write().unwrap();
+        memory.write_byte(0x0000, 0x0A);
+        memory.write_byte(0x4000, 0x02);
+    }
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Cpu, 0xA010, 2).unwrap(),
+        vec![0x12, 0x34]
+    );
+    assert!(gameboy
+        .read_memory(MemoryRegion::CartridgeRam(4), 0, 1)
+        .is_err());
+
+    gameboy
+        .write_memory(MemoryRegion::Hram, 0, &[0x56])
+        .unwrap();
+    gameboy
+        .write_memory(MemoryRegion::Wram(1), 0x0FFF, &[0x78])
+        .unwrap();
+    gameboy
+        .write_memory(MemoryRegion::Vram(0), 0x1800, &[0x9A])
+        .unwrap();
+    let memory = gameboy.memory.read().unwrap();
+    assert_eq!(memory.read_byte(0xFF80), 0x56);
+    assert_eq!(memory.read_byte(0xDFFF), 0x78);
+    assert_eq!(memory.read_byte(0x9800), 0x9A);
+    drop(memory);
+
+    assert!(gameboy.read_memory(MemoryRegion::Hram, 0x7F, 1).is_err());
+    // Outside CGB mode there's a single VRAM bank and two WRAM banks
+    assert!(gameboy.read_memory(MemoryRegion::Vram(1), 0, 1).is_err());
+    assert!(gameboy.read_memory(MemoryRegion::Wram(2), 0, 1).is_err());
+}
+
+#[test]
+fn cgb_banks_are_readable_whichever_is_mapped() {
+    let gameboy = cgb_gameboy();
+    {
+        let mut memory = gameboy.memory.write().unwrap();
+        memory.write_byte(0xFF4F, 0x01);
+        memory.write_byte(0x8000, 0x11);
+        memory.write_byte(0xFF4F, 0x00);
+        memory.write_byte(0xFF70, 0x07);
+        memory.write_byte(0xD000, 0x22);
+        memory.write_byte(0xFF70, 0x01);
+    }
+
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Vram(1), 0, 1).unwrap(),
+        vec![0x11]
+    );
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Vram(0), 0, 1).unwrap(),
+        vec![0x00]
+    );
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Wram(7), 0, 1).unwrap(),
+        vec![0x22]
+    );
+    assert!(gameboy.read_memory(MemoryRegion::Wram(8), 0, 1).is_err());
+}
+
+#[test]
+fn debugger_commands_poke_and_read_memory() {
+    let mut gameboy = banked_gameboy(8);
+    let write = DebugCommand::WriteMemory {
+        region: MemoryRegion::Cpu,
+        offset: 0xC100,
+        value: 0xEE,
+    };
+    gameboy.debug(write);
+
+    assert_eq!(
+        gameboy.debug_reply(write, None),
+        DebugEvent::Memory(Some(vec![0xEE]))
+    );
+    assert_eq!(
+        gameboy.debug_reply(
+            DebugCommand::ReadMemory {
+                region: MemoryRegion::Wram(0),
+                start: 0x0100,
+                length: 2,
+            },
+            None
+        ),
+        DebugEvent::Memory(Some(vec![0xEE, 0x00]))
+    );
+    assert_eq!(
+        gameboy.debug_reply(
+            DebugCommand::ReadMemory {
+                region: MemoryRegion::Cpu,
+                start: 0xFFFF,
+                length: 2,
+            },
+            None
+        ),
+        DebugEvent::Memory(None)
+    );
+}
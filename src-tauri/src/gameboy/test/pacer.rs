@@ -0,0 +1,206 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    emulator::{EmulationSpeed, EmulatorCommand},
+    gameboy::{
+        apu::SAMPLE_BUFFER_SIZE,
+        pacer::{FramePacer, PacerClock},
+        AudioSink, Boot, Gameboy, GameboyConfig, GlobalConstants, NullDisplay,
+    },
+};
+
+use super::test_rom;
+
+/// A clock that only moves when told to, or when the pacer sleeps on it. Clones share the same
+/// time and record of sleeps.
+#[derive(Clone)]
+struct FakeClock {
+    now: Rc<Cell<Instant>>,
+    sleeps: Rc<RefCell<Vec<Duration>>>,
+}
+
+impl FakeClock {
+    fn new() -> Self {
+        FakeClock {
+            now: Rc::new(Cell::new(Instant::now())),
+            sleeps: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+
+    fn take_sleeps(&self) -> Vec<Duration> {
+        std::mem::take(&mut self.sleeps.borrow_mut())
+    }
+}
+
+impl PacerClock for FakeClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.sleeps.borrow_mut().push(duration);
+        self.advance(duration);
+    }
+}
+
+/// How long a frame lasts on real hardware.
+fn frame_duration() -> Duration {
+    Duration::from_secs_f64(
+        GlobalConstants::FRAME_CYCLES as f64 / GlobalConstants::SYSTEM_CLOCK_RATE as f64,
+    )
+}
+
+/// Asserts that two durations are within a microsecond of each other, as pacing rounds to the
+/// nearest nanosecond.
+fn assert_close(actual: Duration, expected: Duration) {
+    let difference = actual.abs_diff(expected);
+    assert!(
+        difference < Duration::from_micros(1),
+        "Expected {:?} but was {:?}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn pacer_sleeps_out_the_rest_of_each_frame() {
+    let clock = FakeClock::new();
+    let mut pacer = FramePacer::with_clock(EmulationSpeed::default(), Box::new(clock.clone()));
+
+    // Emulating the frame took 5ms, so the rest of it is slept
+    clock.advance(Duration::from_millis(5));
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    let sleeps = clock.take_sleeps();
+    assert_eq!(sleeps.len(), 1);
+    assert_close(sleeps[0], frame_duration() - Duration::from_millis(5));
+
+    // Running two frames behind, which is less than the maximum lag, is caught up on without
+    // sleeping
+    clock.advance(frame_duration() * 2);
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    assert!(clock.take_sleeps().is_empty());
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    let sleeps = clock.take_sleeps();
+    assert_eq!(sleeps.len(), 1);
+    assert_close(sleeps[0], frame_duration());
+}
+
+#[test]
+fn pacer_scales_frames_by_the_speed() {
+    let clock = FakeClock::new();
+    let mut pacer =
+        FramePacer::with_clock(EmulationSpeed::multiplier(2.0), Box::new(clock.clone()));
+
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    pacer.set_speed(EmulationSpeed::multiplier(0.5));
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+
+    let sleeps = clock.take_sleeps();
+    assert_eq!(sleeps.len(), 2);
+    assert_close(sleeps[0], frame_duration() / 2);
+    assert_close(sleeps[1], frame_duration() * 2);
+}
+
+#[test]
+fn unthrottled_pacer_never_sleeps() {
+    let clock = FakeClock::new();
+    let mut pacer = FramePacer::with_clock(EmulationSpeed::Unthrottled, Box::new(clock.clone()));
+
+    for _ in 0..60 {
+        pacer.wait(GlobalConstants::FRAME_CYCLES);
+    }
+    assert!(clock.take_sleeps().is_empty());
+
+    // Switching back to a multiplier paces from then on, rather than from when pacing stopped
+    clock.advance(Duration::from_secs(1));
+    pacer.set_speed(EmulationSpeed::default());
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    let sleeps = clock.take_sleeps();
+    assert_eq!(sleeps.len(), 1);
+    assert_close(sleeps[0], frame_duration());
+}
+
+#[test]
+fn pacer_drops_lag_beyond_the_maximum() {
+    let clock = FakeClock::new();
+    let mut pacer = FramePacer::with_clock(EmulationSpeed::default(), Box::new(clock.clone()));
+
+    // After a long stall, only the frames from then on are paced
+    clock.advance(FramePacer::MAX_LAG * 3);
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    assert!(clock.take_sleeps().is_empty());
+
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    let sleeps = clock.take_sleeps();
+    assert_eq!(sleeps.len(), 1);
+    assert_close(sleeps[0], frame_duration());
+}
+
+#[test]
+fn pacer_clamps_unsupported_multipliers() {
+    let clock = FakeClock::new();
+    let mut pacer =
+        FramePacer::with_clock(EmulationSpeed::Multiplier(0.0), Box::new(clock.clone()));
+
+    pacer.wait(GlobalConstants::FRAME_CYCLES);
+    for multiplier in [-1.0, f32::NAN, 100.0] {
+        pacer.set_speed(EmulationSpeed::Multiplier(multiplier));
+        pacer.wait(GlobalConstants::FRAME_CYCLES);
+    }
+
+    let sleeps = clock.take_sleeps();
+    assert_eq!(sleeps.len(), 4);
+    let max_frame = frame_duration().div_f32(EmulationSpeed::MIN_MULTIPLIER);
+    let min_frame = frame_duration().div_f32(EmulationSpeed::MAX_MULTIPLIER);
+    assert_close(sleeps[0], max_frame);
+    assert_close(sleeps[1], max_frame);
+    assert_close(sleeps[2], frame_duration());
+    assert_close(sleeps[3], min_frame);
+}
+
+/// Counts the sample buffers pushed to it.
+#[derive(Clone, Default)]
+struct CountingAudioSink(Rc<Cell<usize>>);
+
+impl AudioSink for CountingAudioSink {
+    fn push_samples(&mut self, _samples: [i16; SAMPLE_BUFFER_SIZE]) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[test]
+fn audio_is_muted_at_any_speed_but_real_time() {
+    let audio = CountingAudioSink::default();
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .boot(Boot::Skip)
+        .audio(audio.clone())
+        .speed(EmulationSpeed::multiplier(2.0))
+        .build();
+    let mut gameboy = Gameboy::new(test_rom(), config).unwrap();
+
+    let samples_pushed = |gameboy: &mut Gameboy| {
+        audio.0.set(0);
+        gameboy.run_frame();
+        audio.0.get() > 0
+    };
+
+    assert!(!samples_pushed(&mut gameboy));
+    gameboy.set_speed(EmulationSpeed::default());
+    assert!(samples_pushed(&mut gameboy));
+    gameboy.set_speed(EmulationSpeed::Unthrottled);
+    assert!(!samples_pushed(&mut gameboy));
+    gameboy.set_speed(EmulationSpeed::multiplier(0.5));
+    assert!(!samples_pushed(&mut gameboy));
+}
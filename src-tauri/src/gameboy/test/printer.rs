@@ -0,0 +1,98 @@
+use std::{cell::RefCell, env, fs, rc::Rc};
+
+use crate::gameboy::{PrintSink, PrintedImage, Printer, SerialEndpoint};
+
+#[derive(Debug)]
+struct PrintCapture(Rc<RefCell<Vec<PrintedImage>>>);
+
+impl PrintSink for PrintCapture {
+    fn print(&mut self, image: PrintedImage) {
+        self.0.borrow_mut().push(image);
+    }
+}
+
+/// Sends a packet to the printer, returning its device ID and status replies.
+fn send_packet(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+    let mut packet = vec![command, compressed as u8];
+    packet.extend((data.len() as u16).to_le_bytes());
+    packet.extend(data);
+    let checksum = packet
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+
+    for byte in [0x88, 0x33].into_iter().chain(packet) {
+        assert_eq!(printer.exchange(byte), 0x00);
+    }
+    for byte in checksum.to_le_bytes() {
+        assert_eq!(printer.exchange(byte), 0x00);
+    }
+
+    (printer.exchange(0x00), printer.exchange(0x00))
+}
+
+#[test]
+fn printer_prints_tile_data_with_palette() {
+    let prints = Rc::new(RefCell::new(Vec::new()));
+    let mut printer = Printer::new(Box::new(PrintCapture(prints.clone())));
+
+    assert_eq!(send_packet(&mut printer, 0x01, false, &[]), (0x81, 0x00));
+
+    // A row of tiles, with the first one in color 3 and the rest in color 0
+    let mut row = vec![0x00; 20 * 16];
+    row[..16].fill(0xFF);
+    assert_eq!(send_packet(&mut printer, 0x04, false, &row), (0x81, 0x08));
+    assert_eq!(send_packet(&mut printer, 0x04, false, &[]), (0x81, 0x0C));
+
+    // Printed without a margin after it, so the job continues
+    assert_eq!(
+        send_packet(&mut printer, 0x02, false, &[0x01, 0x10, 0xE4, 0x40]),
+        (0x81, 0x06)
+    );
+    assert!(prints.borrow().is_empty());
+
+    // Stays busy for a few status requests
+    let statuses: Vec<u8> = (0..5)
+        .map(|_| send_packet(&mut printer, 0x0F, false, &[]).1)
+        .collect();
+    assert_eq!(statuses, vec![0x06, 0x06, 0x06, 0x06, 0x00]);
+
+    // The same row, run-length encoded, printed with an inverted palette and a margin after it
+    let compressed = [0x8E, 0xFF, 0xFF, 0x00, 0xFF, 0x00, 0xAC, 0x00];
+    assert_eq!(
+        send_packet(&mut printer, 0x04, true, &compressed),
+        (0x81, 0x08)
+    );
+    send_packet(&mut printer, 0x02, false, &[0x01, 0x03, 0x1B, 0x40]);
+
+    let prints = prints.borrow();
+    assert_eq!(prints.len(), 1);
+    let image = &prints[0];
+    assert_eq!((image.width(), image.height()), (160, 16));
+    assert_eq!(image.pixels()[0], 0x00);
+    assert_eq!(image.pixels()[8], 0xFF);
+    assert_eq!(image.pixels()[8 * 160], 0xFF);
+    assert_eq!(image.pixels()[8 * 160 + 8], 0x00);
+
+    let path = env::temp_dir().join("emyco-printer.png");
+    image.write_png(&path).unwrap();
+    let reader = png::Decoder::new(fs::File::open(&path).unwrap())
+        .read_info()
+        .unwrap();
+    assert_eq!((reader.info().width, reader.info().height), (160, 16));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn printer_reports_checksum_errors() {
+    let mut printer = Printer::new(Box::new(PrintCapture(Rc::new(RefCell::new(Vec::new())))));
+
+    for byte in [0x88, 0x33, 0x04, 0x00, 0x01, 0x00, 0xAA, 0x00, 0x00] {
+        printer.exchange(byte);
+    }
+    assert_eq!(
+        (printer.exchange(0x00), printer.exchange(0x00)),
+        (0x81, 0x01)
+    );
+
+    assert_eq!(send_packet(&mut printer, 0x0F, false, &[]), (0x81, 0x00));
+}
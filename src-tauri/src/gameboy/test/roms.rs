@@ -0,0 +1,175 @@
+use std::{env, fmt, fs, path::PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::run_frames;
+use crate::gameboy::{Boot, BufferSerialEndpoint, Gameboy, GameboyConfig, Model, NullDisplay};
+
+/// Lists the test ROMs to run and the result each is expected to have.
+const MANIFEST_PATH: &str = "test-roms/manifest.json";
+
+/// The directory the test ROMs are read from, unless overridden with EMYCO_TEST_ROMS. The ROMs
+/// aren't distributed with the repository.
+const DEFAULT_ROM_DIRECTORY: &str = "test-roms";
+
+/// The register values Mooneye test ROMs load before LD B,B when they pass.
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+/// Reads a test ROM from the ROM directory, failing if it's missing.
+pub(super) fn read_rom(rom: &str) -> Vec<u8> {
+    let path = env::var("EMYCO_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_ROM_DIRECTORY))
+        .join(rom);
+
+    fs::read(&path).unwrap_or_else(|e| panic!("Unable to read test ROM {:?}. {}", path, e))
+}
+
+pub(super) fn read_manifest<T: DeserializeOwned>(path: &str) -> Vec<T> {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// The number of frames emulated per second.
+const FRAMES_PER_SECOND: u32 = 60;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Protocol {
+    /// Blargg ROMs print their results over the serial port.
+    Serial,
+    /// Mooneye ROMs load the Fibonacci sequence into B, C, D, E, H and L, then execute LD B,B.
+    Fibonacci,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Pass,
+    Fail,
+    /// The ROM didn't report a result in time.
+    Timeout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    rom: String,
+    protocol: Protocol,
+    /// The result recorded by the last run with EMYCO_UPDATE_MANIFEST, or None if the ROM hasn't
+    /// been run yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expected: Option<Outcome>,
+    /// The model to emulate, instead of detecting it from the cartridge header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<Model>,
+    /// How many emulated seconds the ROM gets to report a result.
+    seconds: u32,
+}
+
+impl fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.model {
+            Some(model) => write!(f, "{} ({:?})", self.rom, model),
+            None => write!(f, "{}", self.rom),
+        }
+    }
+}
+
+impl ManifestEntry {
+    fn run(&self, rom: Vec<u8>) -> Outcome {
+        let serial = BufferSerialEndpoint::new();
+        let (_sender, receiver) = crossbeam::channel::unbounded();
+        let mut config = GameboyConfig::builder()
+            .display(NullDisplay)
+            .input(receiver)
+            .serial(serial.clone())
+            .boot(Boot::Skip);
+        if let Some(model) = self.model {
+            config = config.model(model);
+        }
+        let mut gameboy = Gameboy::new(rom, config.build()).unwrap();
+        let mut serial_output = Vec::new();
+
+        for _ in 0..self.seconds * FRAMES_PER_SECOND {
+            run_frames(&mut gameboy, 1);
+            serial_output.extend(serial.take_sent());
+
+            if let Some(outcome) = self.outcome(&mut gameboy, &serial_output) {
+                return outcome;
+            }
+        }
+
+        Outcome::Timeout
+    }
+
+    fn outcome(&self, gameboy: &mut Gameboy, serial_output: &[u8]) -> Option<Outcome> {
+        match self.protocol {
+            Protocol::Serial => {
+                let text = String::from_utf8_lossy(serial_output);
+
+                if text.contains("Passed") {
+                    Some(Outcome::Pass)
+                } else if text.contains("Failed") {
+                    Some(Outcome::Fail)
+                } else {
+                    None
+                }
+            }
+            Protocol::Fibonacci => {
+                if !gameboy.take_software_breakpoint() {
+                    return None;
+                }
+
+                let registers = gameboy.registers();
+                let values = [
+                    registers.b,
+                    registers.c,
+                    registers.d,
+                    registers.e,
+                    registers.h,
+                    registers.l,
+                ];
+                match values == MOONEYE_PASS {
+                    true => Some(Outcome::Pass),
+                    false => Some(Outcome::Fail),
+                }
+            }
+        }
+    }
+}
+
+/// Runs every ROM in the manifest and compares its result against the recorded one. The ROMs
+/// aren't distributed with the repository, so this only runs when asked for, and fails if any are
+/// missing. The Test ROMs workflow downloads them and runs this in CI, where a ROM with no
+/// recorded result fails too. Set EMYCO_UPDATE_MANIFEST to record the observed results instead:
+///
+/// EMYCO_TEST_ROMS=path/to/roms EMYCO_UPDATE_MANIFEST=1 cargo test test_rom_manifest -- --ignored
+#[test]
+#[ignore = "needs the test ROMs, which aren't distributed with the repository"]
+fn test_rom_manifest() {
+    let update = env::var("EMYCO_UPDATE_MANIFEST").is_ok();
+
+    let mut manifest: Vec<ManifestEntry> = read_manifest(MANIFEST_PATH);
+    let mut mismatches = Vec::new();
+
+    for entry in manifest.iter_mut() {
+        let outcome = entry.run(read_rom(&entry.rom));
+        if entry.expected != Some(outcome) {
+            mismatches.push(match entry.expected {
+                Some(expected) => format!("{}: expected {:?}, got {:?}", entry, expected, outcome),
+                None => format!("{}: no result recorded, got {:?}", entry, outcome),
+            });
+            entry.expected = Some(outcome);
+        }
+    }
+
+    if update {
+        let json = serde_json::to_string_pretty(&manifest).unwrap();
+        fs::write(MANIFEST_PATH, json + "\n").unwrap();
+    } else {
+        assert!(
+            mismatches.is_empty(),
+            "Test ROM results differ from the manifest:\n{}",
+            mismatches.join("\n")
+        );
+    }
+}
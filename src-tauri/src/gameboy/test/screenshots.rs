@@ -0,0 +1,315 @@
+use std::{
+    cell::RefCell,
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use super::{
+    roms::{read_manifest, read_rom},
+    run_frames,
+};
+use crate::gameboy::{ppu::DMG_COLORS, Boot, Display, Gameboy, GameboyConfig, Model};
+
+/// Lists the ROMs to screenshot and the reference image each is compared against.
+const MANIFEST_PATH: &str = "test-roms/screenshots.json";
+
+/// Where reference images are read from and written to when updating them.
+const REFERENCE_DIRECTORY: &str = "test-roms/screenshots";
+
+/// Where the actual frame and a diff image are written for each mismatching screenshot.
+const DIFF_DIRECTORY: &str = "target/screenshot-diffs";
+
+/// An RGB888 image of a presented frame.
+#[derive(Debug, PartialEq, Clone)]
+struct Screenshot {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    fn read(path: &Path) -> Result<Self, anyhow::Error> {
+        let mut reader = png::Decoder::new(File::open(path)?).read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        buffer.truncate(info.buffer_size());
+
+        let pixels = match (info.color_type, info.bit_depth) {
+            (png::ColorType::Rgb, png::BitDepth::Eight) => buffer,
+            (png::ColorType::Rgba, png::BitDepth::Eight) => buffer
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect(),
+            other => bail!("Unsupported reference image format {:?}.", other),
+        };
+
+        Ok(Screenshot {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn write(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(File::create(path)?), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+
+        Ok(())
+    }
+
+    /// Compares against a reference image, returning the number of differing pixels along with an
+    /// image that marks them in red over a faded copy of the reference.
+    fn diff(&self, reference: &Screenshot) -> (usize, Screenshot) {
+        if (self.width, self.height) != (reference.width, reference.height) {
+            return ((self.width * self.height) as usize, self.clone());
+        }
+
+        let mut differences = 0;
+        let pixels = self
+            .pixels
+            .chunks_exact(3)
+            .zip(reference.pixels.chunks_exact(3))
+            .flat_map(|(actual, expected)| match actual == expected {
+                true => [expected[0], expected[1], expected[2]].map(|channel| channel / 4 + 0xC0),
+                false => {
+                    differences += 1;
+                    [0xFF, 0x00, 0x00]
+                }
+            })
+            .collect();
+
+        (
+            differences,
+            Screenshot {
+                pixels,
+                ..reference.clone()
+            },
+        )
+    }
+}
+
+/// The grayscale the four DMG shades are drawn in by the reference images, from lightest to
+/// darkest.
+const REFERENCE_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// Keeps the most recently presented frame.
+struct CapturingDisplay {
+    width: usize,
+    height: usize,
+    buffer: Vec<u16>,
+    /// Whether to capture DMG shades in the reference grayscale, rather than the colors they're
+    /// displayed as.
+    grayscale: bool,
+    frame: Rc<RefCell<Option<Screenshot>>>,
+}
+
+impl CapturingDisplay {
+    fn new(grayscale: bool, frame: Rc<RefCell<Option<Screenshot>>>) -> Self {
+        CapturingDisplay {
+            width: 160,
+            height: 144,
+            buffer: vec![0; 160 * 144],
+            grayscale,
+            frame,
+        }
+    }
+
+    fn pixel(&self, color: u16) -> [u8; 3] {
+        let shade = DMG_COLORS
+            .iter()
+            .position(|shade| *shade == color)
+            .filter(|_| self.grayscale);
+        if let Some(shade) = shade {
+            return [REFERENCE_SHADES[shade]; 3];
+        }
+
+        // Expands each RGB555 channel to 8 bits
+        let expand = |channel: u16| ((channel << 3) | (channel >> 2)) as u8;
+        [
+            expand(color & 0x1F),
+            expand((color >> 5) & 0x1F),
+            expand((color >> 10) & 0x1F),
+        ]
+    }
+}
+
+impl Display for CapturingDisplay {
+    fn push_pixel(&mut self, x: u8, y: u8, color: u16) {
+        self.buffer[y as usize * self.width + x as usize] = color;
+    }
+
+    fn present(&mut self) {
+        let pixels = self
+            .buffer
+            .iter()
+            .flat_map(|color| self.pixel(*color))
+            .collect();
+
+        *self.frame.borrow_mut() = Some(Screenshot {
+            width: self.width as u32,
+            height: self.height as u32,
+            pixels,
+        });
+    }
+
+    fn resize(&mut self, width: u16, height: u16) {
+        self.width = width as usize;
+        self.height = height as usize;
+        self.buffer = vec![0; self.width * self.height];
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ScreenshotEntry {
+    rom: String,
+    /// The name of the reference image in the reference directory, without its extension.
+    name: String,
+    /// The model to emulate, instead of detecting it from the cartridge header.
+    #[serde(default)]
+    model: Option<Model>,
+    /// The most frames to run for. The screenshot is taken earlier if the ROM signals completion
+    /// with LD B,B.
+    frames: u32,
+}
+
+impl ScreenshotEntry {
+    /// Whether the ROM runs on a model that displays shades rather than colors.
+    fn is_dmg(&self) -> bool {
+        matches!(self.model, Some(Model::DMG0 | Model::DMG | Model::MGB))
+    }
+
+    fn capture(&self, rom: Vec<u8>) -> Screenshot {
+        let frame = Rc::new(RefCell::new(None));
+        let (_sender, receiver) = crossbeam::channel::unbounded();
+        let mut config = GameboyConfig::builder()
+            .display(CapturingDisplay::new(self.is_dmg(), frame.clone()))
+            .input(receiver)
+            .boot(Boot::Skip);
+        if let Some(model) = self.model {
+            config = config.model(model);
+        }
+        let mut gameboy = Gameboy::new(rom, config.build()).unwrap();
+
+        for _ in 0..self.frames {
+            run_frames(&mut gameboy, 1);
+            if gameboy.take_software_breakpoint() {
+                // Let the frame in progress finish rendering
+                run_frames(&mut gameboy, 1);
+                break;
+            }
+        }
+
+        let screenshot = frame.borrow_mut().take();
+        screenshot.unwrap_or_else(|| panic!("{} never presented a frame.", self.rom))
+    }
+}
+
+/// Runs every ROM in the screenshot manifest and compares its final frame against the reference
+/// image published by its test suite, with DMG frames captured in the grayscale those use. Like
+/// the test ROM manifest, this needs the ROMs, so it only runs when asked for and fails if any are
+/// missing. On a mismatch, the actual frame and a diff image are written to
+/// target/screenshot-diffs. Set EMYCO_UPDATE_SCREENSHOTS to replace the reference images with the
+/// actual frames instead, which only makes sense for ROMs without a published reference:
+///
+/// EMYCO_TEST_ROMS=path/to/roms EMYCO_UPDATE_SCREENSHOTS=1 cargo test screenshot -- --ignored
+#[test]
+#[ignore = "needs the test ROMs, which aren't distributed with the repository"]
+fn screenshots_match_references() {
+    let update = env::var("EMYCO_UPDATE_SCREENSHOTS").is_ok();
+
+    let manifest: Vec<ScreenshotEntry> = read_manifest(MANIFEST_PATH);
+    let mut mismatches = Vec::new();
+
+    for entry in manifest.iter() {
+        let actual = entry.capture(read_rom(&entry.rom));
+        let reference_path = Path::new(REFERENCE_DIRECTORY).join(format!("{}.png", entry.name));
+
+        if update {
+            fs::create_dir_all(REFERENCE_DIRECTORY).unwrap();
+            actual.write(&reference_path).unwrap();
+            continue;
+        }
+
+        let Ok(reference) = Screenshot::read(&reference_path) else {
+            mismatches.push(format!(
+                "{}: no reference image at {:?}",
+                entry.name, reference_path
+            ));
+            continue;
+        };
+
+        let (differences, diff) = actual.diff(&reference);
+        if differences > 0 {
+            fs::create_dir_all(DIFF_DIRECTORY).unwrap();
+            let diff_path = Path::new(DIFF_DIRECTORY).join(format!("{}-diff.png", entry.name));
+            diff.write(&diff_path).unwrap();
+            actual
+                .write(&Path::new(DIFF_DIRECTORY).join(format!("{}-actual.png", entry.name)))
+                .unwrap();
+            mismatches.push(format!(
+                "{}: {} pixels differ, see {:?}",
+                entry.name, differences, diff_path
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "Screenshots differ from their references:\n{}",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn screenshot_diff_marks_changed_pixels() {
+    let reference = Screenshot {
+        width: 2,
+        height: 1,
+        pixels: vec![0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF],
+    };
+    let mut actual = reference.clone();
+    actual.pixels[3..].copy_from_slice(&[0x00, 0x00, 0x00]);
+
+    let path = env::temp_dir().join("emyco-screenshot-diff.png");
+    reference.write(&path).unwrap();
+    assert_eq!(Screenshot::read(&path).unwrap(), reference);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(reference.diff(&reference).0, 0);
+    let (differences, diff) = actual.diff(&reference);
+    assert_eq!(differences, 1);
+    assert_eq!(diff.pixels, vec![0xC0, 0xC0, 0xC0, 0xFF, 0x00, 0x00]);
+}
+
+#[test]
+fn dmg_shades_are_captured_in_reference_grayscale() {
+    let frame = Rc::new(RefCell::new(None));
+    let mut display = CapturingDisplay::new(true, frame.clone());
+    display.resize(5, 1);
+    for (x, color) in DMG_COLORS.iter().chain([&0x7FFF]).enumerate() {
+        display.push_pixel(x as u8, 0, *color);
+    }
+    display.present();
+
+    let screenshot = frame.borrow_mut().take().unwrap();
+    assert_eq!(
+        screenshot.pixels,
+        [[0xFF; 3], [0xAA; 3], [0x55; 3], [0x00; 3], [0xFF; 3]].concat()
+    );
+
+    // Color models capture the colors as they are
+    let mut display = CapturingDisplay::new(false, frame.clone());
+    display.resize(1, 1);
+    display.push_pixel(0, 0, DMG_COLORS[0]);
+    display.present();
+    assert_ne!(frame.borrow_mut().take().unwrap().pixels, vec![0xFF; 3]);
+}
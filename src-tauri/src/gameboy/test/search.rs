@@ -0,0 +1,213 @@
+use super::{banked_gameboy, run_frames};
+use crate::gameboy::{
+    memory::MemoryController, CheatCode, DebugCommand, DebugEvent, Gameboy, MemoryRegion,
+    SearchComparison, SearchResult, SearchResults, SearchWidth,
+};
+
+fn search(gameboy: &mut Gameboy, command: DebugCommand) -> SearchResults {
+    gameboy.debug(command);
+    match gameboy.debug_reply(command, None) {
+        DebugEvent::Search(results) => results,
+        event => panic!("Unexpected reply {:?}", event),
+    }
+}
+
+fn write_byte(gameboy: &Gameboy, address: u16, value: u8) {
+    gameboy.memory.write().unwrap().write_byte(address, value);
+}
+
+#[test]
+fn passes_narrow_down_values_across_frames() {
+    let mut gameboy = banked_gameboy(2);
+    write_byte(&gameboy, 0xC010, 10);
+
+    let results = search(
+        &mut gameboy,
+        DebugCommand::StartSearch {
+            width: SearchWidth::Byte,
+            signed: false,
+        },
+    );
+    // Two banks of WRAM, HRAM and four banks of cartridge RAM
+    assert_eq!(results.count, 2 * 0x1000 + 0x7F + 4 * 0x2000);
+    assert_eq!(results.results.len(), 256);
+
+    write_byte(&gameboy, 0xC010, 11);
+    let results = search(
+        &mut gameboy,
+        DebugCommand::NarrowSearch(SearchComparison::Increased),
+    );
+    assert_eq!(results.count, 1);
+
+    run_frames(&mut gameboy, 1);
+    let results = search(
+        &mut gameboy,
+        DebugCommand::NarrowSearch(SearchComparison::Equal),
+    );
+    assert_eq!(
+        results.results,
+        [SearchResult {
+            region: MemoryRegion::Wram(0),
+            offset: 0x10,
+            value: 11,
+        }]
+    );
+
+    // Results are offsets the memory viewer can read
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Wram(0), 0x10, 1).unwrap(),
+        vec![11]
+    );
+
+    let results = search(
+        &mut gameboy,
+        DebugCommand::NarrowSearch(SearchComparison::Value(12)),
+    );
+    assert_eq!(results.count, 0);
+}
+
+#[test]
+fn words_are_compared_with_their_signedness() {
+    let mut gameboy = banked_gameboy(2);
+    gameboy
+        .write_memory(MemoryRegion::CartridgeRam(1), 0x05, &[0xFF, 0xFF])
+        .unwrap();
+
+    let start = |signed| DebugCommand::StartSearch {
+        width: SearchWidth::Word,
+        signed,
+    };
+    search(&mut gameboy, start(true));
+    let results = search(
+        &mut gameboy,
+        DebugCommand::NarrowSearch(SearchComparison::Value(-1)),
+    );
+    assert_eq!(results.count, 1);
+
+    // 0xFFFE is -2 as a signed word
+    gameboy
+        .write_memory(MemoryRegion::CartridgeRam(1), 0x05, &[0xFE])
+        .unwrap();
+    let results = search(
+        &mut gameboy,
+        DebugCommand::NarrowSearch(SearchComparison::Decreased),
+    );
+    assert_eq!(
+        results.results,
+        [SearchResult {
+            region: MemoryRegion::CartridgeRam(1),
+            offset: 0x05,
+            value: -2,
+        }]
+    );
+
+    search(&mut gameboy, start(false));
+    let results = search(
+        &mut gameboy,
+        DebugCommand::NarrowSearch(SearchComparison::Value(0xFFFE)),
+    );
+    assert_eq!(results.count, 1);
+    gameboy
+        .write_memory(MemoryRegion::CartridgeRam(1), 0x05, &[0x00, 0x00])
+        .unwrap();
+    let results = search(
+        &mut gameboy,
+        DebugCommand::NarrowSearch(SearchComparison::Increased),
+    );
+    assert_eq!(results.count, 0);
+}
+
+#[test]
+fn results_become_game_shark_cheats() {
+    let mut gameboy = banked_gameboy(2);
+    search(
+        &mut gameboy,
+        DebugCommand::StartSearch {
+            width: SearchWidth::Word,
+            signed: false,
+        },
+    );
+
+    let command = DebugCommand::AddSearchCheat {
+        region: MemoryRegion::Wram(1),
+        offset: 0x20,
+        value: 0x1234,
+    };
+    gameboy.debug(command);
+    let DebugEvent::Cheats(cheats) = gameboy.debug_reply(command, None) else {
+        panic!("Expected the cheats in reply");
+    };
+    let codes: Vec<_> = cheats.iter().map(|cheat| cheat.code).collect();
+    assert_eq!(
+        codes,
+        [
+            CheatCode::GameShark {
+                bank: 0x81,
+                value: 0x34,
+                address: 0xD020,
+            },
+            CheatCode::GameShark {
+                bank: 0x81,
+                value: 0x12,
+                address: 0xD021,
+            },
+        ]
+    );
+
+    gameboy.run_frame();
+    assert_eq!(
+        gameboy.read_memory(MemoryRegion::Wram(1), 0x20, 2).unwrap(),
+        vec![0x34, 0x12]
+    );
+
+    // Only searched memory can be held by a cheat, and only where the whole value fits
+    for (region, offset) in [(MemoryRegion::Vram(0), 0), (MemoryRegion::Hram, 0x7E)] {
+        gameboy.debug(DebugCommand::AddSearchCheat {
+            region,
+            offset,
+            value: 0,
+        });
+    }
+    assert_eq!(gameboy.cheats().len(), 2);
+}
+
+#[test]
+fn cartridge_ram_cheats_write_the_bank_they_were_found_in() {
+    let mut gameboy = banked_gameboy(2);
+    search(
+        &mut gameboy,
+        DebugCommand::StartSearch {
+            width: SearchWidth::Byte,
+            signed: false,
+        },
+    );
+
+    gameboy.debug(DebugCommand::AddSearchCheat {
+        region: MemoryRegion::CartridgeRam(2),
+        offset: 0x10,
+        value: 0x56,
+    });
+    assert_eq!(
+        gameboy.cheats()[0].code,
+        CheatCode::GameShark {
+            bank: 0x02,
+            value: 0x56,
+            address: 0xA010,
+        }
+    );
+
+    // Bank 0 is mapped, and is left alone
+    gameboy.run_frame();
+    assert_eq!(
+        gameboy
+            .read_memory(MemoryRegion::CartridgeRam(2), 0x10, 1)
+            .unwrap(),
+        vec![0x56]
+    );
+    assert_eq!(
+        gameboy
+            .read_memory(MemoryRegion::CartridgeRam(0), 0x10, 1)
+            .unwrap(),
+        vec![0x00]
+    );
+}
@@ -0,0 +1,112 @@
+use std::{cell::RefCell, env, fs, io::Cursor, rc::Rc};
+
+use super::{read_byte, run_frames, test_rom};
+use crate::{
+    emulator::EmulatorCommand,
+    gameboy::{
+        compare_traces, Boot, FileTraceSink, Gameboy, GameboyConfig, Model, NullDisplay, Registers,
+        TraceLine, TraceSink,
+    },
+};
+
+#[derive(Debug, Default)]
+struct RecordingTrace(Rc<RefCell<Vec<TraceLine>>>);
+
+impl TraceSink for RecordingTrace {
+    fn trace(&mut self, line: &TraceLine) {
+        self.0.borrow_mut().push(*line);
+    }
+}
+
+fn traced_gameboy(trace: impl TraceSink + 'static) -> Gameboy {
+    let (_sender, receiver) = crossbeam::channel::unbounded::<EmulatorCommand>();
+    let config = GameboyConfig::builder()
+        .display(NullDisplay)
+        .input(receiver)
+        .boot(Boot::Skip)
+        .model(Model::DMG)
+        .trace(trace)
+        .stub_ly(true)
+        .build();
+
+    Gameboy::new(test_rom(), config).unwrap()
+}
+
+#[test]
+fn trace_lines_use_the_gameboy_doctor_format() {
+    let line = TraceLine {
+        registers: Registers {
+            a: 0x01,
+            f: 0xB0,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        },
+        pcmem: [0x00, 0xC3, 0x13, 0x02],
+    };
+
+    assert_eq!(
+        line.to_string(),
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02"
+    );
+}
+
+#[test]
+fn instructions_are_traced_before_they_run() {
+    let trace = RecordingTrace::default();
+    let lines = trace.0.clone();
+    let mut gameboy = traced_gameboy(trace);
+    run_frames(&mut gameboy, 1);
+
+    let lines: Vec<_> = lines
+        .borrow()
+        .iter()
+        .take(4)
+        .map(|line| line.to_string())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,CE",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:18,FE,00,00",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:18,FE,00,00",
+        ]
+    );
+
+    // gameboy-doctor expects LY to be stubbed
+    assert_eq!(read_byte(&gameboy, 0xFF44), 0x90);
+}
+
+#[test]
+fn file_traces_stop_after_the_count_and_compare_against_a_reference() {
+    let path = env::temp_dir().join(format!("emyco-trace-{}.log", std::process::id()));
+    let mut gameboy = traced_gameboy(FileTraceSink::create(&path, Some(3)).unwrap());
+    while gameboy.tracing() {
+        gameboy.tick();
+    }
+
+    let log = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(log.lines().count(), 3);
+
+    // A longer reference matches as far as the trace goes
+    let reference = format!("{}{}\n", log, log.lines().last().unwrap());
+    assert_eq!(
+        compare_traces(Cursor::new(&log), Cursor::new(&reference)).unwrap(),
+        None
+    );
+
+    let reference = log.replacen("PC:0150", "PC:0151", 1);
+    let divergence = compare_traces(Cursor::new(&log), Cursor::new(&reference))
+        .unwrap()
+        .unwrap();
+    assert_eq!(divergence.line, 3);
+    assert!(divergence.expected.contains("PC:0151"));
+    assert!(divergence.actual.contains("PC:0150"));
+}
@@ -0,0 +1,168 @@
+use std::{cell::Cell, rc::Rc};
+
+use super::{cgb_gameboy, headless_gameboy, test_rom};
+use crate::gameboy::{
+    memory::MemoryController, DebugCommand, DebugEvent, Gameboy, ViewerImage, ViewerPalette,
+};
+
+const VIEWPORT_COLOR: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+
+fn dmg_gameboy() -> Gameboy {
+    headless_gameboy(test_rom(), Rc::new(Cell::new(0)))
+}
+
+fn write_bytes(gameboy: &Gameboy, address: u16, bytes: &[u8]) {
+    let mut memory = gameboy.memory.write().unwrap();
+    for (offset, byte) in bytes.iter().enumerate() {
+        memory.write_byte(address + offset as u16, *byte);
+    }
+}
+
+fn view_tiles(gameboy: &Gameboy, bank: u8, palette: ViewerPalette) -> ViewerImage {
+    gameboy
+        .memory
+        .read()
+        .unwrap()
+        .ppu()
+        .render_tiles(bank, palette)
+}
+
+#[test]
+fn tiles_are_rendered_with_the_chosen_palette() {
+    let gameboy = dmg_gameboy();
+    // Tile 1 has a row of color 1 and a row of color 2, and tile 383 a row of color 3
+    write_bytes(&gameboy, 0x8010, &[0xFF, 0x00, 0x00, 0xFF]);
+    write_bytes(&gameboy, 0x97F0, &[0xFF, 0xFF]);
+    write_bytes(&gameboy, 0xFF47, &[0x1B]);
+
+    let grayscale = view_tiles(&gameboy, 0, ViewerPalette::Grayscale);
+    assert_eq!((grayscale.width, grayscale.height), (128, 192));
+    assert_eq!(grayscale.pixels.len(), 128 * 192 * 4);
+    let shades: Vec<_> = [(0, 0), (8, 0), (8, 1), (120, 184)]
+        .iter()
+        .map(|&(x, y)| grayscale.pixel(x, y))
+        .collect();
+    assert!((1..4).all(|shade| shades[shade] != shades[shade - 1]));
+
+    // BGP reverses the shades
+    let bgp = view_tiles(&gameboy, 0, ViewerPalette::Bgp);
+    assert_eq!(bgp.pixel(0, 0), shades[3]);
+    assert_eq!(bgp.pixel(8, 0), shades[2]);
+    assert_eq!(bgp.pixel(8, 1), shades[1]);
+    assert_eq!(bgp.pixel(120, 184), shades[0]);
+
+    // Outside CGB mode there is only one bank
+    assert_eq!(view_tiles(&gameboy, 1, ViewerPalette::Grayscale), grayscale);
+}
+
+#[test]
+fn tilemaps_follow_the_addressing_mode_and_outline_the_viewport() {
+    let gameboy = dmg_gameboy();
+    // Tile 1 at the top left of the first tile map, whose data differs between addressing modes
+    write_bytes(&gameboy, 0x9800, &[0x01]);
+    write_bytes(&gameboy, 0x8010, &[0xFF, 0xFF]);
+    write_bytes(&gameboy, 0x9010, &[0x00, 0x00]);
+    write_bytes(&gameboy, 0xFF47, &[0xE4]);
+    // LCD on with 0x8000 addressing and the background on the first tile map
+    write_bytes(&gameboy, 0xFF40, &[0x91]);
+    write_bytes(&gameboy, 0xFF42, &[200, 100]);
+
+    let tilemap = gameboy.memory.read().unwrap().ppu().render_tilemap(0);
+    assert_eq!((tilemap.image.width, tilemap.image.height), (256, 256));
+    assert_eq!(tilemap.address, 0x9800);
+    assert!(tilemap.background);
+    assert!(tilemap.window);
+    assert_eq!((tilemap.scx, tilemap.scy), (100, 200));
+    let color_3 = tilemap.image.pixel(1, 0);
+
+    // The viewport wraps around from (100, 200) to (259, 343)
+    for (x, y) in [
+        (100, 200),
+        (255, 200),
+        (3, 200),
+        (100, 255),
+        (100, 87),
+        (3, 87),
+    ] {
+        assert_eq!(tilemap.image.pixel(x, y), VIEWPORT_COLOR, "({}, {})", x, y);
+    }
+    assert_ne!(tilemap.image.pixel(101, 201), VIEWPORT_COLOR);
+    assert_ne!(tilemap.image.pixel(4, 200), VIEWPORT_COLOR);
+
+    // With 0x8800 addressing, tile 1 is read from 0x9010
+    write_bytes(&gameboy, 0xFF40, &[0x81]);
+    let tilemap = gameboy.memory.read().unwrap().ppu().render_tilemap(0);
+    assert_ne!(tilemap.image.pixel(1, 0), color_3);
+
+    // The second tile map isn't in use, so has no viewport
+    let tilemap = gameboy.memory.read().unwrap().ppu().render_tilemap(1);
+    assert_eq!(tilemap.address, 0x9C00);
+    assert!(!tilemap.background);
+    assert!(!tilemap.window);
+    assert!(tilemap
+        .image
+        .pixels
+        .chunks(4)
+        .all(|pixel| pixel != VIEWPORT_COLOR));
+}
+
+#[test]
+fn oam_entries_are_decoded_and_rendered() {
+    let gameboy = dmg_gameboy();
+    // Tile 3 has color 1 on the left of its top row
+    write_bytes(&gameboy, 0x8030, &[0x80, 0x00]);
+    // Object 1 uses tile 3, flipped horizontally, with OBP1
+    write_bytes(&gameboy, 0xFE04, &[0x10, 0x08, 0x03, 0x30]);
+    write_bytes(&gameboy, 0xFF40, &[0x91]);
+
+    let DebugEvent::Oam(entries) = gameboy.debug_reply(DebugCommand::ViewOam, None) else {
+        panic!("Expected OAM entries");
+    };
+    assert_eq!(entries.len(), 40);
+    let entry = &entries[1];
+    assert_eq!(
+        (entry.index, entry.y, entry.x, entry.tile_index),
+        (1, 0x10, 0x08, 3)
+    );
+    assert!(entry.x_flip);
+    assert!(!entry.y_flip);
+    assert!(!entry.priority);
+    assert_eq!(entry.dmg_palette, 1);
+    assert_eq!((entry.image.width, entry.image.height), (8, 8));
+    assert_eq!(entry.image.pixel(7, 0)[3], 0xFF);
+    assert_eq!(entry.image.pixel(0, 0), [0, 0, 0, 0]);
+
+    // Tall objects span tiles 2 and 3
+    write_bytes(&gameboy, 0xFF40, &[0x95]);
+    let entries = gameboy.memory.read().unwrap().ppu().oam_entries();
+    assert_eq!(entries[1].image.height, 16);
+    assert_eq!(entries[1].image.pixel(7, 8)[3], 0xFF);
+    assert_eq!(entries[1].image.pixel(7, 0)[3], 0x00);
+}
+
+#[test]
+fn cgb_tilemaps_use_tile_attributes() {
+    let gameboy = cgb_gameboy();
+    {
+        let mut memory = gameboy.memory.write().unwrap();
+        // The top left tile is tile 0 from bank 1, flipped vertically, with palette 2
+        memory.write_byte(0xFF4F, 0x01);
+        memory.write_byte(0x9800, 0x4A);
+        memory.write_byte(0x8000, 0xFF);
+        memory.write_byte(0xFF4F, 0x00);
+        memory.write_byte(0x9800, 0x00);
+        // Color 1 of background palette 2 is pure blue
+        memory.write_byte(0xFF68, 0x80 | 0x12);
+        memory.write_byte(0xFF69, 0x00);
+        memory.write_byte(0xFF69, 0x7C);
+    }
+    write_bytes(&gameboy, 0xFF40, &[0x91]);
+
+    let tilemap = gameboy.memory.read().unwrap().ppu().render_tilemap(0);
+    // Column 0 is covered by the viewport outline
+    assert_eq!(tilemap.image.pixel(1, 7), [0x00, 0x00, 0xFF, 0xFF]);
+    assert_ne!(tilemap.image.pixel(1, 0), [0x00, 0x00, 0xFF, 0xFF]);
+
+    let tiles = view_tiles(&gameboy, 1, ViewerPalette::Background(2));
+    assert_eq!(tiles.pixel(0, 0), [0x00, 0x00, 0xFF, 0xFF]);
+}
@@ -1,12 +1,17 @@
 use log::{error, trace};
+use serde::{Deserialize, Serialize};
 
-use super::memory::{Interrupt, Register};
+use super::{
+    memory::{Interrupt, Register},
+    state::Snapshot,
+};
 
 const TAC_REGISTER_ADDRESS: u16 = 0xFF07;
 const TMA_REGISTER_ADDRESS: u16 = 0xFF06;
 const TIMA_REGISTER_ADDRESS: u16 = 0xFF05;
 const DIV_REGISTER_ADDRESS: u16 = 0xFF04;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Timer {
     enabled: bool,
     clock: u16,
@@ -18,7 +23,7 @@ pub struct Timer {
     pending_interrupts: Option<Interrupt>,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum TimaReload {
     Idle,
     Reloading,
@@ -39,6 +44,11 @@ impl Timer {
         }
     }
 
+    /// Sets the internal counter, whose upper byte is DIV.
+    pub fn set_divider(&mut self, clock: u16) {
+        self.clock = clock;
+    }
+
     fn edge_detect(&self) -> bool {
         let freq_bit = match self.frequency {
             Frequency::Increment256 => 1 << 9,
@@ -61,6 +71,18 @@ impl Timer {
     }
 }
 
+impl Snapshot for Timer {
+    type State = Timer;
+
+    fn save_state(&self) -> Timer {
+        self.clone()
+    }
+
+    fn load_state(&mut self, state: Timer) {
+        *self = state;
+    }
+}
+
 impl Default for Timer {
     fn default() -> Self {
         Self::new()
@@ -178,6 +200,7 @@ impl Register for Timer {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum Frequency {
     Increment256,
     Increment4,
@@ -0,0 +1,157 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{BufRead, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use log::error;
+
+use super::Registers;
+
+/// A TraceSink receives the CPU state as each instruction starts, so execution can be compared
+/// line by line against other emulators.
+pub trait TraceSink: fmt::Debug {
+    fn trace(&mut self, line: &TraceLine);
+
+    /// Whether the sink wants no more lines, after which the CPU stops tracing.
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+/// The CPU state as an instruction starts, which is displayed in the gameboy-doctor log format:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TraceLine {
+    pub registers: Registers,
+    /// The four bytes from PC onwards.
+    pub pcmem: [u8; 4],
+}
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.registers;
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} \
+             PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            r.a,
+            r.f,
+            r.b,
+            r.c,
+            r.d,
+            r.e,
+            r.h,
+            r.l,
+            r.sp,
+            r.pc,
+            self.pcmem[0],
+            self.pcmem[1],
+            self.pcmem[2],
+            self.pcmem[3]
+        )
+    }
+}
+
+/// A TraceSink that writes a line per instruction to a file, stopping after a number of
+/// instructions if given a limit.
+#[derive(Debug)]
+pub struct FileTraceSink {
+    writer: Option<BufWriter<File>>,
+    remaining: Option<u64>,
+}
+
+impl FileTraceSink {
+    pub fn create(path: &Path, limit: Option<u64>) -> Result<Self, anyhow::Error> {
+        let file =
+            File::create(path).with_context(|| format!("Unable to create trace {:?}.", path))?;
+
+        Ok(FileTraceSink {
+            writer: (limit != Some(0)).then(|| BufWriter::new(file)),
+            remaining: limit,
+        })
+    }
+
+    /// Flushes and closes the file once no more lines will be written.
+    fn close(&mut self) {
+        if let Some(mut writer) = self.writer.take() {
+            if let Err(e) = writer.flush() {
+                error!("Unable to write trace. {:?}", e);
+            }
+        }
+    }
+}
+
+impl TraceSink for FileTraceSink {
+    fn trace(&mut self, line: &TraceLine) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        if let Err(e) = writeln!(writer, "{}", line) {
+            error!("Unable to write trace. {:?}", e);
+            self.writer = None;
+            return;
+        }
+
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+        if self.remaining == Some(0) {
+            self.close();
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.writer.is_none()
+    }
+}
+
+impl Drop for FileTraceSink {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// The first line at which a trace differs from a reference log.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Divergence {
+    /// Counted from 1.
+    pub line: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Line {} differs from the reference.\nExpected: {}\nActual:   {}",
+            self.line, self.expected, self.actual
+        )
+    }
+}
+
+/// Compares a trace against a reference log, such as one from gameboy-doctor, returning the first
+/// line that differs. Only lines present in both are compared, so a trace stopped early matches a
+/// longer reference.
+pub fn compare_traces(
+    trace: impl BufRead,
+    reference: impl BufRead,
+) -> Result<Option<Divergence>, anyhow::Error> {
+    for (index, (actual, expected)) in trace.lines().zip(reference.lines()).enumerate() {
+        let (actual, expected) = (actual?, expected?);
+        if actual.trim() != expected.trim() {
+            return Ok(Some(Divergence {
+                line: index + 1,
+                expected: expected.trim().to_string(),
+                actual: actual.trim().to_string(),
+            }));
+        }
+    }
+
+    Ok(None)
+}
@@ -1,10 +1,17 @@
-mod emulator;
-mod gameboy;
+mod app;
+pub mod cli;
+pub mod emulator;
+pub mod gameboy;
 
 use std::sync::Mutex;
 
-use emulator::{
-    pause_emulator, register_input, unload_emulator, setup_gameboy, start_emulator, stop_emulator, AppState,
+use app::{
+    add_breakpoint, add_cheat, add_search_cheat, add_watchpoint, debugger_state, frame_advance,
+    list_cheats, load_state, narrow_search, pause_emulator, play_movie, read_memory, record_movie,
+    register_input, remove_breakpoint, remove_cheat, remove_watchpoint, rewind, run_to_frame,
+    save_state, search_results, set_cheat_enabled, set_speed, setup_gameboy, start_emulator,
+    start_search, step_instruction, step_out, step_over, stop_emulator, stop_movie, take_frame,
+    unload_emulator, view_oam, view_tilemap, view_tiles, write_memory, AppState, PresentedFrame,
 };
 use tauri::Manager;
 
@@ -19,12 +26,44 @@ pub fn run() {
             unload_emulator,
             start_emulator,
             pause_emulator,
+            frame_advance,
             stop_emulator,
             register_input,
+            save_state,
+            load_state,
+            rewind,
+            set_speed,
+            record_movie,
+            play_movie,
+            stop_movie,
+            debugger_state,
+            add_breakpoint,
+            remove_breakpoint,
+            add_watchpoint,
+            remove_watchpoint,
+            step_instruction,
+            step_over,
+            step_out,
+            run_to_frame,
+            view_tiles,
+            view_tilemap,
+            view_oam,
+            read_memory,
+            write_memory,
+            take_frame,
+            list_cheats,
+            add_cheat,
+            remove_cheat,
+            set_cheat_enabled,
+            start_search,
+            narrow_search,
+            search_results,
+            add_search_cheat,
         ])
         .setup(|app| {
             let app_state = Mutex::new(AppState::new());
             app.manage(app_state);
+            app.manage(PresentedFrame::default());
 
             Ok(())
         })
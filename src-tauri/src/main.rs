@@ -1,7 +1,7 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::env;
+use std::{env, process};
 
 fn main() {
     if env::var("RUST_LOG").is_err() {
@@ -10,5 +10,14 @@ fn main() {
 
     env_logger::init();
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(result) = emyco_lib::cli::run(&args) {
+        if let Err(e) = result {
+            eprintln!("{:#}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
     emyco_lib::run()
 }
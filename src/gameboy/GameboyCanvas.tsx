@@ -1,16 +1,10 @@
 import { Accessor, onCleanup, onMount } from 'solid-js';
+import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 
 const WIDTH = 160;
 const HEIGHT = 144;
 
-const PALETTE = [
-  [155, 188, 15, 255],
-  [139, 172, 15, 255],
-  [48, 98, 48, 255],
-  [15, 56, 15, 255],
-];
-
 interface GameboyCanvasProps {
   enabled: Accessor<boolean>;
 }
@@ -27,16 +21,27 @@ const GameboyCanvas = (props: GameboyCanvasProps) => {
 
     imageData = ctx.createImageData(WIDTH, HEIGHT);
 
-    const unlisten = listen<Uint8Array>('gb-present-frame', (event) => {
-      const frame = new Uint8Array(event.payload);
+    // Frames are announced with their size, then taken as raw RGBA bytes
+    const unlisten = listen<[number, number]>(
+      'gb-present-frame',
+      async (event) => {
+        const [width, height] = event.payload;
+        const frame = new Uint8ClampedArray(
+          await invoke<ArrayBuffer>('take_frame')
+        );
+        if (!canvas || !ctx || frame.length !== width * height * 4) return;
 
-      for (let i = 0; i < frame.length; i++) {
-        const [r, g, b, a] = PALETTE[frame[i]];
-        imageData.data.set([r, g, b, a], i * 4);
-      }
+        // SGB frames include a border around the screen
+        if (width !== imageData.width || height !== imageData.height) {
+          canvas.width = width;
+          canvas.height = height;
+          imageData = ctx.createImageData(width, height);
+        }
 
-      ctx?.putImageData(imageData, 0, 0);
-    });
+        imageData.data.set(frame);
+        ctx.putImageData(imageData, 0, 0);
+      }
+    );
 
     onCleanup(() => {
       unlisten.then((f) => f());
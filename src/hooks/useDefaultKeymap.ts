@@ -70,6 +70,22 @@ const useDefaultKeymap = () => {
         invoke('register_input', { key: 'right', down: false });
       },
     },
+    r: {
+      keydown: () => {
+        invoke('rewind', { active: true });
+      },
+      keyup: () => {
+        invoke('rewind', { active: false });
+      },
+    },
+    f: {
+      keydown: () => {
+        invoke('set_speed', { multiplier: 4 });
+      },
+      keyup: () => {
+        invoke('set_speed', { multiplier: 1 });
+      },
+    },
   });
 }
 